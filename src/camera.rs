@@ -35,9 +35,10 @@ impl Camera {
         
     }
 
-    pub fn update_from_player(&mut self, player: &Player) {
+    //Offset moves the eye without touching the player, used to smooth out prediction corrections
+    pub fn update_from_player(&mut self, player: &Player, eye_offset: &glam::f32::Vec3) {
 
-        self.eye = *player.get_position();
+        self.eye = player.get_eye_position() + *eye_offset;
 
         self.target = self.eye + *player.get_forward();
     }
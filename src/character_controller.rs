@@ -0,0 +1,491 @@
+use crate::collision;
+
+//Units per second squared
+pub const GRAVITY: f32 = 20.0;
+//Units per second, keeps someone falling out of the world from going fast enough to look like a cheat
+pub const MAX_FALL_SPEED: f32 = 50.0;
+//Anything steeper is a wall, it can't be stood on or walked up
+pub const MAX_SLOPE_ANGLE: f32 = 45.0 * std::f32::consts::PI / 180.0;
+//Ledges up to this high are walked onto without jumping, also how far the ground is followed down slopes and stairs
+pub const STEP_HEIGHT: f32 = 0.4;
+//Sweeps per move, each one can hit something and turn the rest of the move along it
+pub const MAX_SLIDE_ITERATIONS: usize = 4;
+//Gap kept between the capsule and the world, sweeps that start touching something go straight through it
+const SKIN_WIDTH: f32 = 0.01;
+
+//Player position is the eye, the capsule hangs below it
+pub const PLAYER_RADIUS: f32 = 0.4;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const EYE_HEIGHT: f32 = 1.5;
+//Crouching keeps the feet where they are and brings the top of the capsule down to this
+pub const CROUCH_HEIGHT: f32 = 1.0;
+
+//Standing capsule relative to the eye
+pub fn get_player_capsule() -> collision::Capsule {
+
+    collision::Capsule::new(glam::f32::Vec3::NEG_Y * EYE_HEIGHT, glam::f32::Vec3::Y * (PLAYER_HEIGHT - EYE_HEIGHT), PLAYER_RADIUS)
+}
+
+//Moves a capsule through the world, the caller decides the velocity and this decides where it actually ends up
+#[derive(Debug, Copy, Clone)]
+pub struct CharacterController {
+    //Relative to the position being moved, what's being swept right now
+    capsule: collision::Capsule,
+    standing_capsule: collision::Capsule,
+    crouched: bool,
+    grounded: bool,
+    ground_normal: glam::f32::Vec3,
+}
+
+impl Default for CharacterController {
+
+    fn default() -> Self {
+
+        Self::new(get_player_capsule())
+    }
+}
+
+impl CharacterController {
+
+    pub fn new(capsule: collision::Capsule) -> Self {
+
+        Self { capsule, standing_capsule: capsule, crouched: false, grounded: false, ground_normal: glam::f32::Vec3::Y }
+    }
+
+    //One tick of movement, velocity comes back with whatever the world took out of it
+    pub fn move_and_slide(&mut self, position: &mut glam::f32::Vec3, velocity: &mut glam::f32::Vec3, delta_time: f32, world: &[&collision::TriangleSoup]) {
+
+        let start = *position;
+        let (mut end, mut end_velocity) = self.slide(start, *velocity, delta_time, world);
+
+        //Something was in the way while walking, try again from higher up in case it was small enough to step onto
+        let horizontal_velocity = glam::f32::Vec3::new(velocity.x, 0.0, velocity.z);
+        let wanted = horizontal_velocity.length() * delta_time;
+        if self.grounded && velocity.y <= 0.0 && wanted > 0.0 && get_horizontal_distance(start, end) < wanted - SKIN_WIDTH {
+            let (raised, _) = self.sweep_to(start, glam::f32::Vec3::Y * STEP_HEIGHT, world);
+            let (stepped, stepped_velocity) = self.slide(raised, horizontal_velocity, delta_time, world);
+            let (landed, hit) = self.sweep_to(stepped, glam::f32::Vec3::NEG_Y * (raised.y - start.y + SKIN_WIDTH * 2.0), world);
+
+            //Whatever it landed on has to be low enough, the round bottom can reach a little higher than STEP_HEIGHT
+            let feet = start.y + self.capsule.get_base().y;
+            let step = hit.and_then(|normal| self.find_ground(landed, normal, world)).filter(|(_, height)| *height <= feet + STEP_HEIGHT);
+            if step.is_some() && get_horizontal_distance(start, landed) > get_horizontal_distance(start, end) + SKIN_WIDTH {
+                end = landed;
+                end_velocity = stepped_velocity;
+            }
+        }
+
+        *position = end;
+        *velocity = end_velocity;
+        self.update_ground(position, velocity, world);
+    }
+
+    //Call before moving, e.g. when jumping
+    pub fn leave_ground(&mut self) {
+
+        self.grounded = false;
+    }
+
+    pub fn is_grounded(&self) -> bool {
+
+        self.grounded
+    }
+
+    pub fn get_ground_normal(&self) -> &glam::f32::Vec3 {
+
+        &self.ground_normal
+    }
+
+    //Relative to the position
+    pub fn get_capsule(&self) -> &collision::Capsule {
+
+        &self.capsule
+    }
+
+    //The standing capsule, stays crouched if crouching
+    pub fn set_capsule(&mut self, capsule: collision::Capsule) {
+
+        self.standing_capsule = capsule;
+        self.capsule = capsule;
+        if self.crouched {
+            self.crouch();
+        }
+    }
+
+    pub fn crouch(&mut self) {
+
+        let base = self.standing_capsule.get_base();
+        let radius = self.standing_capsule.get_radius();
+        let up = (self.standing_capsule.get_tip() - base).normalize_or_zero();
+        self.capsule = collision::Capsule::new(base, base + up * CROUCH_HEIGHT.max(radius * 2.0), radius);
+        self.crouched = true;
+    }
+
+    //False if there's no room to stand up at position, the capsule stays crouched
+    pub fn stand_up(&mut self, position: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> bool {
+
+        if !self.crouched {
+            return true;
+        }
+
+        let standing = collision::Capsule::new(self.standing_capsule.get_base() + position, self.standing_capsule.get_tip() + position, self.standing_capsule.get_radius());
+        if world.iter().any(|triangle_soup| standing.vs_triangle_soup(triangle_soup).collided) {
+            return false;
+        }
+
+        self.capsule = self.standing_capsule;
+        self.crouched = false;
+
+        true
+    }
+
+    pub fn is_crouched(&self) -> bool {
+
+        self.crouched
+    }
+
+    pub fn get_world_capsule(&self, position: glam::f32::Vec3) -> collision::Capsule {
+
+        collision::Capsule::new(self.capsule.get_base() + position, self.capsule.get_tip() + position, self.capsule.get_radius())
+    }
+
+    //Collide and slide, each hit takes the part of the move going into the surface away and carries on with the rest
+    fn slide(&self, mut position: glam::f32::Vec3, mut velocity: glam::f32::Vec3, delta_time: f32, world: &[&collision::TriangleSoup]) -> (glam::f32::Vec3, glam::f32::Vec3) {
+
+        let mut motion = velocity * delta_time;
+        let mut planes: Vec<glam::f32::Vec3> = Vec::with_capacity(MAX_SLIDE_ITERATIONS);
+
+        for _ in 0..MAX_SLIDE_ITERATIONS {
+            if motion.length_squared() < f32::EPSILON * f32::EPSILON {
+                break;
+            }
+
+            let (moved_to, hit) = self.sweep_to(position, motion, world);
+            let travelled = (moved_to - position).dot(motion.normalize());
+            position = moved_to;
+            let Some(normal) = hit else {
+                break;
+            };
+
+            //Walking into something too steep to stand on shouldn't push us up it
+            let normal = match glam::f32::Vec3::new(normal.x, 0.0, normal.z).try_normalize() {
+                Some(wall_normal) if self.grounded && !is_walkable(normal) && normal.y > 0.0 => wall_normal,
+                _ => normal,
+            };
+
+            motion = clip_velocity(motion * (1.0 - travelled / motion.length()).max(0.0), normal);
+            velocity = clip_velocity(velocity, normal);
+
+            //Into a corner, the only way left is along the crease between the two surfaces
+            for previous in &planes {
+                if motion.dot(*previous) < 0.0 {
+                    let crease = previous.cross(normal).normalize_or_zero();
+                    motion = crease * crease.dot(motion);
+                    velocity = crease * crease.dot(velocity);
+                }
+            }
+            planes.push(normal);
+        }
+
+        (position, velocity)
+    }
+
+    //Earliest hit moving by motion, with how far along it was and the normal facing back along it
+    fn sweep(&self, position: glam::f32::Vec3, motion: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> Option<(f32, glam::f32::Vec3)> {
+
+        let capsule = self.get_world_capsule(position);
+
+        world.iter()
+            .map(|triangle_soup| capsule.vs_while_moving_triangle_soup(&motion, triangle_soup))
+            .filter(|t| t.collided)
+            .min_by(|a, b| a.penetration_or_time.total_cmp(&b.penetration_or_time))
+            .map(|t| {
+                let normal = t.normal.normalize_or_zero();
+                (t.penetration_or_time.max(0.0), if normal.dot(motion) > 0.0 { -normal } else { normal })
+            })
+    }
+
+    //Moves as far as it can along motion, stopping SKIN_WIDTH short of anything in the way
+    fn sweep_to(&self, position: glam::f32::Vec3, motion: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> (glam::f32::Vec3, Option<glam::f32::Vec3>) {
+
+        let length = motion.length();
+        if length < f32::EPSILON {
+            return (position, None);
+        }
+
+        match self.sweep(position, motion, world) {
+            Some((time, normal)) => (position + motion / length * (time * length - SKIN_WIDTH).max(0.0), Some(normal)),
+            None => (position + motion, None),
+        }
+    }
+
+    //Sticks to the ground while walking over bumps and down slopes, anything in the air only lands once it's touching
+    fn update_ground(&mut self, position: &mut glam::f32::Vec3, velocity: &mut glam::f32::Vec3, world: &[&collision::TriangleSoup]) {
+
+        if !self.grounded && velocity.y > 0.0 {
+            return;
+        }
+
+        let probe = if self.grounded { STEP_HEIGHT } else { SKIN_WIDTH * 2.0 };
+        let (grounded_position, hit) = self.sweep_to(*position, glam::f32::Vec3::NEG_Y * probe, world);
+
+        match hit.and_then(|normal| self.find_ground(grounded_position, normal, world)) {
+            Some((normal, _)) => {
+                *position = grounded_position;
+                *velocity = clip_velocity(*velocity, normal);
+                self.grounded = true;
+                self.ground_normal = normal;
+            }
+            None => {
+                self.grounded = false;
+                self.ground_normal = glam::f32::Vec3::Y;
+            }
+        }
+    }
+
+    //Normal and height of the walkable surface under something the capsule landed on
+    //Its round bottom resting on the edge of a step gets a normal too steep to stand on, so the surface just past the edge is checked instead
+    fn find_ground(&self, position: glam::f32::Vec3, normal: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> Option<(glam::f32::Vec3, f32)> {
+
+        let radius = self.capsule.get_radius();
+        let contact = position + self.capsule.get_base() + glam::f32::Vec3::Y * radius - normal * (radius + SKIN_WIDTH);
+        if is_walkable(normal) {
+            return Some((normal, contact.y));
+        }
+        if normal.y <= 0.0 {
+            return None;
+        }
+
+        let outward = glam::f32::Vec3::new(-normal.x, 0.0, -normal.z).normalize_or_zero();
+        let ray = collision::Ray::new(contact + outward * SKIN_WIDTH + glam::f32::Vec3::Y * SKIN_WIDTH * 5.0, glam::f32::Vec3::NEG_Y * SKIN_WIDTH * 10.0);
+
+        world.iter()
+            .map(|triangle_soup| triangle_soup.vs_ray(&ray))
+            .filter(|t| t.collided && t.penetration_or_time <= 1.0)
+            .min_by(|a, b| a.penetration_or_time.total_cmp(&b.penetration_or_time))
+            .map(|t| (t.normal.normalize_or_zero() * t.normal.y.signum(), t.position.y))
+            .filter(|(surface_normal, _)| is_walkable(*surface_normal))
+    }
+}
+
+pub fn is_walkable(normal: glam::f32::Vec3) -> bool {
+
+    normal.y >= MAX_SLOPE_ANGLE.cos()
+}
+
+//Takes out the part of velocity going into the surface, leaves anything moving away from it alone
+pub fn clip_velocity(velocity: glam::f32::Vec3, normal: glam::f32::Vec3) -> glam::f32::Vec3 {
+
+    velocity - normal * velocity.dot(normal).min(0.0)
+}
+
+fn get_horizontal_distance(a: glam::f32::Vec3, b: glam::f32::Vec3) -> f32 {
+
+    glam::f32::Vec2::new(a.x - b.x, a.z - b.z).length()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+
+    //Two triangles facing up, front face is counter clockwise looking down the normal
+    fn quad(a: glam::f32::Vec3, b: glam::f32::Vec3, c: glam::f32::Vec3, d: glam::f32::Vec3) -> [collision::Triangle; 2] {
+
+        [collision::Triangle::new(a, b, c), collision::Triangle::new(a, c, d)]
+    }
+
+    fn floor(height: f32) -> [collision::Triangle; 2] {
+
+        quad(glam::f32::Vec3::new(-20.0, height, -20.0), glam::f32::Vec3::new(-20.0, height, 20.0), glam::f32::Vec3::new(20.0, height, 20.0), glam::f32::Vec3::new(20.0, height, -20.0))
+    }
+
+    //Wall across the X axis at z facing -Z
+    fn wall_facing_back(z: f32) -> [collision::Triangle; 2] {
+
+        quad(glam::f32::Vec3::new(-20.0, -5.0, z), glam::f32::Vec3::new(-20.0, 5.0, z), glam::f32::Vec3::new(20.0, 5.0, z), glam::f32::Vec3::new(20.0, -5.0, z))
+    }
+
+    //Wall across the Z axis at x facing -X
+    fn wall_facing_left(x: f32) -> [collision::Triangle; 2] {
+
+        quad(glam::f32::Vec3::new(x, -5.0, -20.0), glam::f32::Vec3::new(x, -5.0, 20.0), glam::f32::Vec3::new(x, 5.0, 20.0), glam::f32::Vec3::new(x, 5.0, -20.0))
+    }
+
+    //Ramp going up towards +Z from z = 0 at the given angle, on a floor at 0
+    fn ramp(angle: f32) -> collision::TriangleSoup {
+
+        let length = 20.0;
+        let top = angle.tan() * length;
+        let mut triangles: Vec<collision::Triangle> = quad(glam::f32::Vec3::new(-20.0, 0.0, -20.0), glam::f32::Vec3::new(-20.0, 0.0, 0.0), glam::f32::Vec3::new(20.0, 0.0, 0.0), glam::f32::Vec3::new(20.0, 0.0, -20.0)).to_vec();
+        triangles.extend(quad(glam::f32::Vec3::new(-20.0, 0.0, 0.0), glam::f32::Vec3::new(-20.0, top, length), glam::f32::Vec3::new(20.0, top, length), glam::f32::Vec3::new(20.0, 0.0, 0.0)));
+
+        collision::TriangleSoup::new(triangles)
+    }
+
+    //Steps going up towards +Z, each one depth deep starting at z = 0
+    fn stairs(rise: f32, depth: f32, count: usize) -> collision::TriangleSoup {
+
+        let mut triangles: Vec<collision::Triangle> = quad(glam::f32::Vec3::new(-20.0, 0.0, -20.0), glam::f32::Vec3::new(-20.0, 0.0, 0.0), glam::f32::Vec3::new(20.0, 0.0, 0.0), glam::f32::Vec3::new(20.0, 0.0, -20.0)).to_vec();
+        for step in 0..count {
+            let z = step as f32 * depth;
+            let y = (step + 1) as f32 * rise;
+            let back = if step + 1 == count { 20.0 } else { z + depth };
+            //Riser facing -Z then the tread facing up
+            triangles.extend(quad(glam::f32::Vec3::new(-20.0, y - rise, z), glam::f32::Vec3::new(-20.0, y, z), glam::f32::Vec3::new(20.0, y, z), glam::f32::Vec3::new(20.0, y - rise, z)));
+            triangles.extend(quad(glam::f32::Vec3::new(-20.0, y, z), glam::f32::Vec3::new(-20.0, y, back), glam::f32::Vec3::new(20.0, y, back), glam::f32::Vec3::new(20.0, y, z)));
+        }
+
+        collision::TriangleSoup::new(triangles)
+    }
+
+    //Standing on the floor at 0, eye at EYE_HEIGHT
+    fn standing_at(x: f32, z: f32) -> glam::f32::Vec3 {
+
+        glam::f32::Vec3::new(x, EYE_HEIGHT + SKIN_WIDTH, z)
+    }
+
+    //Walks with gravity for a number of ticks, jumping resets nothing so velocity is set fresh each tick
+    fn walk(controller: &mut CharacterController, position: &mut glam::f32::Vec3, direction: glam::f32::Vec3, speed: f32, ticks: usize, world: &[&collision::TriangleSoup]) -> glam::f32::Vec3 {
+
+        let mut velocity = glam::f32::Vec3::ZERO;
+        for _ in 0..ticks {
+            let fall = if controller.is_grounded() { 0.0 } else { velocity.y - GRAVITY * DELTA_TIME };
+            velocity = direction * speed + glam::f32::Vec3::Y * fall;
+            controller.move_and_slide(position, &mut velocity, DELTA_TIME, world);
+        }
+
+        velocity
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_floor() {
+
+        let floor = collision::TriangleSoup::new(floor(0.0).to_vec());
+        let mut controller = CharacterController::default();
+        let mut position = glam::f32::Vec3::new(0.0, 5.0, 0.0);
+
+        walk(&mut controller, &mut position, glam::f32::Vec3::ZERO, 0.0, 120, &[&floor]);
+
+        assert!(controller.is_grounded());
+        assert!((position.y - EYE_HEIGHT).abs() < SKIN_WIDTH * 3.0, "{:?}", position);
+        assert_eq!(*controller.get_ground_normal(), glam::f32::Vec3::Y);
+
+        //Nothing underneath
+        let mut position = glam::f32::Vec3::new(0.0, 5.0, 0.0);
+        let mut controller = CharacterController::default();
+        walk(&mut controller, &mut position, glam::f32::Vec3::ZERO, 0.0, 60, &[]);
+        assert!(!controller.is_grounded());
+        assert!(position.y < 0.0);
+    }
+
+    #[test]
+    fn walks_up_gentle_slopes_but_not_steep_ones() {
+
+        let gentle = ramp(30.0_f32.to_radians());
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(0.0, -2.0);
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 4.0, 5, &[&gentle]);
+        assert!(controller.is_grounded());
+
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 4.0, 120, &[&gentle]);
+        assert!(controller.is_grounded());
+        assert!(position.z > 3.0, "{:?}", position);
+        //Up the ramp by about as far along it as we went
+        let expected = EYE_HEIGHT + position.z * 30.0_f32.to_radians().tan();
+        assert!((position.y - expected).abs() < 0.3, "{:?} expected height {}", position, expected);
+
+        let steep = ramp(60.0_f32.to_radians());
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(0.0, -2.0);
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 4.0, 120, &[&steep]);
+        assert!(position.z < 0.0, "{:?}", position);
+        assert!(position.y < EYE_HEIGHT + 0.5, "{:?}", position);
+    }
+
+    #[test]
+    fn steps_up_stairs_but_not_walls() {
+
+        let low = stairs(0.25, 0.5, 4);
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(0.0, -1.0);
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 4.0, 120, &[&low]);
+
+        assert!(controller.is_grounded());
+        assert!(position.z > 2.0, "{:?}", position);
+        assert!((position.y - (EYE_HEIGHT + 1.0)).abs() < 0.05, "{:?}", position);
+
+        //Back down again without leaving the ground
+        let mut airborne = 0;
+        for _ in 0..120 {
+            walk(&mut controller, &mut position, glam::f32::Vec3::NEG_Z, 4.0, 1, &[&low]);
+            airborne += !controller.is_grounded() as usize;
+        }
+        assert_eq!(airborne, 0);
+        assert!((position.y - EYE_HEIGHT).abs() < 0.05, "{:?}", position);
+
+        //Too high to step, needs a jump
+        let high = stairs(STEP_HEIGHT * 1.5, 1.0, 1);
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(0.0, -1.0);
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 4.0, 120, &[&high]);
+
+        assert!(position.z < -PLAYER_RADIUS + SKIN_WIDTH * 3.0, "{:?}", position);
+        assert!(position.z > -PLAYER_RADIUS - 0.05, "{:?}", position);
+        assert!((position.y - EYE_HEIGHT).abs() < 0.05, "{:?}", position);
+    }
+
+    #[test]
+    fn slides_along_walls_and_stops_in_corners() {
+
+        let mut triangles = floor(0.0).to_vec();
+        triangles.extend(wall_facing_back(2.0));
+        triangles.extend(wall_facing_left(2.0));
+        let room = collision::TriangleSoup::new(triangles);
+
+        //Diagonally into the wall facing us, the sideways part carries on
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(-5.0, 0.0);
+        let velocity = walk(&mut controller, &mut position, glam::f32::Vec3::new(1.0, 0.0, 1.0).normalize(), 4.0, 40, &[&room]);
+        assert!(position.z < 2.0 - PLAYER_RADIUS, "{:?}", position);
+        assert!(position.z > 2.0 - PLAYER_RADIUS - 0.05, "{:?}", position);
+        assert!(velocity.z.abs() < 0.001 && velocity.x > 2.0, "{:?}", velocity);
+
+        //Then into the corner where it stops
+        walk(&mut controller, &mut position, glam::f32::Vec3::new(1.0, 0.0, 1.0).normalize(), 4.0, 120, &[&room]);
+        assert!(position.x < 2.0 - PLAYER_RADIUS && position.x > 2.0 - PLAYER_RADIUS - 0.05, "{:?}", position);
+        assert!(position.z < 2.0 - PLAYER_RADIUS && position.z > 2.0 - PLAYER_RADIUS - 0.05, "{:?}", position);
+        assert!((position.y - EYE_HEIGHT).abs() < 0.05, "{:?}", position);
+    }
+    #[test]
+    fn crouches_under_ceilings_and_only_stands_up_with_room() {
+
+        //Ceiling facing down over z > 0, lower than standing height but higher than crouched
+        let ceiling = 1.4;
+        let mut triangles = floor(0.0).to_vec();
+        triangles.extend(quad(glam::f32::Vec3::new(-20.0, ceiling, 0.0), glam::f32::Vec3::new(20.0, ceiling, 0.0), glam::f32::Vec3::new(20.0, ceiling, 20.0), glam::f32::Vec3::new(-20.0, ceiling, 20.0)));
+        let tunnel = collision::TriangleSoup::new(triangles);
+
+        let mut controller = CharacterController::default();
+        let mut position = standing_at(0.0, -2.0);
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 2.0, 90, &[&tunnel]);
+        assert!(position.z < -PLAYER_RADIUS + SKIN_WIDTH * 3.0, "{:?}", position);
+
+        controller.crouch();
+        assert!(controller.is_crouched());
+        assert_eq!(controller.get_capsule().get_base(), get_player_capsule().get_base());
+        assert!((controller.get_capsule().get_tip().y - (get_player_capsule().get_base().y + CROUCH_HEIGHT)).abs() < 0.0001);
+
+        walk(&mut controller, &mut position, glam::f32::Vec3::Z, 2.0, 90, &[&tunnel]);
+        assert!(position.z > 2.0, "{:?}", position);
+        assert!((position.y - EYE_HEIGHT).abs() < 0.05, "{:?}", position);
+        assert!(!controller.stand_up(position, &[&tunnel]));
+        assert!(controller.is_crouched());
+
+        walk(&mut controller, &mut position, glam::f32::Vec3::NEG_Z, 2.0, 120, &[&tunnel]);
+        assert!(controller.stand_up(position, &[&tunnel]));
+        assert!(!controller.is_crouched());
+        assert_eq!(controller.get_capsule().get_tip(), get_player_capsule().get_tip());
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+//In characters, a message at the limit still fits in a reliable message
+pub const MAX_CHAT_LENGTH: usize = 100;
+//Each player can send a few messages at once then one every CHAT_MESSAGE_INTERVAL
+pub const CHAT_BURST: f32 = 4.0;
+pub const CHAT_MESSAGE_INTERVAL: Duration = Duration::from_secs(2);
+//Lines on the HUD stay solid for this long then fade out over CHAT_FADE_TIME
+pub const CHAT_DISPLAY_TIME: Duration = Duration::from_secs(8);
+pub const CHAT_FADE_TIME: Duration = Duration::from_secs(2);
+//Lines kept for the history when the chat box is open
+pub const CHAT_HISTORY_LENGTH: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatChannel {
+    All,
+    //Only players on the sender's team see it
+    Team,
+    //From the server itself, e.g. telling a player to slow down
+    Server,
+}
+
+impl ChatChannel {
+
+    pub fn get_name(&self) -> &'static str {
+
+        match self {
+            ChatChannel::All => "All",
+            ChatChannel::Team => "Team",
+            ChatChannel::Server => "Server",
+        }
+    }
+}
+
+//Trims, drops control characters and cuts it down to MAX_CHAT_LENGTH, None if nothing is left
+pub fn sanitize_chat(text: &str) -> Option<String> {
+
+    let text: String = text.chars().filter(|character| !character.is_control()).collect::<String>().trim().chars().take(MAX_CHAT_LENGTH).collect();
+
+    if text.is_empty() { None } else { Some(text) }
+}
+
+//Server side, one per player, token bucket so a burst is fine but a flood is dropped
+pub struct ChatFloodGuard {
+    allowance: f32,
+    last_message: Option<Instant>,
+}
+
+impl Default for ChatFloodGuard {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl ChatFloodGuard {
+
+    pub fn new() -> Self {
+
+        Self { allowance: CHAT_BURST, last_message: None }
+    }
+
+    //False if the message should be dropped
+    pub fn allow(&mut self, now: Instant) -> bool {
+
+        if let Some(last_message) = self.last_message {
+            self.allowance = (self.allowance + now.saturating_duration_since(last_message).as_secs_f32() / CHAT_MESSAGE_INTERVAL.as_secs_f32()).min(CHAT_BURST);
+        }
+        self.last_message = Some(now);
+
+        if self.allowance < 1.0 {
+            return false;
+        }
+        self.allowance -= 1.0;
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatLine {
+    pub player_id: u32,
+    pub channel: ChatChannel,
+    pub text: String,
+    pub received: Instant,
+}
+
+impl ChatLine {
+
+    //How it shows up on the HUD and in the console
+    pub fn get_display_text(&self) -> String {
+
+        match self.channel {
+            ChatChannel::All => format!("Player {}: {}", self.player_id, self.text),
+            ChatChannel::Team => format!("(Team) Player {}: {}", self.player_id, self.text),
+            ChatChannel::Server => self.text.clone(),
+        }
+    }
+}
+
+//Client side, what the chat overlay draws from
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+}
+
+impl Default for ChatLog {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl ChatLog {
+
+    pub fn new() -> Self {
+
+        Self { lines: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, line: ChatLine) {
+
+        self.lines.push_back(line);
+        while self.lines.len() > CHAT_HISTORY_LENGTH {
+            self.lines.pop_front();
+        }
+    }
+
+    //Oldest first
+    pub fn get_history(&self) -> &VecDeque<ChatLine> {
+
+        &self.lines
+    }
+
+    //Lines that haven't faded out yet with how opaque they should be, oldest first
+    pub fn get_visible(&self, now: Instant) -> Vec<(&ChatLine, f32)> {
+
+        self.lines.iter().filter_map(|line| {
+            let age = now.saturating_duration_since(line.received);
+            if age >= CHAT_DISPLAY_TIME + CHAT_FADE_TIME {
+                return None;
+            }
+            let fade = age.saturating_sub(CHAT_DISPLAY_TIME).as_secs_f32() / CHAT_FADE_TIME.as_secs_f32();
+
+            Some((line, 1.0 - fade))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_cleaned_up_and_cut_to_length() {
+
+        assert_eq!(sanitize_chat("  hello\n"), Some("hello".to_string()));
+        assert_eq!(sanitize_chat("bell\u{7}ringer"), Some("bellringer".to_string()));
+        assert_eq!(sanitize_chat(" \t\r\n"), None);
+        assert_eq!(sanitize_chat(&"é".repeat(MAX_CHAT_LENGTH * 2)).unwrap().chars().count(), MAX_CHAT_LENGTH);
+    }
+
+    #[test]
+    fn floods_are_dropped_until_the_allowance_builds_back_up() {
+
+        let mut flood_guard = ChatFloodGuard::new();
+        let start = Instant::now();
+
+        for _ in 0..CHAT_BURST as usize {
+            assert!(flood_guard.allow(start));
+        }
+        assert!(!flood_guard.allow(start));
+        assert!(!flood_guard.allow(start + CHAT_MESSAGE_INTERVAL / 2));
+        assert!(flood_guard.allow(start + CHAT_MESSAGE_INTERVAL));
+        assert!(!flood_guard.allow(start + CHAT_MESSAGE_INTERVAL));
+    }
+
+    #[test]
+    fn lines_fade_out_but_stay_in_the_history() {
+
+        let mut chat_log = ChatLog::new();
+        let start = Instant::now();
+        for i in 0..CHAT_HISTORY_LENGTH + 5 {
+            chat_log.push(ChatLine { player_id: 1, channel: ChatChannel::All, text: i.to_string(), received: start });
+        }
+
+        assert_eq!(chat_log.get_history().len(), CHAT_HISTORY_LENGTH);
+        assert_eq!(chat_log.get_history()[0].text, "5");
+
+        assert!(chat_log.get_visible(start).iter().all(|(_, alpha)| *alpha == 1.0));
+        let fading = chat_log.get_visible(start + CHAT_DISPLAY_TIME + CHAT_FADE_TIME / 2);
+        assert!((fading[0].1 - 0.5).abs() < 0.01);
+        assert!(chat_log.get_visible(start + CHAT_DISPLAY_TIME + CHAT_FADE_TIME).is_empty());
+        assert_eq!(chat_log.get_history().len(), CHAT_HISTORY_LENGTH);
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::game_state::TICK_RATE_SECONDS;
+
+//How often the client asks the server what tick it is on
+pub const CLOCK_SYNC_INTERVAL: Duration = Duration::from_millis(200);
+//Ticks of slack the client keeps on top of the trip to the server, so inputs turn up before the server needs them
+pub const DEFAULT_TARGET_TICK_LEAD: f32 = 2.0;
+//Round trips to remember, the fastest one was held up the least by queues so it gives the best estimate
+const CLOCK_SYNC_SAMPLES: usize = 10;
+//Fraction of the tick interval taken off or added for each tick the lead is out by
+const TICK_RATE_GAIN: f64 = 0.01;
+//Never run more than 5% fast or slow, enough to catch up without it being felt
+const MAX_TICK_RATE_ADJUSTMENT: f64 = 0.05;
+//Further behind than this and it's better to jump straight there
+const MAX_LEAD_ERROR: f64 = 10.0;
+
+#[derive(Debug, Copy, Clone)]
+struct ClockSample {
+    received: Instant,
+    round_trip: Duration,
+    //Estimated server tick at the moment the response was received
+    server_tick: f64,
+}
+
+//Client side, NTP style estimate of the server's tick so the client can stay a little ahead of it
+pub struct ClockSync {
+    epoch: Instant,
+    samples: VecDeque<ClockSample>,
+    last_request: Option<Instant>,
+    target_tick_lead: f32,
+    jumped: bool,
+}
+
+impl Default for ClockSync {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl ClockSync {
+
+    pub fn new() -> Self {
+
+        Self { epoch: Instant::now(), samples: VecDeque::new(), last_request: None, target_tick_lead: DEFAULT_TARGET_TICK_LEAD, jumped: false }
+    }
+
+    //Time to put in a clock request if one is due, the server echoes it back
+    pub fn request(&mut self, now: Instant) -> Option<f64> {
+
+        if self.last_request.is_some_and(|last_request| now.duration_since(last_request) < CLOCK_SYNC_INTERVAL) {
+            return None;
+        }
+        self.last_request = Some(now);
+
+        Some(now.duration_since(self.epoch).as_secs_f64())
+    }
+
+    //client_time is what we sent in the request, server_tick is the server's tick when it replied, now is when the reply arrived
+    pub fn add_sample(&mut self, client_time: f64, server_tick: f64, now: Instant) {
+
+        let round_trip = now.duration_since(self.epoch).as_secs_f64() - client_time;
+        //Both come back from the network, nothing stops them being rubbish
+        if !round_trip.is_finite() || round_trip < 0.0 || !server_tick.is_finite() {
+            return;
+        }
+
+        //Assume the reply took half the round trip to get here
+        let server_tick = server_tick + round_trip / 2.0 / TICK_RATE_SECONDS as f64;
+        self.samples.push_back(ClockSample { received: now, round_trip: Duration::from_secs_f64(round_trip), server_tick });
+        while self.samples.len() > CLOCK_SYNC_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+
+        !self.samples.is_empty()
+    }
+
+    //Fastest recent round trip, queueing only ever adds to it
+    pub fn get_round_trip(&self) -> Option<Duration> {
+
+        self.get_best_sample().map(|sample| sample.round_trip)
+    }
+
+    //The server ticks at a fixed rate so the estimate carries on from the best sample
+    pub fn get_server_tick(&self, now: Instant) -> Option<f64> {
+
+        self.get_best_sample().map(|sample| sample.server_tick + now.saturating_duration_since(sample.received).as_secs_f64() / TICK_RATE_SECONDS as f64)
+    }
+
+    //Server tick minus client tick
+    pub fn get_offset(&self, client_tick: f64, now: Instant) -> Option<f64> {
+
+        self.get_server_tick(now).map(|server_tick| server_tick - client_tick)
+    }
+
+    //How many ticks the client is ahead of the server right now
+    pub fn get_tick_lead(&self, client_tick: f64, now: Instant) -> Option<f64> {
+
+        self.get_offset(client_tick, now).map(|offset| -offset)
+    }
+
+    //Lead an input needs to reach the server with target_tick_lead ticks to spare
+    pub fn get_desired_tick_lead(&self) -> Option<f64> {
+
+        self.get_round_trip().map(|round_trip| round_trip.as_secs_f64() / 2.0 / TICK_RATE_SECONDS as f64 + self.target_tick_lead as f64)
+    }
+
+    //Multiply the tick interval by this, under 1 when the client has fallen behind and over 1 when it's too far ahead
+    pub fn get_tick_interval_scale(&self, client_tick: f64, now: Instant) -> f64 {
+
+        match self.get_lead_error(client_tick, now) {
+            Some(lead_error) => 1.0 - (lead_error * TICK_RATE_GAIN).clamp(-MAX_TICK_RATE_ADJUSTMENT, MAX_TICK_RATE_ADJUSTMENT),
+            None => 1.0,
+        }
+    }
+
+    //Ticks the client should jump by, the first estimate is always taken, after that only to catch up from far behind
+    //Going back would repeat ticks the server already has inputs for, so that is left to the tick rate
+    pub fn take_tick_jump(&mut self, client_tick: f64, now: Instant) -> i64 {
+
+        let Some(lead_error) = self.get_lead_error(client_tick, now) else {
+            return 0;
+        };
+        if self.jumped && lead_error <= MAX_LEAD_ERROR {
+            return 0;
+        }
+        self.jumped = true;
+
+        lead_error.round() as i64
+    }
+
+    pub fn set_target_tick_lead(&mut self, target_tick_lead: f32) {
+
+        self.target_tick_lead = target_tick_lead;
+    }
+
+    pub fn get_target_tick_lead(&self) -> f32 {
+
+        self.target_tick_lead
+    }
+
+    //Forget everything, e.g. after reconnecting to a different server
+    pub fn reset(&mut self) {
+
+        self.samples.clear();
+        self.last_request = None;
+        self.jumped = false;
+    }
+
+    //Positive when the client needs to be further ahead
+    fn get_lead_error(&self, client_tick: f64, now: Instant) -> Option<f64> {
+
+        Some(self.get_desired_tick_lead()? - self.get_tick_lead(client_tick, now)?)
+    }
+
+    fn get_best_sample(&self) -> Option<&ClockSample> {
+
+        self.samples.iter().min_by_key(|sample| sample.round_trip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks(seconds: f64) -> f64 {
+
+        seconds / TICK_RATE_SECONDS as f64
+    }
+
+    //Sends a request and gets the reply after round_trip, the server was on server_tick when it replied
+    fn exchange(clock_sync: &mut ClockSync, sent: Instant, round_trip: Duration, server_tick: f64) -> Instant {
+
+        let client_time = sent.duration_since(clock_sync.epoch).as_secs_f64();
+        let received = sent + round_trip;
+        clock_sync.add_sample(client_time, server_tick, received);
+
+        received
+    }
+
+    #[test]
+    fn estimates_the_server_tick_and_round_trip() {
+
+        let mut clock_sync = ClockSync::new();
+        assert!(!clock_sync.is_synced());
+
+        let start = clock_sync.epoch;
+        let received = exchange(&mut clock_sync, start, Duration::from_millis(100), 500.0);
+
+        assert_eq!(clock_sync.get_round_trip(), Some(Duration::from_millis(100)));
+        assert!((clock_sync.get_server_tick(received).unwrap() - (500.0 + ticks(0.05))).abs() < 0.01);
+        //Keeps counting on its own
+        let later = received + Duration::from_secs(1);
+        assert!((clock_sync.get_server_tick(later).unwrap() - (500.0 + ticks(1.05))).abs() < 0.01);
+        assert!((clock_sync.get_offset(400.0, received).unwrap() - (100.0 + ticks(0.05))).abs() < 0.01);
+    }
+
+    #[test]
+    fn trusts_the_fastest_round_trip() {
+
+        let mut clock_sync = ClockSync::new();
+        let start = clock_sync.epoch;
+
+        //Server really is on tick 1000 at start, this one sat in a queue on the way back
+        exchange(&mut clock_sync, start, Duration::from_millis(300), 1000.0 + ticks(0.05));
+        let received = exchange(&mut clock_sync, start + Duration::from_secs(1), Duration::from_millis(50), 1000.0 + ticks(1.025));
+
+        assert_eq!(clock_sync.get_round_trip(), Some(Duration::from_millis(50)));
+        assert!((clock_sync.get_server_tick(received).unwrap() - (1000.0 + ticks(1.05))).abs() < 0.01);
+    }
+
+    #[test]
+    fn nudges_the_tick_rate_towards_the_target_lead() {
+
+        let mut clock_sync = ClockSync::new();
+        let start = clock_sync.epoch;
+        let received = exchange(&mut clock_sync, start, Duration::from_millis(100), 100.0);
+        let server_tick = clock_sync.get_server_tick(received).unwrap();
+        let desired_tick_lead = clock_sync.get_desired_tick_lead().unwrap();
+        assert!((desired_tick_lead - (ticks(0.05) + DEFAULT_TARGET_TICK_LEAD as f64)).abs() < 0.01);
+
+        assert!((clock_sync.get_tick_interval_scale(server_tick + desired_tick_lead, received) - 1.0).abs() < 1e-6);
+        assert!(clock_sync.get_tick_interval_scale(server_tick + desired_tick_lead - 1.0, received) < 1.0);
+        assert!(clock_sync.get_tick_interval_scale(server_tick + desired_tick_lead + 1.0, received) > 1.0);
+        assert_eq!(clock_sync.get_tick_interval_scale(server_tick - 1000.0, received), 1.0 - MAX_TICK_RATE_ADJUSTMENT);
+    }
+
+    #[test]
+    fn jumps_once_then_only_to_catch_up() {
+
+        let mut clock_sync = ClockSync::new();
+        let start = clock_sync.epoch;
+        assert_eq!(clock_sync.take_tick_jump(0.0, start), 0);
+
+        let received = exchange(&mut clock_sync, start, Duration::ZERO, 100.0);
+
+        //Way ahead of the server, the first estimate still gets taken
+        let jump = clock_sync.take_tick_jump(500.0, received);
+        assert_eq!(jump, 100 + DEFAULT_TARGET_TICK_LEAD as i64 - 500);
+        assert_eq!(clock_sync.take_tick_jump(500.0, received), 0);
+        assert_eq!(clock_sync.take_tick_jump(97.0, received), 0);
+        assert_eq!(clock_sync.take_tick_jump(50.0, received), 52);
+    }
+
+    #[test]
+    fn ignores_replies_from_the_future() {
+
+        let mut clock_sync = ClockSync::new();
+
+        clock_sync.add_sample(1000.0, 10.0, clock_sync.epoch);
+        clock_sync.add_sample(0.0, f64::NAN, clock_sync.epoch);
+
+        assert!(!clock_sync.is_synced());
+    }
+}
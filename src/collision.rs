@@ -263,7 +263,7 @@ impl Sphere {
             }
         }
 
-        best_collision_packet.collided = collision_packet.penetration_or_time < 1.0;
+        best_collision_packet.collided &= best_collision_packet.penetration_or_time < 1.0;
 
         best_collision_packet
     }
@@ -288,6 +288,16 @@ impl Sphere {
         self.center = center;
     }
 
+    pub fn get_center(&self) -> glam::f32::Vec3 {
+
+        self.center
+    }
+
+    pub fn get_radius(&self) -> f32 {
+
+        self.radius
+    }
+
     pub fn get_transform(&self) -> glam::f32::Mat4 {
 
         glam::f32::Mat4::from_scale_rotation_translation(glam::f32::Vec3::new(self.radius, self.radius, self.radius), glam::f32::Quat::IDENTITY, self.center)
@@ -372,7 +382,7 @@ impl Capsule {
 
         if capsule_normal.dot(triangle_normal) != 0.0 {
 
-            let t = triangle_normal.dot(triangle.vertex_0 - self.base) / triangle_normal.dot(capsule_normal).abs();
+            let t = triangle_normal.dot(triangle.vertex_0 - self.base) / triangle_normal.dot(capsule_normal);
             let line_plane_intersection = self.base + capsule_normal * t;
 
             reference_point = closest_point_on_triangle(triangle, &triangle_normal, &line_plane_intersection);
@@ -385,290 +395,57 @@ impl Capsule {
 
     pub fn vs_while_moving_triangle(&self, velocity: &glam::f32::Vec3, triangle: &Triangle) -> CollisionPacket {
 
-        let ray = Ray::new((self.base + self.tip) / 2.0, *velocity);
-
-        let self_normal = (self.tip - self.base).normalize_or_zero();
-
-        let mut test_capsule = self.clone();
-        //Test against triangle vertexes
-        test_capsule.set_center(triangle.vertex_0);
-        let tip_1 = test_capsule.tip;
-        let base_1 = test_capsule.base;
-        let mut best_collision_packet = ray.vs_capsule(&test_capsule);
-
-        test_capsule.set_center(triangle.vertex_1);
-        let tip_2 = test_capsule.tip;
-        let base_2 = test_capsule.base;
-        let mut collision_packet = ray.vs_capsule(&test_capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        test_capsule.set_center(triangle.vertex_2);
-        let tip_3 = test_capsule.tip;
-        let base_3 = test_capsule.base;
-        collision_packet = ray.vs_capsule(&test_capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        //Triangle edges top
-        let mut test_capsule_direction = (tip_2 - tip_1).normalize_or_zero() * self.radius;
-        let point_a = tip_1 - self_normal * self.radius;
-        let point_b = tip_2 - self_normal * self.radius;
-        let point_c = tip_3 - self_normal * self.radius;
-        let mut capsule = Capsule::new(point_b + test_capsule_direction, point_a - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        test_capsule_direction = (tip_3 - tip_1).normalize_or_zero() * self.radius;
-        capsule = Capsule::new(point_c + test_capsule_direction, point_a - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        test_capsule_direction = (tip_3 - tip_2).normalize_or_zero() * self.radius;
-        capsule = Capsule::new(point_c + test_capsule_direction, point_b - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        //Triangle edges bottom
-        test_capsule_direction = (base_2 - base_1).normalize_or_zero() * self.radius;
-        let point_a_base = base_1 + self_normal * self.radius;
-        let point_b_base = base_2 + self_normal * self.radius;
-        let point_c_base = base_3 + self_normal * self.radius;
-        capsule = Capsule::new(point_b_base + test_capsule_direction, point_a_base - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        test_capsule_direction = (base_3 - base_1).normalize_or_zero() * self.radius;
-        capsule = Capsule::new(point_c_base + test_capsule_direction, point_a_base - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&capsule);
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        test_capsule_direction = (base_3 - base_2).normalize_or_zero() * self.radius;
-        capsule = Capsule::new(point_c_base + test_capsule_direction, point_b_base - test_capsule_direction, self.radius);
-        collision_packet = ray.vs_capsule(&Capsule::new(point_c_base + test_capsule_direction, point_b_base - test_capsule_direction, self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        //Sides
+        //Same as casting the center as a ray against the triangle swept back along the capsule's inner line and grown by the radius
+        //Every surface tested is inside that shape, the ray starts outside so the first one it hits is on the outside
+        let center = self.get_center();
+        let axis = (self.tip - self.base).normalize_or_zero();
+        let a = self.base + axis * self.radius - center;
+        let b = self.tip - axis * self.radius - center;
+        let ray = Ray::new(center, *velocity);
+        let vertices = [triangle.vertex_0, triangle.vertex_1, triangle.vertex_2];
         let triangle_normal = (triangle.vertex_1 - triangle.vertex_0).cross(triangle.vertex_2 - triangle.vertex_0).normalize_or_zero();
-        let edge_1_normal = (triangle.vertex_1 - triangle.vertex_0).cross(triangle_normal).normalize_or_zero();
-        collision_packet = ray.vs_triangle(&Triangle::new(point_a + edge_1_normal * self.radius, point_b + edge_1_normal * self.radius, point_a_base + edge_1_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
 
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        collision_packet = ray.vs_triangle(&Triangle::new(point_a_base + edge_1_normal * self.radius, point_b_base + edge_1_normal * self.radius, point_b + edge_1_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        let edge_2_normal = triangle_normal.cross(triangle.vertex_2 - triangle.vertex_0).normalize_or_zero();
-        collision_packet = ray.vs_triangle(&Triangle::new(point_a + edge_2_normal * self.radius, point_c + edge_2_normal * self.radius, point_a_base + edge_2_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        collision_packet = ray.vs_triangle(&Triangle::new(point_a_base + edge_2_normal * self.radius, point_c_base + edge_2_normal * self.radius, point_c + edge_2_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-
-        let edge_3_normal = (triangle.vertex_2 - triangle.vertex_1).cross(triangle_normal).normalize_or_zero();
-        collision_packet = ray.vs_triangle(&Triangle::new(point_b + edge_3_normal * self.radius, point_c + edge_3_normal * self.radius, point_b_base + edge_3_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
-
-                best_collision_packet = collision_packet;
-            }
-        }
-        collision_packet = ray.vs_triangle(&Triangle::new(point_b_base + edge_3_normal * self.radius, point_c_base + edge_3_normal * self.radius, point_c + edge_3_normal * self.radius));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
+        let mut best_collision_packet = CollisionPacket { collided: false, position: glam::f32::Vec3::ZERO, normal: glam::f32::Vec3::Y, penetration_or_time: f32::MAX };
+        let mut keep_earliest = |collision_packet: CollisionPacket, normal: glam::f32::Vec3| {
+            if collision_packet.collided && collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
+                best_collision_packet = CollisionPacket { normal, ..collision_packet };
             }
-            else {
+        };
 
-                best_collision_packet = collision_packet;
+        //Both ends of the swept triangle
+        for end in [a, b] {
+            for side in [triangle_normal, -triangle_normal] {
+                let offset = side * self.radius - end;
+                keep_earliest(ray.vs_triangle(&Triangle::new(triangle.vertex_0 + offset, triangle.vertex_1 + offset, triangle.vertex_2 + offset)), side);
             }
         }
 
-        //Front face
-        collision_packet = ray.vs_triangle(&Triangle::new(tip_1, tip_2, tip_3));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
-
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
-            }
-            else {
+        for i in 0..3 {
+            let start = vertices[i];
+            let end = vertices[(i + 1) % 3];
 
-                best_collision_packet = collision_packet;
+            //Side swept by this edge
+            let side_normal = (end - start).cross(b - a).normalize_or_zero();
+            for side in [side_normal, -side_normal] {
+                let offset = side * self.radius;
+                keep_earliest(ray.vs_triangle(&Triangle::new(start - a + offset, end - a + offset, end - b + offset)), side);
+                keep_earliest(ray.vs_triangle(&Triangle::new(start - a + offset, end - b + offset, start - b + offset)), side);
             }
-        }
-
-        //Back face
-        collision_packet = ray.vs_triangle(&Triangle::new(base_1, base_3, base_2));
-        if collision_packet.collided {
-            if best_collision_packet.collided {
 
-                if collision_packet.penetration_or_time < best_collision_packet.penetration_or_time {
-                    
-                    best_collision_packet = collision_packet;
-                }
+            //Rounded edges, this edge at both ends and the corner swept between them
+            for (edge_start, edge_end) in [(start - a, end - a), (start - b, end - b), (start - a, start - b)] {
+                let collision_packet = ray.vs_cylinder(&edge_start, &edge_end, self.radius);
+                keep_earliest(collision_packet, collision_packet.normal);
             }
-            else {
 
-                best_collision_packet = collision_packet;
+            //Rounded corners
+            for corner in [start - a, start - b] {
+                let collision_packet = ray.vs_sphere(&Sphere::new(corner, self.radius));
+                keep_earliest(collision_packet, collision_packet.normal.normalize_or_zero());
             }
         }
 
-        best_collision_packet.collided &= best_collision_packet.penetration_or_time.abs() < 1.0;
+        best_collision_packet.collided &= best_collision_packet.penetration_or_time < 1.0;
 
         best_collision_packet
     }
@@ -706,6 +483,16 @@ impl Capsule {
         (self.base + self.tip) / 2.0
     }
 
+    pub fn get_base(&self) -> glam::f32::Vec3 {
+
+        self.base
+    }
+
+    pub fn get_tip(&self) -> glam::f32::Vec3 {
+
+        self.tip
+    }
+
     pub fn render(&self, render_commands: &mut Vec<RenderCommands>) {
 
         let up = (self.tip - self.base).normalize_or_zero();
@@ -1004,8 +791,10 @@ impl Ray {
 
         let t = v0v2.dot(qvec) * inv_det;
         let normal = v0v1.cross(v0v2);
+        //Behind the start of the ray doesn't count, same as vs_sphere
+        let collided = t > 0.0;
 
-        CollisionPacket { collided: true, position: self.start + self.direction * t, normal , penetration_or_time: t }
+        CollisionPacket { collided, position: self.start + self.direction * t, normal , penetration_or_time: t }
     }
 
     pub fn vs_triangle_soup(&self, triangle_soup: &TriangleSoup) -> CollisionPacket {
@@ -1055,4 +844,77 @@ pub fn closest_point_on_triangle(triangle: &Triangle, triangle_normal: &glam::f3
     }
     
     closest_point
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Facing +Y, big enough that nothing in these tests goes past the edges
+    fn floor(y: f32) -> Triangle {
+
+        Triangle::new(glam::f32::Vec3::new(-10.0, y, -10.0), glam::f32::Vec3::new(0.0, y, 10.0), glam::f32::Vec3::new(10.0, y, -10.0))
+    }
+
+    #[test]
+    fn rays_only_hit_triangles_in_front_of_them() {
+
+        let ray = Ray::new(glam::f32::Vec3::ZERO, glam::f32::Vec3::NEG_Y * 10.0);
+
+        let hit = ray.vs_triangle(&floor(-5.0));
+        assert!(hit.collided);
+        assert!((hit.penetration_or_time - 0.5).abs() < 0.0001);
+
+        //A wall behind whoever fired used to block the shot
+        assert!(!ray.vs_triangle(&floor(5.0)).collided);
+    }
+
+    #[test]
+    fn moving_spheres_only_collide_with_what_they_reach() {
+
+        let sphere = Sphere::new(glam::f32::Vec3::new(0.0, 5.0, 0.0), 0.5);
+
+        let hit = sphere.vs_while_moving_triangle(&(glam::f32::Vec3::NEG_Y * 10.0), &floor(0.0));
+        assert!(hit.collided);
+        assert!((hit.penetration_or_time - 0.45).abs() < 0.0001);
+
+        //Moving away or not far enough, whatever the last surface tested said
+        assert!(!sphere.vs_while_moving_triangle(&glam::f32::Vec3::Y, &floor(0.0)).collided);
+        assert!(!sphere.vs_while_moving_triangle(&glam::f32::Vec3::NEG_Y, &floor(0.0)).collided);
+    }
+
+    #[test]
+    fn moving_capsules_stop_on_the_outside_of_triangles() {
+
+        let capsule = Capsule::new(glam::f32::Vec3::new(0.0, 1.0, 0.0), glam::f32::Vec3::new(0.0, 3.0, 0.0), 0.5);
+
+        //Dropping onto the floor, the bottom of the capsule touches it after a tenth of the move
+        let hit = capsule.vs_while_moving_triangle(&(glam::f32::Vec3::NEG_Y * 10.0), &floor(0.0));
+        assert!(hit.collided);
+        assert!((hit.penetration_or_time - 0.1).abs() < 0.0001, "{:?}", hit);
+        assert!(hit.normal.abs_diff_eq(glam::f32::Vec3::Y, 0.0001), "{:?}", hit);
+
+        //Moving away from it or not reaching it
+        assert!(!capsule.vs_while_moving_triangle(&glam::f32::Vec3::Y, &floor(0.0)).collided);
+        assert!(!capsule.vs_while_moving_triangle(&(glam::f32::Vec3::NEG_Y * 0.5), &floor(0.0)).collided);
+
+        //Walking into the edge of a ledge at head height hits the rounded edge, not the ledge's plane
+        let ledge = Triangle::new(glam::f32::Vec3::new(1.0, 2.0, -10.0), glam::f32::Vec3::new(1.0, 2.0, 10.0), glam::f32::Vec3::new(20.0, 2.0, 0.0));
+        let hit = capsule.vs_while_moving_triangle(&(glam::f32::Vec3::X * 2.0), &ledge);
+        assert!(hit.collided);
+        assert!((hit.penetration_or_time - 0.25).abs() < 0.0001, "{:?}", hit);
+    }
+
+    #[test]
+    fn capsules_pointing_either_way_find_where_they_cross_a_triangle() {
+
+        //Leaning over and through the floor, crossing it at x = 1
+        let down = Capsule::new(glam::f32::Vec3::new(0.0, 1.0, 0.0), glam::f32::Vec3::new(2.0, -1.0, 0.0), 0.5);
+        let up = Capsule::new(glam::f32::Vec3::new(2.0, -1.0, 0.0), glam::f32::Vec3::new(0.0, 1.0, 0.0), 0.5);
+
+        for capsule in [down, up] {
+            let hit = capsule.vs_triangle(&floor(0.0));
+            assert!(hit.collided, "{:?}", capsule);
+            assert!((hit.penetration_or_time - 0.5).abs() < 0.0001, "{:?}", hit);
+        }
+    }
+}
@@ -3,20 +3,66 @@ use std::collections::HashMap;
 pub struct Console {
     log: String,
     timing: HashMap<String, f32>,
+    //Headless servers have no console window so echo everything to stdout
+    print_to_stdout: bool,
+    //Typed into the console window, the game runs them at the start of the next tick
+    commands: Vec<String>,
+    //Output of the command being run for RCON, it goes back to the admin as well as into the log
+    capture: Option<String>,
 }
 
 impl Console {
 
     pub fn new() -> Self {
 
-        Self { log: "".to_string(), timing: HashMap::new() }
+        Self { log: "".to_string(), timing: HashMap::new(), print_to_stdout: false, commands: Vec::new(), capture: None }
     }
 
     pub fn output_to_console(&mut self, output: &str) {
 
+        if self.print_to_stdout {
+            println!("{}", output);
+        }
+
+        if let Some(capture) = self.capture.as_mut() {
+            *capture += &(output.to_string() + "\n");
+        }
+
         self.log += &(output.to_string() + "\n");
     }
 
+    pub fn submit_command(&mut self, command: &str) {
+
+        self.output_to_console(&format!("> {}", command));
+        self.commands.push(command.to_string());
+    }
+
+    pub fn get_commands_pending(&self) -> usize {
+
+        self.commands.len()
+    }
+
+    pub fn take_commands(&mut self) -> Vec<String> {
+
+        std::mem::take(&mut self.commands)
+    }
+
+    //Everything output until finish_capture is also collected
+    pub fn start_capture(&mut self) {
+
+        self.capture = Some("".to_string());
+    }
+
+    pub fn finish_capture(&mut self) -> String {
+
+        self.capture.take().unwrap_or_default()
+    }
+
+    pub fn set_print_to_stdout(&mut self, print_to_stdout: bool) {
+
+        self.print_to_stdout = print_to_stdout;
+    }
+
     pub fn get_log(&self) -> &String {
 
         &self.log
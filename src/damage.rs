@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::TICK_RATE_SECONDS;
+use crate::hitbox::HitRegion;
+
+pub const MAX_HEALTH: f32 = 100.0;
+pub const MAX_ARMOR: f32 = 100.0;
+//What everyone comes back with
+pub const SPAWN_ARMOR: f32 = 50.0;
+//Share of each hit the armor soaks up instead of health while there's any left
+pub const ARMOR_ABSORPTION: f32 = 0.6;
+//Seconds between dying and coming back
+pub const RESPAWN_TIME: f32 = 3.0;
+
+pub fn get_respawn_ticks() -> u32 {
+
+    (RESPAWN_TIME / TICK_RATE_SECONDS).round() as u32
+}
+
+//Where on the victim the damage landed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HitLocation {
+    Body(HitRegion),
+    Splash,
+}
+
+impl HitLocation {
+
+    pub fn get_name(&self) -> String {
+
+        match self {
+            HitLocation::Body(region) => format!("the {}", region.get_name()),
+            HitLocation::Splash => "the blast".to_string(),
+        }
+    }
+}
+
+//Everything that hurts someone goes through one of these, the server applies it and tells both players
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamageEvent {
+    pub attacker: u32,
+    pub victim: u32,
+    //Index into WEAPONS
+    pub weapon: usize,
+    pub location: HitLocation,
+    //Before armor, after the hit region's multiplier
+    pub damage: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    health: f32,
+    armor: f32,
+}
+
+impl Default for Health {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl Health {
+
+    pub fn new() -> Self {
+
+        Self { health: MAX_HEALTH, armor: SPAWN_ARMOR }
+    }
+
+    //Armor takes its share first, whatever it can't cover comes off health, returns how much health was lost
+    pub fn take_damage(&mut self, damage: f32) -> f32 {
+
+        let absorbed = (damage.max(0.0) * ARMOR_ABSORPTION).min(self.armor);
+        self.armor -= absorbed;
+        let lost = (damage.max(0.0) - absorbed).min(self.health);
+        self.health -= lost;
+
+        lost
+    }
+
+    pub fn add_armor(&mut self, armor: f32) {
+
+        self.armor = (self.armor + armor).clamp(0.0, MAX_ARMOR);
+    }
+
+    pub fn get_health(&self) -> f32 {
+
+        self.health
+    }
+
+    pub fn get_armor(&self) -> f32 {
+
+        self.armor
+    }
+
+    pub fn is_dead(&self) -> bool {
+
+        self.health <= 0.0
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    pub kills: i32,
+    pub deaths: u32,
+}
+
+//Kept by the server and sent to everyone whenever it changes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scoreboard {
+    scores: BTreeMap<u32, Score>,
+}
+
+impl Scoreboard {
+
+    pub fn new() -> Self {
+
+        Self { scores: BTreeMap::new() }
+    }
+
+    pub fn add_player(&mut self, player_id: u32) {
+
+        self.scores.entry(player_id).or_default();
+    }
+
+    pub fn remove_player(&mut self, player_id: u32) {
+
+        self.scores.remove(&player_id);
+    }
+
+    //Killing yourself costs a kill rather than giving one
+    pub fn record_death(&mut self, attacker: u32, victim: u32) {
+
+        self.scores.entry(victim).or_default().deaths += 1;
+        self.scores.entry(attacker).or_default().kills += if attacker == victim { -1 } else { 1 };
+    }
+
+    pub fn get_score(&self, player_id: u32) -> Option<Score> {
+
+        self.scores.get(&player_id).copied()
+    }
+
+    //Most kills first, fewest deaths breaks ties
+    pub fn get_rows(&self) -> Vec<(u32, Score)> {
+
+        let mut rows: Vec<(u32, Score)> = self.scores.iter().map(|(player_id, score)| (*player_id, *score)).collect();
+        rows.sort_by(|(_, a), (_, b)| b.kills.cmp(&a.kills).then(a.deaths.cmp(&b.deaths)));
+
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_soaks_up_its_share_until_it_runs_out() {
+
+        let mut health = Health::new();
+        assert_eq!(health.take_damage(50.0), 50.0 - 50.0 * ARMOR_ABSORPTION);
+        assert_eq!(health.get_armor(), SPAWN_ARMOR - 50.0 * ARMOR_ABSORPTION);
+
+        //Only 20 armor left to take 36 of this
+        let before = health.get_health();
+        assert_eq!(health.take_damage(60.0), 40.0);
+        assert_eq!(health.get_armor(), 0.0);
+        assert!(!health.is_dead());
+
+        //Can't lose more than is left
+        assert_eq!(health.take_damage(1000.0), before - 40.0);
+        assert!(health.is_dead());
+
+        health.add_armor(1000.0);
+        assert_eq!(health.get_armor(), MAX_ARMOR);
+    }
+
+    #[test]
+    fn scoreboard_counts_kills_and_deaths() {
+
+        let mut scoreboard = Scoreboard::new();
+        scoreboard.add_player(1);
+        scoreboard.add_player(2);
+        scoreboard.add_player(3);
+
+        scoreboard.record_death(1, 2);
+        scoreboard.record_death(1, 3);
+        scoreboard.record_death(3, 2);
+        scoreboard.record_death(3, 3);
+
+        assert_eq!(scoreboard.get_score(1), Some(Score { kills: 2, deaths: 0 }));
+        assert_eq!(scoreboard.get_score(2), Some(Score { kills: 0, deaths: 2 }));
+        assert_eq!(scoreboard.get_score(3), Some(Score { kills: 0, deaths: 2 }));
+        assert_eq!(scoreboard.get_rows().iter().map(|(player_id, _)| *player_id).collect::<Vec<u32>>(), vec![1, 2, 3]);
+
+        scoreboard.remove_player(2);
+        assert_eq!(scoreboard.get_score(2), None);
+    }
+}
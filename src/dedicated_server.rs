@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::console::Console;
+use crate::game_state::GameState;
+use crate::input::Inputs;
+use crate::net;
+use crate::rcon;
+use crate::resource_manager::{self, ResourceManager};
+
+//Runs the game tick loop with no window, GPU or audio device, never returns unless the socket can't be bound
+//With an RCON password admins can run console commands over TCP on the same port
+pub fn run(address: &str, name: &str, rcon_password: Option<&str>, rcon_log: Option<&str>) {
+
+    let console = Rc::new(RefCell::new(Console::new()));
+    console.borrow_mut().set_print_to_stdout(true);
+
+    let mut resource_manager = ResourceManager::new(console.clone());
+    resource_manager.bulk_load_headless();
+
+    let mut game_state = GameState::new_dedicated(console.clone());
+
+    match net::NetServer::bind(address) {
+        Ok(server) => game_state.set_network(net::Network::Server(server)),
+        Err(e) => {
+            console.borrow_mut().output_to_console(&format!("Failed to bind {}: {}", address, e));
+            return;
+        }
+    }
+
+    match net::discovery::DiscoveryResponder::bind(("0.0.0.0", net::discovery::DISCOVERY_PORT), name, resource_manager::DEFAULT_MAP) {
+        Ok(discovery) => game_state.set_discovery(discovery),
+        Err(e) => console.borrow_mut().output_to_console(&format!("LAN server browsers won't see this server: {}", e)),
+    }
+
+    if let Some(rcon_password) = rcon_password {
+        match rcon::RconServer::bind(address, rcon_password, console.clone()) {
+            Ok(mut rcon) => {
+                if let Some(rcon_log) = rcon_log {
+                    if let Err(e) = rcon.set_log_file(rcon_log) {
+                        console.borrow_mut().output_to_console(&format!("Failed to open RCON log {}: {}", rcon_log, e));
+                    }
+                }
+                game_state.set_rcon(rcon);
+            }
+            Err(e) => console.borrow_mut().output_to_console(&format!("Failed to start RCON on {}: {}", address, e)),
+        }
+    }
+
+    //Nobody is at the keyboard but update still wants something to read from
+    let mut inputs = Inputs::new();
+
+    loop {
+        game_state.update(&mut inputs, &mut resource_manager);
+        std::thread::sleep(game_state.get_time_until_next_tick());
+    }
+}
@@ -0,0 +1,460 @@
+use serde::{Deserialize, Serialize};
+
+use crate::collision;
+use crate::weapon;
+
+//Index into the component storages plus how many times that slot has been reused
+//An id for something that has been despawned never matches whatever takes its slot, so it can sit in a packet or a map safely
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+
+    pub fn new(index: u32, generation: u32) -> Self {
+
+        Self { index, generation }
+    }
+
+    pub fn get_index(&self) -> u32 {
+
+        self.index
+    }
+
+    pub fn get_generation(&self) -> u32 {
+
+        self.generation
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub position: glam::f32::Vec3,
+    pub rotation: glam::f32::Quat,
+    pub scale: glam::f32::Vec3,
+}
+
+impl Transform {
+
+    pub fn new(position: glam::f32::Vec3, rotation: glam::f32::Quat, scale: glam::f32::Vec3) -> Self {
+
+        Self { position, rotation, scale }
+    }
+
+    pub fn from_position(position: glam::f32::Vec3) -> Self {
+
+        Self { position, rotation: glam::f32::Quat::IDENTITY, scale: glam::f32::Vec3::ONE }
+    }
+
+    //T * R * S
+    pub fn get_matrix(&self) -> glam::f32::Mat4 {
+
+        glam::f32::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+}
+
+//Shapes are relative to the transform's position, rotation and scale are ignored
+#[derive(Debug, Copy, Clone)]
+pub enum ColliderShape {
+    Sphere(collision::Sphere),
+    Capsule(collision::Capsule),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    //Dynamic colliders are moved by physics, static ones only get collided with
+    pub dynamic: bool,
+}
+
+impl Collider {
+
+    pub fn new(shape: ColliderShape, dynamic: bool) -> Self {
+
+        Self { shape, dynamic }
+    }
+
+    pub fn get_world_shape(&self, transform: &Transform) -> ColliderShape {
+
+        match self.shape {
+            ColliderShape::Sphere(sphere) => ColliderShape::Sphere(collision::Sphere::new(sphere.get_center() + transform.position, sphere.get_radius())),
+            ColliderShape::Capsule(capsule) => ColliderShape::Capsule(collision::Capsule::new(capsule.get_base() + transform.position, capsule.get_tip() + transform.position, capsule.get_radius())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Renderable {
+    Model { model_name: String, texture_name: String },
+    SkeletonModel { model_name: String, texture_name: String },
+    //The collider's debug shapes
+    Collider,
+}
+
+impl Renderable {
+
+    pub fn model(model_name: &str, texture_name: &str) -> Self {
+
+        Renderable::Model { model_name: model_name.to_string(), texture_name: texture_name.to_string() }
+    }
+
+    pub fn skeleton_model(model_name: &str, texture_name: &str) -> Self {
+
+        Renderable::SkeletonModel { model_name: model_name.to_string(), texture_name: texture_name.to_string() }
+    }
+}
+
+//Skeleton models share one pose between everything drawing them, so entities using the same model animate together
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkeletonAnimation {
+    pub model_name: String,
+    //Multiplies the tick time the animation advances by
+    pub speed: f32,
+}
+
+//Follows a player's simulation rather than being simulated itself
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerControl {
+    //Whoever is at this keyboard
+    Local,
+    Remote(u32),
+}
+
+//Level decoration, spins and sways from a fixed origin
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Spinner {
+    pub origin: glam::f32::Vec3,
+    //Euler XYZ radians per tick
+    pub rotation_rate: glam::f32::Vec3,
+    //Along X
+    pub sway_amplitude: f32,
+    //Radians per tick
+    pub sway_rate: f32,
+}
+
+//Rockets and grenades, moved by systems::fly_projectiles rather than falling like other dynamic things
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Projectile {
+    //Player who fired it, they can't hit themselves directly but can catch their own splash
+    pub owner: u32,
+    //Index into WEAPONS
+    pub weapon: usize,
+    pub definition: weapon::ProjectileDefinition,
+    pub velocity: glam::f32::Vec3,
+    pub ticks_left: u32,
+    pub bounces_left: u32,
+}
+
+impl Projectile {
+
+    pub fn new(owner: u32, weapon: usize, definition: weapon::ProjectileDefinition, direction: glam::f32::Vec3) -> Self {
+
+        Self { owner, weapon, definition, velocity: direction.normalize_or_zero() * definition.speed, ticks_left: definition.get_lifetime_ticks(), bounces_left: definition.max_bounces }
+    }
+}
+
+//One slot per entity index, the generation is kept with each component so a stale id never finds one
+pub struct ComponentStorage<T> {
+    components: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for ComponentStorage<T> {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl<T> ComponentStorage<T> {
+
+    pub fn new() -> Self {
+
+        Self { components: Vec::new() }
+    }
+
+    fn insert(&mut self, id: EntityId, component: T) {
+
+        let index = id.index as usize;
+        if self.components.len() <= index {
+            self.components.resize_with(index + 1, || None);
+        }
+
+        self.components[index] = Some((id.generation, component));
+    }
+
+    fn remove(&mut self, id: EntityId) -> Option<T> {
+
+        self.get(id)?;
+
+        self.components[id.index as usize].take().map(|(_, component)| component)
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+
+        match self.components.get(id.index as usize)? {
+            Some((generation, component)) if *generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+
+        match self.components.get_mut(id.index as usize)? {
+            Some((generation, component)) if *generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+
+        self.components.iter().enumerate().filter_map(|(index, slot)| slot.as_ref().map(|(generation, component)| (EntityId::new(index as u32, *generation), component)))
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+
+        self.components.iter_mut().enumerate().filter_map(|(index, slot)| slot.as_mut().map(|(generation, component)| (EntityId::new(index as u32, *generation), component)))
+    }
+}
+
+//Anything the store can hold, each one has its own storage in EntityStore
+pub trait Component: Sized + 'static {
+
+    fn get_storage(entities: &EntityStore) -> &ComponentStorage<Self>;
+
+    fn get_mut_storage(entities: &mut EntityStore) -> &mut ComponentStorage<Self>;
+}
+
+macro_rules! impl_component {
+    ($component:ty, $storage:ident) => {
+        impl Component for $component {
+
+            fn get_storage(entities: &EntityStore) -> &ComponentStorage<Self> {
+
+                &entities.$storage
+            }
+
+            fn get_mut_storage(entities: &mut EntityStore) -> &mut ComponentStorage<Self> {
+
+                &mut entities.$storage
+            }
+        }
+    };
+}
+
+impl_component!(Transform, transforms);
+impl_component!(Collider, colliders);
+impl_component!(Renderable, renderables);
+impl_component!(SkeletonAnimation, skeleton_animations);
+impl_component!(PlayerControl, player_controls);
+impl_component!(Spinner, spinners);
+impl_component!(Projectile, projectiles);
+
+pub struct EntityStore {
+    //Current generation of every slot, bumped on despawn
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    transforms: ComponentStorage<Transform>,
+    colliders: ComponentStorage<Collider>,
+    renderables: ComponentStorage<Renderable>,
+    skeleton_animations: ComponentStorage<SkeletonAnimation>,
+    player_controls: ComponentStorage<PlayerControl>,
+    spinners: ComponentStorage<Spinner>,
+    projectiles: ComponentStorage<Projectile>,
+}
+
+impl Default for EntityStore {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl EntityStore {
+
+    pub fn new() -> Self {
+
+        Self { generations: Vec::new(), alive: Vec::new(), free: Vec::new(), transforms: ComponentStorage::new(), colliders: ComponentStorage::new(), renderables: ComponentStorage::new(), skeleton_animations: ComponentStorage::new(), player_controls: ComponentStorage::new(), spinners: ComponentStorage::new(), projectiles: ComponentStorage::new() }
+    }
+
+    pub fn spawn(&mut self) -> EntityId {
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.generations.len() as u32 - 1
+            }
+        };
+        self.alive[index as usize] = true;
+
+        EntityId::new(index, self.generations[index as usize])
+    }
+
+    //For mirroring an entity the server spawned, false if the slot is taken or the id is older than one already seen there
+    pub fn spawn_at(&mut self, id: EntityId) -> bool {
+
+        let index = id.index as usize;
+        while self.generations.len() <= index {
+            self.free.push(self.generations.len() as u32);
+            self.generations.push(0);
+            self.alive.push(false);
+        }
+        if self.alive[index] || self.generations[index] > id.generation {
+            return false;
+        }
+
+        self.free.retain(|free| *free != id.index);
+        self.generations[index] = id.generation;
+        self.alive[index] = true;
+
+        true
+    }
+
+    //Takes every component with it, false if it was already gone
+    pub fn despawn(&mut self, id: EntityId) -> bool {
+
+        if !self.is_alive(id) {
+            return false;
+        }
+
+        self.transforms.remove(id);
+        self.colliders.remove(id);
+        self.renderables.remove(id);
+        self.skeleton_animations.remove(id);
+        self.player_controls.remove(id);
+        self.spinners.remove(id);
+        self.projectiles.remove(id);
+
+        let index = id.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(id.index);
+
+        true
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+
+        self.alive.get(id.index as usize).is_some_and(|alive| *alive) && self.generations[id.index as usize] == id.generation
+    }
+
+    pub fn get_entity_count(&self) -> usize {
+
+        self.alive.iter().filter(|alive| **alive).count()
+    }
+
+    //Replaces any component of the same type, false if the entity is gone
+    pub fn insert<T: Component>(&mut self, id: EntityId, component: T) -> bool {
+
+        if !self.is_alive(id) {
+            return false;
+        }
+        T::get_mut_storage(self).insert(id, component);
+
+        true
+    }
+
+    pub fn remove<T: Component>(&mut self, id: EntityId) -> Option<T> {
+
+        T::get_mut_storage(self).remove(id)
+    }
+
+    pub fn get<T: Component>(&self, id: EntityId) -> Option<&T> {
+
+        T::get_storage(self).get(id)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, id: EntityId) -> Option<&mut T> {
+
+        T::get_mut_storage(self).get_mut(id)
+    }
+
+    //Every entity with a T, in index order
+    pub fn iter<T: Component>(&self) -> impl Iterator<Item = (EntityId, &T)> {
+
+        T::get_storage(self).iter()
+    }
+
+    pub fn iter_mut<T: Component>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+
+        T::get_mut_storage(self).iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn despawned_ids_never_match_their_replacement() {
+
+        let mut entities = EntityStore::new();
+        let first = entities.spawn();
+        entities.insert(first, Transform::from_position(glam::f32::Vec3::X));
+
+        assert!(entities.despawn(first));
+        assert!(!entities.despawn(first));
+        let second = entities.spawn();
+
+        //Same slot, new generation
+        assert_eq!(second.get_index(), first.get_index());
+        assert_ne!(second, first);
+        assert!(!entities.is_alive(first));
+        assert!(entities.get::<Transform>(second).is_none());
+        assert!(!entities.insert(first, Transform::from_position(glam::f32::Vec3::Y)));
+        assert!(entities.get::<Transform>(second).is_none());
+    }
+
+    #[test]
+    fn components_go_with_the_entity() {
+
+        let mut entities = EntityStore::new();
+        let cube = entities.spawn();
+        let sphere = entities.spawn();
+        entities.insert(cube, Transform::from_position(glam::f32::Vec3::X));
+        entities.insert(cube, Renderable::model("cube", "tree"));
+        entities.insert(sphere, Transform::from_position(glam::f32::Vec3::Y));
+
+        let with_transforms: Vec<EntityId> = entities.iter::<Transform>().map(|(id, _)| id).collect();
+        assert_eq!(with_transforms, vec![cube, sphere]);
+        assert_eq!(entities.iter::<Renderable>().count(), 1);
+
+        entities.get_mut::<Transform>(sphere).unwrap().position = glam::f32::Vec3::Z;
+        assert_eq!(entities.get::<Transform>(sphere).unwrap().position, glam::f32::Vec3::Z);
+
+        entities.despawn(cube);
+        assert_eq!(entities.iter::<Renderable>().count(), 0);
+        assert_eq!(entities.get_entity_count(), 1);
+        assert_eq!(entities.remove::<Transform>(sphere).unwrap().position, glam::f32::Vec3::Z);
+        assert!(entities.get::<Transform>(sphere).is_none());
+    }
+
+    #[test]
+    fn clients_can_mirror_server_ids() {
+
+        let mut server = EntityStore::new();
+        let mut client = EntityStore::new();
+
+        let ids: Vec<EntityId> = (0..4).map(|_| server.spawn()).collect();
+        server.despawn(ids[1]);
+        let respawned = server.spawn();
+
+        for id in [ids[0], ids[2], ids[3], respawned] {
+            assert!(client.spawn_at(id));
+        }
+        assert!(!client.spawn_at(ids[0]));
+        //Something the client already saw replaced
+        assert!(!client.spawn_at(ids[1]));
+
+        //Local spawns don't collide with mirrored ones
+        assert!(client.spawn().get_index() >= 4);
+        assert!(client.despawn(respawned));
+        assert!(client.spawn_at(EntityId::new(respawned.get_index(), respawned.get_generation() + 1)));
+    }
+}
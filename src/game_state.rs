@@ -1,35 +1,98 @@
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use std::time::Instant;
 
 use crate::camera;
+use crate::character_controller;
+use crate::chat;
+use crate::clock_sync;
 use crate::console::Console;
+use crate::damage;
+use crate::entity;
+use crate::hitbox;
 use crate::render_commands::*;
 use crate::collision;
 use crate::input::*;
+use crate::interpolation;
+use crate::lag_compensation;
+use crate::level;
+use crate::movement;
+use crate::net;
 use crate::player;
+use crate::prediction;
+use crate::rcon;
 use crate::resource_manager;
+use crate::states::{self, States};
+use crate::systems;
+use crate::user_cmd;
+use crate::validation;
+use crate::weapon;
 
-enum States {
-    Start,
-}
-
-const TICK_RATE: f32 = 16.66666;
-const TICK_RATE_SECONDS: f32 = TICK_RATE / 1000.0;
+pub const TICK_RATE: f32 = 16.66666;
+pub const TICK_RATE_SECONDS: f32 = TICK_RATE / 1000.0;
+//Where players start when the map doesn't say
+const DEFAULT_SPAWN_POINT: glam::f32::Vec3 = glam::f32::Vec3::new(0.0, 1.0, 4.0);
 
 pub struct GameState {
     current_state: States,
+    //Ticks since current_state was entered
+    state_ticks: u32,
+    round: u32,
+    //What to go back to when unpaused and how far into it we were
+    paused_state: Option<(States, u32)>,
+    state_settings: states::StateSettings,
     delta_time: f32,
     tick_time: f32,
+    //Milliseconds between ticks, clients run slightly fast or slow to keep their lead on the server
+    tick_interval: f32,
     current_tick: u32,
     current_time: std::time::SystemTime,
     camera: camera::Camera,
     render_commands: Vec<RenderCommands>,
-    sphere: collision::Sphere,
-    capsule: collision::Capsule,
+    //Everything in the world that isn't simulated by the netcode, the level and what players are driving
+    entities: entity::EntityStore,
+    level: Option<level::Level>,
     player: player::Player,
-    hit_areas: Vec<collision::Sphere>,
+    hit_areas: Vec<hitbox::Hitbox>,
     console: Rc<RefCell<Console>>,
+    //No local player and nothing to render, only simulates for connected clients
+    dedicated: bool,
+    network: Option<net::Network>,
+    //Newest commands for the local player, all of them go in every input packet
+    local_commands: VecDeque<user_cmd::UserCmd>,
+    //Host side, what the local player fired this tick and the tick they saw others at, traced with everyone else's shots
+    local_shot: Option<(weapon::Shot, f32)>,
+    prediction: prediction::Prediction,
+    //Server side players controlled by connected clients
+    remote_players: HashMap<u32, player::Player>,
+    //Client side, estimate of the server's tick
+    clock_sync: clock_sync::ClockSync,
+    //Server side, commands from each client waiting for their tick
+    command_buffers: HashMap<u32, user_cmd::UserCmdBuffer>,
+    //Server side, checks each client's commands for anything impossible
+    validators: HashMap<u32, validation::PlayerValidator>,
+    validation_settings: validation::ValidationSettings,
+    movement_settings: movement::MovementSettings,
+    //Client side, tick of the newest snapshot the server sent us
+    latest_snapshot_tick: Option<u32>,
+    //Client side, other players are shown a little in the past so they move smoothly
+    interpolation: interpolation::SnapshotInterpolator<net::PlayerSnapshot>,
+    //Server side, recent hitboxes of every player so shots can be checked against what the shooter saw
+    lag_compensation: lag_compensation::LagCompensation,
+    //Admin commands over TCP, run the same as ones typed into the console
+    rcon: Option<rcon::RconServer>,
+    //Answers LAN server browsers while hosting
+    discovery: Option<net::discovery::DiscoveryResponder>,
+    chat_log: chat::ChatLog,
+    //Server side, one per client
+    chat_flood_guards: HashMap<u32, chat::ChatFloodGuard>,
+    //Server side, hits from this tick waiting to be applied and sent out
+    pending_damage: Vec<damage::DamageEvent>,
+    //Server side, tick each dead player comes back on
+    respawn_ticks: HashMap<u32, u32>,
+    //The server's is the real one, clients get a copy whenever it changes
+    scoreboard: damage::Scoreboard,
 }
 
 impl GameState {
@@ -48,11 +111,262 @@ impl GameState {
             100.0
         );
 
-        let sphere = collision::Sphere::new(glam::f32::Vec3::new(-2.0, 0.0, 0.0), 1.0);
-        let capsule = collision::Capsule::new(glam::f32::Vec3::new(0.0, 1.0, 0.0), glam::f32::Vec3::new(0.0, 5.0, 0.0), 1.0);
-        let player = player::Player::new(glam::f32::Vec3::new(0.0, 1.0, 4.0), -90.0_f32.to_radians());
+        let player = player::Player::new(DEFAULT_SPAWN_POINT, -90.0_f32.to_radians());
+
+        let mut entities = entity::EntityStore::new();
+        let level = level::Level::load(resource_manager::DEFAULT_MAP, &mut entities);
+        let local_player = entities.spawn();
+        entities.insert(local_player, entity::Transform::from_position(*player.get_position()));
+        entities.insert(local_player, entity::PlayerControl::Local);
+
+        Self { current_state: States::MainMenu, state_ticks: 0, round: 0, paused_state: None, state_settings: states::StateSettings::default(), delta_time: 0.0, tick_time: 0.0, tick_interval: TICK_RATE, current_tick: 0, current_time, camera, render_commands: Vec::new(), entities, level, player, hit_areas: Vec::new(), console, dedicated: false, network: None, local_commands: VecDeque::new(), local_shot: None, prediction: prediction::Prediction::new(), remote_players: HashMap::new(), clock_sync: clock_sync::ClockSync::new(), command_buffers: HashMap::new(), validators: HashMap::new(), validation_settings: validation::ValidationSettings::default(), movement_settings: movement::MovementSettings::default(), latest_snapshot_tick: None, interpolation: interpolation::SnapshotInterpolator::new(interpolation::DEFAULT_INTERPOLATION_DELAY, interpolation::DEFAULT_MAX_EXTRAPOLATION), lag_compensation: lag_compensation::LagCompensation::new(), rcon: None, discovery: None, chat_log: chat::ChatLog::new(), chat_flood_guards: HashMap::new(), pending_damage: Vec::new(), respawn_ticks: HashMap::new(), scoreboard: damage::Scoreboard::new() }
+    }
+
+    pub fn new_dedicated(console: Rc<RefCell<Console>>) -> Self {
+
+        let mut game_state = Self::new(console);
+        game_state.dedicated = true;
+        //Nobody is sat at a dedicated server
+        let local_players: Vec<entity::EntityId> = game_state.entities.iter::<entity::PlayerControl>().filter(|(_, control)| **control == entity::PlayerControl::Local).map(|(id, _)| id).collect();
+        for id in local_players {
+            game_state.entities.despawn(id);
+        }
+
+        game_state
+    }
+
+    pub fn set_network(&mut self, mut network: net::Network) {
+
+        match &mut network {
+            net::Network::Server(server) => {
+                self.console.borrow_mut().output_to_console(&format!("Hosting server on {}", server.local_addr().unwrap()));
+                if !self.current_state.is_match_state() {
+                    self.set_state(States::Warmup, "hosting");
+                }
+            }
+            net::Network::Client(client) => {
+                //Joining from the server browser while already connected somewhere
+                if let Some(net::Network::Client(previous)) = self.network.as_mut() {
+                    let _ = previous.disconnect();
+                    self.remote_players.clear();
+                    self.forget_server();
+                }
+                self.console.borrow_mut().output_to_console(&format!("Connecting to {}", client.get_server_address()));
+                if let Err(e) = client.connect() {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send connect: {}", e));
+                }
+                self.set_state(States::Connecting, "connecting");
+            }
+        }
+
+        self.network = Some(network);
+    }
+
+    //Runs the exit hook of the current state and the enter hook of the new one, hosts tell their clients
+    pub fn set_state(&mut self, state: States, reason: &str) {
+
+        if state == self.current_state {
+            return;
+        }
+
+        let previous = self.current_state;
+        let previous_ticks = self.state_ticks;
+        self.console.borrow_mut().output_to_console(&format!("State {} -> {} ({})", previous.get_name(), state.get_name(), reason));
+
+        self.exit_state(previous);
+        self.current_state = state;
+        self.state_ticks = 0;
+        match self.paused_state.take() {
+            //Carry on where we left off rather than starting the state again
+            Some((paused, paused_ticks)) if previous == States::Paused && paused == state => self.state_ticks = paused_ticks,
+            _ => self.enter_state(state, previous, previous_ticks),
+        }
+
+        if let Some(net::Network::Server(server)) = self.network.as_mut() {
+            if state.is_match_state() {
+                if let Err(e) = server.broadcast_reliable(net::ReliableMessage::StateChange { state, round: self.round }) {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send state change: {}", e));
+                }
+            }
+        }
+    }
+
+    fn enter_state(&mut self, state: States, previous: States, previous_ticks: u32) {
+
+        match state {
+            States::MainMenu => {
+                //Leaving a game, hosts take their clients with them
+                match self.network.take() {
+                    Some(net::Network::Client(mut client)) => {
+                        let _ = client.disconnect();
+                    }
+                    Some(net::Network::Server(mut server)) => {
+                        for player_id in server.get_player_ids() {
+                            let _ = server.kick(player_id);
+                        }
+                    }
+                    None => {}
+                }
+                for player_id in self.remote_players.keys().copied().collect::<Vec<u32>>() {
+                    self.remove_remote_player(player_id);
+                }
+                self.forget_server();
+                self.round = 0;
+            }
+            States::Warmup => {
+                self.round = 0;
+            }
+            States::InRound => {
+                //Clients are told the round number by the server
+                if !matches!(self.network, Some(net::Network::Client(_))) {
+                    self.round += 1;
+                }
+                self.console.borrow_mut().output_to_console(&format!("Round {} of {} started", self.round, self.state_settings.rounds_per_match));
+            }
+            States::RoundEnd => {
+                self.console.borrow_mut().output_to_console(&format!("Round {} over", self.round));
+            }
+            States::MatchEnd => {
+                self.console.borrow_mut().output_to_console("Match over");
+            }
+            States::Paused => {
+                self.paused_state = Some((previous, previous_ticks));
+            }
+            States::Connecting | States::Loading => {}
+        }
+    }
+
+    //Nothing the last server told us or we predicted against it carries over to the next one
+    fn forget_server(&mut self) {
+
+        self.local_commands.clear();
+        self.latest_snapshot_tick = None;
+        self.interpolation.clear();
+        self.prediction = prediction::Prediction::new();
+        self.clock_sync = clock_sync::ClockSync::new();
+    }
+
+    fn exit_state(&mut self, state: States) {
+
+        match state {
+            //Shots from the last round shouldn't hang around
+            States::InRound | States::Warmup => {
+                self.hit_areas.clear();
+            }
+            //Whatever was held before the pause is stale
+            States::Paused => {
+                self.local_commands.clear();
+            }
+            _ => {}
+        }
+    }
+
+    //state [main_menu | warmup | in_round | round_end | match_end | pause | resume]
+    fn state_command(&mut self, arguments: &[&str]) {
+
+        let is_client = matches!(self.network, Some(net::Network::Client(_)));
+
+        match arguments {
+            [] => {}
+            ["pause"] if self.current_state.ticks_world() && !is_client => self.set_state(States::Paused, "console"),
+            ["resume"] if self.current_state == States::Paused && !is_client => {
+                if let Some((paused, _)) = self.paused_state {
+                    self.set_state(paused, "console");
+                }
+            }
+            ["pause"] | ["resume"] if !is_client => {
+                self.console.borrow_mut().output_to_console(&format!("state: can't {} while {}", arguments[0], self.current_state.get_name()));
+                return;
+            }
+            [name] => match States::from_name(name) {
+                //Leaving is always fine, everything else is up to the server
+                Some(States::MainMenu) => self.set_state(States::MainMenu, "console"),
+                Some(_) if is_client => {
+                    self.console.borrow_mut().output_to_console("state: only the server can change state");
+                    return;
+                }
+                Some(state @ (States::Warmup | States::InRound | States::RoundEnd | States::MatchEnd)) => self.set_state(state, "console"),
+                Some(state) => {
+                    self.console.borrow_mut().output_to_console(&format!("state: {} can't be set from the console", state.get_name()));
+                    return;
+                }
+                None => {
+                    self.console.borrow_mut().output_to_console(&format!("state: unknown state {}", name));
+                    return;
+                }
+            },
+            _ => {
+                self.console.borrow_mut().output_to_console("Usage: state [main_menu | warmup | in_round | round_end | match_end | pause | resume]");
+                return;
+            }
+        }
+
+        let mut status = format!("State: {}, round {} of {}", self.current_state.get_name(), self.round, self.state_settings.rounds_per_match);
+        if let Some(duration) = self.state_settings.get_duration_ticks(self.current_state) {
+            status += &format!(", {:.1}s left", duration.saturating_sub(self.state_ticks) as f32 * TICK_RATE_SECONDS);
+        }
+        self.console.borrow_mut().output_to_console(&status);
+    }
+
+    pub fn get_state(&self) -> States {
+
+        self.current_state
+    }
+
+    pub fn get_round(&self) -> u32 {
+
+        self.round
+    }
+
+    pub fn get_player(&self) -> &player::Player {
+
+        &self.player
+    }
+
+    //Server side, how the server has a client's player
+    pub fn get_remote_player(&self, player_id: u32) -> Option<&player::Player> {
+
+        self.remote_players.get(&player_id)
+    }
+
+    pub fn get_scoreboard(&self) -> &damage::Scoreboard {
+
+        &self.scoreboard
+    }
+
+    //Seconds until a timed state moves on
+    pub fn get_state_time_left(&self) -> Option<f32> {
+
+        self.state_settings.get_duration_ticks(self.current_state).map(|duration| duration.saturating_sub(self.state_ticks) as f32 * TICK_RATE_SECONDS)
+    }
+
+    pub fn get_mut_state_settings(&mut self) -> &mut states::StateSettings {
+
+        &mut self.state_settings
+    }
+
+    pub fn get_chat_log(&self) -> &chat::ChatLog {
+
+        &self.chat_log
+    }
+
+    pub fn set_discovery(&mut self, discovery: net::discovery::DiscoveryResponder) {
+
+        self.discovery = Some(discovery);
+    }
+
+    pub fn set_rcon(&mut self, rcon: rcon::RconServer) {
+
+        if let Ok(address) = rcon.local_addr() {
+            self.console.borrow_mut().output_to_console(&format!("RCON listening on {}", address));
+        }
+
+        self.rcon = Some(rcon);
+    }
 
-        Self { current_state: States::Start, delta_time: 0.0, tick_time: 0.0, current_tick: 0, current_time, camera, render_commands: Vec::new(), sphere, capsule, player, hit_areas: Vec::new(), console }
+    pub fn get_rcon(&self) -> Option<&rcon::RconServer> {
+
+        self.rcon.as_ref()
     }
 
     pub fn update(&mut self, inputs: &mut Inputs, resource_manager: &mut resource_manager::ResourceManager) {
@@ -60,12 +374,13 @@ impl GameState {
         self.delta_time = self.current_time.elapsed().unwrap().as_micros() as f32 / 1000.0;
         self.tick_time += self.delta_time;
         self.current_time = std::time::SystemTime::now();
-        while self.tick_time >= TICK_RATE {
+        while self.tick_time >= self.tick_interval {
             let start = Instant::now();
-            self.tick_time -= TICK_RATE;
+            self.tick_time -= self.tick_interval;
             self.begin_tick();
             self.tick(inputs, resource_manager);
             self.end_tick();
+            self.pump_network(resource_manager);
             self.current_tick += 1;
             inputs.end_tick_clean();
             let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
@@ -76,78 +391,821 @@ impl GameState {
     fn begin_tick(&mut self) {
 
         self.render_commands.clear();
+
+        let commands = self.console.borrow_mut().take_commands();
+        for command in commands {
+            self.run_command(&command);
+        }
+
+        if let Some(mut rcon) = self.rcon.take() {
+            for command in rcon.poll() {
+                self.console.borrow_mut().start_capture();
+                self.run_command(&command.command);
+                let output = self.console.borrow_mut().finish_capture();
+                rcon.send_output(command.connection_id, &output);
+            }
+            self.rcon = Some(rcon);
+        }
     }
 
-    fn tick(&mut self, inputs: &mut Inputs, resource_manager: &mut resource_manager::ResourceManager) {
+    pub fn run_command(&mut self, command: &str) {
 
-        match self.current_state {
-            States::Start => {
+        let arguments: Vec<&str> = command.split_whitespace().collect();
 
-            },
+        match arguments.first() {
+            Some(&"net_sim") => self.net_sim_command(&arguments[1..]),
+            Some(&"validation") => self.validation_command(&arguments[1..]),
+            Some(&"state") => self.state_command(&arguments[1..]),
+            Some(&"movement") => self.movement_command(&arguments[1..]),
+            Some(&"interpolation") => self.interpolation_command(&arguments[1..]),
+            Some(name) => self.console.borrow_mut().output_to_console(&format!("Unknown command: {}", name)),
+            None => {}
         }
+    }
+
+    //net_sim [off | latency ms | jitter ms | loss % | duplicate % | reorder % | seed n]
+    fn net_sim_command(&mut self, arguments: &[&str]) {
+
+        let Some(network) = self.network.as_mut() else {
+            self.console.borrow_mut().output_to_console("net_sim needs a network, use --host or --connect");
+            return;
+        };
+        let socket = network.get_mut_socket();
+
+        match arguments {
+            [] => {}
+            ["off"] => socket.set_simulator(None),
+            [setting, value] => {
+                let Ok(value) = value.parse::<f32>() else {
+                    self.console.borrow_mut().output_to_console(&format!("net_sim: {} is not a number", value));
+                    return;
+                };
+                if socket.get_simulator().is_none() {
+                    socket.set_simulator(Some(net::simulator::NetworkSimulator::new(net::simulator::NetworkConditions::default(), 0)));
+                }
+                let simulator = socket.get_mut_simulator().unwrap();
+                let mut conditions = *simulator.get_conditions();
+                match *setting {
+                    "latency" => conditions.latency = std::time::Duration::from_secs_f32(value.max(0.0) / 1000.0),
+                    "jitter" => conditions.jitter = std::time::Duration::from_secs_f32(value.max(0.0) / 1000.0),
+                    "loss" => conditions.loss = value / 100.0,
+                    "duplicate" => conditions.duplication = value / 100.0,
+                    "reorder" => conditions.reordering = value / 100.0,
+                    "seed" => simulator.set_seed(value as u64),
+                    _ => {
+                        self.console.borrow_mut().output_to_console(&format!("net_sim: unknown setting {}", setting));
+                        return;
+                    }
+                }
+                simulator.set_conditions(conditions);
+            }
+            _ => {
+                self.console.borrow_mut().output_to_console("Usage: net_sim [off | latency ms | jitter ms | loss % | duplicate % | reorder % | seed n]");
+                return;
+            }
+        }
+
+        let status = match socket.get_simulator() {
+            Some(simulator) => {
+                let conditions = simulator.get_conditions();
+                format!("Network simulator: latency {}ms, jitter {}ms, loss {}%, duplicate {}%, reorder {}%, seed {}", conditions.latency.as_millis(), conditions.jitter.as_millis(), conditions.loss * 100.0, conditions.duplication * 100.0, conditions.reordering * 100.0, simulator.get_seed())
+            }
+            None => "Network simulator off".to_string(),
+        };
+        self.console.borrow_mut().output_to_console(&status);
+    }
+
+    //validation [max_speed u/s | max_acceleration u/s^2 | max_teleport u | max_turn_rate deg/s | kick kind count|off]
+    fn validation_command(&mut self, arguments: &[&str]) {
 
-        let mut input_vector = glam::f32::Vec3::ZERO;
-        if inputs.check_key_down(RIGHT) {
-            input_vector += glam::f32::Vec3::X;
+        let settings = &mut self.validation_settings;
+
+        match arguments {
+            [] => {}
+            ["kick", kind, threshold] => {
+                let Some(kind) = validation::ViolationKind::from_name(kind) else {
+                    self.console.borrow_mut().output_to_console(&format!("validation: unknown check {}", kind));
+                    return;
+                };
+                let threshold = match *threshold {
+                    "off" => None,
+                    threshold => match threshold.parse::<u32>() {
+                        Ok(threshold) => Some(threshold),
+                        Err(_) => {
+                            self.console.borrow_mut().output_to_console(&format!("validation: {} is not a number", threshold));
+                            return;
+                        }
+                    },
+                };
+                settings.set_kick_threshold(kind, threshold);
+            }
+            [setting, value] => {
+                let Ok(value) = value.parse::<f32>() else {
+                    self.console.borrow_mut().output_to_console(&format!("validation: {} is not a number", value));
+                    return;
+                };
+                match *setting {
+                    "max_speed" => settings.max_speed = value,
+                    "max_acceleration" => settings.max_acceleration = value,
+                    "max_teleport" => settings.max_teleport_distance = value,
+                    "max_turn_rate" => settings.max_turn_rate = value.to_radians(),
+                    _ => {
+                        self.console.borrow_mut().output_to_console(&format!("validation: unknown setting {}", setting));
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.console.borrow_mut().output_to_console("Usage: validation [max_speed u/s | max_acceleration u/s^2 | max_teleport u | max_turn_rate deg/s | kick check count|off]");
+                return;
+            }
         }
-        if inputs.check_key_down(LEFT) {
-            input_vector += glam::f32::Vec3::NEG_X;
+
+        let settings = &self.validation_settings;
+        let thresholds: Vec<String> = validation::ViolationKind::ALL.iter().map(|kind| format!("{} {}", kind.get_name(), settings.get_kick_threshold(*kind).map_or("off".to_string(), |threshold| threshold.to_string()))).collect();
+        let mut status = format!("Validation: max speed {}, max acceleration {}, max teleport {}, max turn rate {} deg/s\nKick after: {}", settings.max_speed, settings.max_acceleration, settings.max_teleport_distance, settings.max_turn_rate.to_degrees(), thresholds.join(", "));
+        let mut player_ids: Vec<&u32> = self.validators.keys().collect();
+        player_ids.sort();
+        for player_id in player_ids {
+            let validator = &self.validators[player_id];
+            let counts: Vec<String> = validation::ViolationKind::ALL.iter().filter(|kind| validator.get_count(**kind) > 0).map(|kind| format!("{} {}", kind.get_name(), validator.get_count(*kind))).collect();
+            status += &format!("\nPlayer {}: {} violations {}", player_id, validator.get_total_count(), counts.join(", "));
         }
-        if inputs.check_key_down(FORWARD) {
-            input_vector += glam::f32::Vec3::Z;
+        self.console.borrow_mut().output_to_console(&status);
+    }
+
+    //movement [setting value], settings are max_speed, accelerate, air_accelerate, air_speed_cap, friction, stop_speed, jump_impulse
+    fn movement_command(&mut self, arguments: &[&str]) {
+
+        match arguments {
+            [] => {}
+            [setting, value] => {
+                if matches!(self.network, Some(net::Network::Client(_))) {
+                    self.console.borrow_mut().output_to_console("movement: only the server can change movement settings");
+                    return;
+                }
+                let Ok(value) = value.parse::<f32>() else {
+                    self.console.borrow_mut().output_to_console(&format!("movement: {} is not a number", value));
+                    return;
+                };
+                if !self.movement_settings.set(setting, value) {
+                    self.console.borrow_mut().output_to_console(&format!("movement: unknown setting {}", setting));
+                    return;
+                }
+                if let Some(net::Network::Server(server)) = self.network.as_mut() {
+                    if let Err(e) = server.broadcast_reliable(net::ReliableMessage::MovementSettings(self.movement_settings)) {
+                        self.console.borrow_mut().output_to_console(&format!("Failed to send movement settings: {}", e));
+                    }
+                }
+            }
+            _ => {
+                self.console.borrow_mut().output_to_console(&format!("Usage: movement [{} value]", movement::MovementSettings::NAMES.join(" | ")));
+                return;
+            }
         }
-        if inputs.check_key_down(BACKWARD) {
-            input_vector += glam::f32::Vec3::NEG_Z;
+
+        let settings: Vec<String> = movement::MovementSettings::NAMES.iter().map(|name| format!("{} {}", name, self.movement_settings.get(name).unwrap())).collect();
+        self.console.borrow_mut().output_to_console(&format!("Movement: {}", settings.join(", ")));
+    }
+
+    //interpolation [delay ticks | max_extrapolation ticks], how far behind the server remote players are drawn
+    fn interpolation_command(&mut self, arguments: &[&str]) {
+
+        match arguments {
+            [] => {}
+            [setting, value] => {
+                let Ok(value) = value.parse::<f32>() else {
+                    self.console.borrow_mut().output_to_console(&format!("interpolation: {} is not a number", value));
+                    return;
+                };
+                match *setting {
+                    "delay" => self.interpolation.set_interpolation_delay(value),
+                    "max_extrapolation" => self.interpolation.set_max_extrapolation(value),
+                    _ => {
+                        self.console.borrow_mut().output_to_console(&format!("interpolation: unknown setting {}", setting));
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.console.borrow_mut().output_to_console("Usage: interpolation [delay ticks | max_extrapolation ticks]");
+                return;
+            }
         }
-        if inputs.check_key_down(UP) {
-            input_vector += glam::f32::Vec3::Y;
+
+        self.console.borrow_mut().output_to_console(&format!("Interpolation: delay {} ticks, max extrapolation {} ticks", self.interpolation.get_interpolation_delay(), self.interpolation.get_max_extrapolation()));
+    }
+
+    fn tick(&mut self, inputs: &mut Inputs, resource_manager: &mut resource_manager::ResourceManager) {
+
+        //Timed states move on by themselves, clients wait for the server to tell them
+        self.state_ticks += 1;
+        if !matches!(self.network, Some(net::Network::Client(_))) {
+            let expired = self.state_settings.get_duration_ticks(self.current_state).is_some_and(|duration| self.state_ticks >= duration);
+            if let Some(next_state) = self.state_settings.get_next_state(self.current_state, self.round).filter(|_| expired) {
+                self.set_state(next_state, "timer");
+            }
         }
-        if inputs.check_key_down(DOWN) {
-            input_vector += glam::f32::Vec3::NEG_Y;
+
+        if self.current_state.ticks_world() {
+            if !self.dedicated {
+                self.prediction.decay_correction();
+                self.local_input(inputs, &resource_manager.get_collisions());
+            }
+
+            let world = resource_manager.get_collisions();
+            systems::spin(&mut self.entities, self.current_tick);
+            systems::fall(&mut self.entities, &world);
+            let bodies = self.get_bodies();
+            let explosions = systems::fly_projectiles(&mut self.entities, &world, &bodies);
+            //Offline and on clients they're just for show
+            if matches!(self.network, Some(net::Network::Server(_))) {
+                for explosion in explosions {
+                    self.explode(&explosion, &world);
+                }
+            }
+            systems::follow_players(&mut self.entities, |control| match control {
+                entity::PlayerControl::Local => Some(&self.player),
+                entity::PlayerControl::Remote(player_id) => self.remote_players.get(&player_id),
+            });
         }
-        self.player.input(inputs);
-        self.player.translate_relative(input_vector * TICK_RATE_SECONDS * 4.0);
 
-        let t = self.capsule.vs_while_moving_triangle_soup(&(glam::f32::Vec3::NEG_Y * 2.0), resource_manager.get_model(&"test_triangle".to_string()).unwrap().get_collision());
-        if t.collided {
-            self.capsule.set_center(self.capsule.get_center() + (glam::f32::Vec3::NEG_Y * 2.0) * t.penetration_or_time + t.normal * std::f32::EPSILON);
-            //println!("Collision on tick {}", self.current_tick);
+        if self.dedicated || !self.current_state.renders_world() {
+            return;
         }
 
         //Render area of game
-        self.camera.update_from_player(&self.player);
+        self.camera.update_from_player(&self.player, self.prediction.get_correction_offset());
         self.render_commands.push(RenderCommands::Camera(self.camera.build_projection_matrix().to_cols_array_2d()));
 
-        //T * R * S
-        {
-            let rotation = glam::f32::Mat4::from_euler(glam::EulerRot::XYZ, self.current_tick as f32 / 10.0, self.current_tick as f32 / 10.0, 0.0);
-            let translation = glam::f32::Mat4::from_translation(glam::f32::Vec3::new((self.current_tick as f32 / 20.0).sin() * 2.0, 7.0, 0.0));
-            let transform = translation * rotation;
-            self.render_commands.push(RenderCommands::Model(ModelRenderCommand::new(transform, "cube", "tree")));
+        systems::animate(&self.entities, resource_manager);
+        systems::render(&self.entities, &mut self.render_commands);
+
+        self.interpolation.advance(1.0);
+        for player_snapshot in self.interpolation.sample() {
+            render_remote_player(&player_snapshot.position, &mut self.render_commands);
         }
-        {
-            let rotation = glam::f32::Mat4::from_euler(glam::EulerRot::XYZ, self.current_tick as f32 / 12.0, self.current_tick as f32 / 40.0, 0.0);
-            let translation = glam::f32::Mat4::from_translation(glam::f32::Vec3::new((self.current_tick as f32 / 10.0).sin() * 1.5, 10.0, 0.0));
-            let transform = translation * rotation;
-            self.render_commands.push(RenderCommands::Model(ModelRenderCommand::new(transform, "cube", "tree")));
+
+        for hitbox in &self.hit_areas {
+            hitbox.render(&mut self.render_commands);
         }
 
-        self.render_commands.push(RenderCommands::Model(ModelRenderCommand::new(glam::f32::Mat4::IDENTITY, "test_triangle", "debug")));
+        self.render_commands.push(RenderCommands::Quad(glam::f32::Vec3::new(-0.005, -0.005, 0.0), glam::f32::Vec3::new(0.005, 0.005, 0.0), "dot_crosshair".to_string()));
+    }
 
-        self.sphere.render(&mut self.render_commands);
+    fn local_input(&mut self, inputs: &mut Inputs, world: &[&collision::TriangleSoup]) {
+
+        self.player.input(inputs);
 
-        self.capsule.render(&mut self.render_commands);
+        //Clients see other players in the past, the host sees them as they were after the last tick
+        let view_tick = match &self.network {
+            Some(net::Network::Client(_)) => self.interpolation.get_render_tick().unwrap_or(0.0),
+            _ => self.current_tick.saturating_sub(1) as f32,
+        };
+        let mut command = user_cmd::UserCmd::from_inputs(self.current_tick, inputs, self.player.get_yaw(), self.player.get_pitch(), self.player.get_weapons().get_current(), view_tick);
+        restrict_command(self.current_state, &mut command);
+        self.local_shot = self.prediction.predict(&mut self.player, command, &self.movement_settings, world).map(|shot| (shot, view_tick));
 
-        for sphere in &self.hit_areas {
-            sphere.render(&mut self.render_commands);
+        self.local_commands.push_back(command);
+        while self.local_commands.len() > user_cmd::USER_CMD_REDUNDANCY {
+            self.local_commands.pop_front();
         }
+    }
 
-        let roll_model_matrix = glam::f32::Mat4::from_scale_rotation_translation(glam::f32::Vec3::new(0.1, 0.1, 0.1), glam::f32::Quat::from_rotation_x(90.0_f32.to_radians()), glam::f32::Vec3::new(2.0, 0.0, 0.0));
+    fn pump_network(&mut self, resource_manager: &resource_manager::ResourceManager) {
 
-        resource_manager.get_mut_skeleton_model(&"Roll_Caskett".to_string()).unwrap().update_skeleton(TICK_RATE_SECONDS);
-        self.render_commands.push(RenderCommands::SkeletonModel(SkeletonModelRenderCommand::new(roll_model_matrix, "Roll_Caskett", "Roll_Caskett")));
+        let Some(mut network) = self.network.take() else {
+            //Nobody to hit offline but rockets and grenades still fly
+            if let Some((shot, _)) = self.local_shot.take().filter(|(shot, _)| shot.get_definition().projectile.is_some()) {
+                self.launch_projectile(net::HOST_PLAYER_ID, shot.weapon, shot.origin, shot.direction);
+            }
+            return;
+        };
 
-        self.render_commands.push(RenderCommands::Quad(glam::f32::Vec3::new(-0.005, -0.005, 0.0), glam::f32::Vec3::new(0.005, 0.005, 0.0), "dot_crosshair".to_string()));
+        match &mut network {
+            net::Network::Server(server) => {
+                for (player_id, packet) in server.receive() {
+                    match packet {
+                        net::Packet::Accepted { .. } => {
+                            let team = self.get_smallest_team();
+                            let mut remote_player = player::Player::new(self.choose_spawn_point(team), -90.0_f32.to_radians());
+                            remote_player.set_team(team);
+                            self.console.borrow_mut().output_to_console(&format!("Player {} connected on team {}", player_id, remote_player.get_team().get_name()));
+                            let entity_id = self.entities.spawn();
+                            self.entities.insert(entity_id, entity::Transform::from_position(*remote_player.get_position()));
+                            self.entities.insert(entity_id, entity::Collider::new(entity::ColliderShape::Capsule(character_controller::get_player_capsule()), false));
+                            self.entities.insert(entity_id, entity::Renderable::Collider);
+                            self.entities.insert(entity_id, entity::PlayerControl::Remote(player_id));
+                            self.remote_players.insert(player_id, remote_player);
+                            self.chat_flood_guards.insert(player_id, chat::ChatFloodGuard::new());
+                            self.command_buffers.insert(player_id, user_cmd::UserCmdBuffer::new());
+                            self.validators.insert(player_id, validation::PlayerValidator::new());
+                            if let Err(e) = server.send_reliable(player_id, net::ReliableMessage::StateChange { state: self.current_state, round: self.round }) {
+                                self.console.borrow_mut().output_to_console(&format!("Failed to send state to player {}: {}", player_id, e));
+                            }
+                            if let Err(e) = server.send_reliable(player_id, net::ReliableMessage::MovementSettings(self.movement_settings)) {
+                                self.console.borrow_mut().output_to_console(&format!("Failed to send movement settings to player {}: {}", player_id, e));
+                            }
+                            if !self.dedicated {
+                                self.scoreboard.add_player(net::HOST_PLAYER_ID);
+                            }
+                            self.scoreboard.add_player(player_id);
+                            if let Err(e) = server.broadcast_reliable(net::ReliableMessage::Scoreboard(self.scoreboard.clone())) {
+                                self.console.borrow_mut().output_to_console(&format!("Failed to send scoreboard: {}", e));
+                            }
+                        }
+                        net::Packet::Disconnect(reason) => {
+                            self.console.borrow_mut().output_to_console(&format!("Player {} disconnected: {:?}", player_id, reason));
+                            self.remove_remote_player(player_id);
+                        }
+                        net::Packet::ClockRequest { client_time } => {
+                            let response = net::Packet::ClockResponse { client_time, server_tick: self.get_fractional_tick() };
+                            if let Err(e) = server.send(player_id, &response) {
+                                self.console.borrow_mut().output_to_console(&format!("Failed to send clock response to player {}: {}", player_id, e));
+                            }
+                        }
+                        net::Packet::Input { commands, .. } => {
+                            if let Some(command_buffer) = self.command_buffers.get_mut(&player_id) {
+                                for command in commands {
+                                    command_buffer.insert(command);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                if let (Some(discovery), Ok(address)) = (self.discovery.as_mut(), server.local_addr()) {
+                    //A listen server's host is a player too
+                    let host_players = if self.dedicated { 0 } else { 1 };
+                    discovery.poll((server.get_client_count() + host_players) as u32, (server.get_max_clients() + host_players) as u32, address.port());
+                }
+
+                for (player_id, message) in server.receive_reliable() {
+                    match message {
+                        //Never trust who a client says a message is from
+                        net::ReliableMessage::Chat { channel, text, .. } => self.relay_chat(server, player_id, channel, &text),
+                        //Only the server decides, clients can't change the map or run commands on it either
+                        net::ReliableMessage::ConsoleCommand(_) | net::ReliableMessage::MapChange(_) | net::ReliableMessage::StateChange { .. } | net::ReliableMessage::MovementSettings(_) | net::ReliableMessage::KillFeed { .. } | net::ReliableMessage::Damage(_) | net::ReliableMessage::Respawn { .. } | net::ReliableMessage::Scoreboard(_) | net::ReliableMessage::ProjectileLaunch { .. } => {}
+                    }
+                }
+
+                let world = resource_manager.get_collisions();
+
+                let mut shots = Vec::new();
+                let mut violations = Vec::new();
+                //Nobody moves while the world is stopped, the host's own input is held back the same way in tick
+                if self.current_state.ticks_world() {
+                    //Exactly one command per player per tick, however many turned up
+                    for (player_id, command_buffer) in self.command_buffers.iter_mut() {
+                        let (Some(mut command), Some(remote_player), Some(validator)) = (command_buffer.take_next(), self.remote_players.get_mut(player_id), self.validators.get_mut(player_id)) else {
+                            continue;
+                        };
+
+                        let mut player_violations = validator.check_command(&self.validation_settings, &mut command);
+                        restrict_command(self.current_state, &mut command);
+                        let previous_position = *remote_player.get_position();
+                        let shot = remote_player.simulate(&command, &self.movement_settings, &world);
+                        let shot_violations = shot.as_ref().map(|shot| validator.check_shot(shot, &world)).unwrap_or_default();
+                        let shot = shot.filter(|_| shot_violations.is_empty());
+                        player_violations.extend(shot_violations);
+                        let movement_violations = validator.check_movement(&self.validation_settings, previous_position, remote_player);
+                        if !movement_violations.is_empty() {
+                            remote_player.set_position(previous_position);
+                        }
+                        player_violations.extend(movement_violations);
+                        violations.push((*player_id, player_violations));
+
+                        if let Some(shot) = shot {
+                            shots.push((*player_id, shot, command.view_tick));
+                        }
+                    }
+                    if let Some((shot, view_tick)) = self.local_shot.take() {
+                        shots.push((net::HOST_PLAYER_ID, shot, view_tick));
+                    }
+                }
+                for (shooter_id, shot, view_tick) in shots {
+                    if shot.get_definition().projectile.is_some() {
+                        self.launch_projectile(shooter_id, shot.weapon, shot.origin, shot.direction);
+                        if let Err(e) = server.broadcast_reliable(net::ReliableMessage::ProjectileLaunch { owner: shooter_id, weapon: shot.weapon, origin: shot.origin, direction: shot.direction }) {
+                            self.console.borrow_mut().output_to_console(&format!("Failed to send projectile from player {}: {}", shooter_id, e));
+                        }
+                        continue;
+                    }
+                    self.fire_hitscan(shooter_id, &shot, view_tick, &world);
+                }
+                self.apply_damage(server);
+                self.respawn_players(server);
+
+                for (player_id, player_violations) in violations {
+                    let Some(validator) = self.validators.get_mut(&player_id) else {
+                        continue;
+                    };
+                    let kick = validator.record(&self.validation_settings, &player_violations);
+                    for violation in &player_violations {
+                        self.console.borrow_mut().output_to_console(&format!("Player {} failed {} check: {:.1} against a limit of {:.1} ({} times)", player_id, violation.kind.get_name(), violation.value, violation.limit, validator.get_count(violation.kind)));
+                    }
+                    if let Some(kind) = kick {
+                        self.console.borrow_mut().output_to_console(&format!("Kicking player {} for failing {} check too often", player_id, kind.get_name()));
+                        if let Err(e) = server.kick(player_id) {
+                            self.console.borrow_mut().output_to_console(&format!("Failed to kick player {}: {}", player_id, e));
+                        }
+                        self.remove_remote_player(player_id);
+                    }
+                }
+
+                //Each player is posed from their own time in the model's first animation, the dead can't be shot
+                let skeleton = resource_manager.get_skeleton(hitbox::PLAYER_MODEL);
+                let get_hitboxes = |player: &player::Player| {
+                    let pose = skeleton.and_then(|skeleton| skeleton.get_animations().first().map(|animation| skeleton.get_pose(animation, player.get_animation_time()))).unwrap_or_default();
+                    player.get_hitboxes(|name| skeleton.and_then(|skeleton| skeleton.get_bone_matrix(&pose, name)))
+                };
+                let mut hitboxes = Vec::new();
+                if !self.dedicated && !self.player.get_health().is_dead() {
+                    hitboxes.push((net::HOST_PLAYER_ID, get_hitboxes(&self.player)));
+                }
+                for (id, remote_player) in self.remote_players.iter().filter(|(_, remote_player)| !remote_player.get_health().is_dead()) {
+                    hitboxes.push((*id, get_hitboxes(remote_player)));
+                }
+                self.lag_compensation.record(self.current_tick, hitboxes);
+
+                let mut players = Vec::new();
+                if !self.dedicated {
+                    players.push(net::PlayerSnapshot { id: net::HOST_PLAYER_ID, position: *self.player.get_position(), yaw: self.player.get_yaw(), pitch: self.player.get_pitch() });
+                }
+                for (id, remote_player) in &self.remote_players {
+                    players.push(net::PlayerSnapshot { id: *id, position: *remote_player.get_position(), yaw: remote_player.get_yaw(), pitch: remote_player.get_pitch() });
+                }
+
+                server.update();
+
+                for player_id in server.get_player_ids() {
+                    //Each client hears about nearby players it can see more often than ones far away or behind walls
+                    let viewer = self.remote_players.get(&player_id).map(|remote_player| *remote_player.get_position());
+                    let relevances: Vec<f32> = players.iter().map(|player_snapshot| match viewer {
+                        Some(viewer) if player_snapshot.id != player_id => net::interest::get_relevance(viewer, player_snapshot.position, &world),
+                        _ => net::interest::ALWAYS_RELEVANT,
+                    }).collect();
+                    let input_tick = self.command_buffers.get(&player_id).and_then(|command_buffer| command_buffer.get_last_tick());
+                    if let Err(e) = server.send_prioritized_snapshot(player_id, self.current_tick, input_tick, self.remote_players.get(&player_id).map(|player| *player.get_weapons()), &players, &relevances) {
+                        self.console.borrow_mut().output_to_console(&format!("Failed to send snapshot to player {}: {}", player_id, e));
+                    }
+                }
+            }
+            net::Network::Client(client) => {
+                let previous_state = client.get_state();
+
+                for mut snapshot in client.receive() {
+                    let own_index = snapshot.players.iter().position(|player_snapshot| player_snapshot.id == snapshot.player_id);
+                    let own_state = own_index.map(|index| snapshot.players.remove(index));
+
+                    //Late snapshots can still fill a gap in the interpolation buffer
+                    self.interpolation.insert(snapshot.tick, snapshot.players);
+
+                    //But only the newest one is worth reconciling against
+                    if self.latest_snapshot_tick.is_some_and(|latest_tick| snapshot.tick <= latest_tick) {
+                        continue;
+                    }
+                    self.latest_snapshot_tick = Some(snapshot.tick);
+
+                    if let (Some(input_tick), Some(own_state)) = (snapshot.input_tick, own_state) {
+                        self.prediction.reconcile(&mut self.player, input_tick, own_state.position, snapshot.weapons, &self.movement_settings, &resource_manager.get_collisions());
+                    }
+                }
+
+                let own_player_id = client.get_player_id().unwrap_or(net::HOST_PLAYER_ID);
+                for message in client.receive_reliable() {
+                    self.handle_reliable_message(message, own_player_id);
+                }
+
+                for response in client.take_clock_responses() {
+                    self.clock_sync.add_sample(response.client_time, response.server_tick, response.received);
+                }
+                let now = Instant::now();
+                let jump = self.clock_sync.take_tick_jump(self.get_fractional_tick(), now);
+                if jump != 0 {
+                    self.console.borrow_mut().output_to_console(&format!("Clock sync moved tick {} by {}", self.current_tick, jump));
+                    self.current_tick = (self.current_tick as i64 + jump).max(0) as u32;
+                    //Their ticks mean nothing to the server now
+                    self.local_commands.clear();
+                }
+                self.tick_interval = TICK_RATE * self.clock_sync.get_tick_interval_scale(self.get_fractional_tick(), now) as f32;
+
+                client.update();
+                if client.get_state() != previous_state {
+                    self.console.borrow_mut().output_to_console(&format!("Connection state: {:?}", client.get_state()));
+                    if client.is_connected() {
+                        self.clock_sync.reset();
+                    }
+                }
+
+                //Nothing to send until the server has let us in
+                if client.is_connected() {
+                    if let Some(client_time) = self.clock_sync.request(now) {
+                        if let Err(e) = client.send(&net::Packet::ClockRequest { client_time }) {
+                            self.console.borrow_mut().output_to_console(&format!("Failed to send clock request: {}", e));
+                        }
+                    }
+                }
+
+                //Inputs are numbered by tick so hold them back until we know roughly which tick the server is on
+                if client.is_connected() && self.clock_sync.is_synced() {
+                    let input = net::Packet::Input { snapshot_ack: client.get_snapshot_ack(), commands: self.local_commands.iter().copied().collect() };
+                    if let Err(e) = client.send(&input) {
+                        self.console.borrow_mut().output_to_console(&format!("Failed to send input: {}", e));
+                    }
+                }
+            }
+        }
+
+        self.network = Some(network);
+
+        //Follow the connection, the server says what comes after loading
+        if let Some(net::Network::Client(client)) = self.network.as_ref() {
+            match client.get_state() {
+                net::ConnectionState::Connected { .. } if self.current_state == States::Connecting => self.set_state(States::Loading, "connected"),
+                net::ConnectionState::Disconnected(reason) => self.set_state(States::MainMenu, &format!("disconnected: {:?}", reason)),
+                _ => {}
+            }
+        }
+    }
+
+    //Sends a line of chat from the local player, hosts hand it straight to relay_chat
+    pub fn send_chat(&mut self, channel: chat::ChatChannel, text: &str) {
+
+        let Some(text) = chat::sanitize_chat(text) else {
+            return;
+        };
+
+        match self.network.take() {
+            Some(net::Network::Server(mut server)) => {
+                self.relay_chat(&mut server, net::HOST_PLAYER_ID, channel, &text);
+                self.network = Some(net::Network::Server(server));
+            }
+            Some(net::Network::Client(mut client)) => {
+                //The server fills in who it's from
+                if let Err(e) = client.send_reliable(net::ReliableMessage::Chat { player_id: 0, channel, text }) {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send chat: {}", e));
+                }
+                self.network = Some(net::Network::Client(client));
+            }
+            None => self.chat_log.push(chat::ChatLine { player_id: net::HOST_PLAYER_ID, channel, text, received: Instant::now() }),
+        }
+    }
+
+    //Server side, checks a line of chat and passes it on to everyone who should see it
+    fn relay_chat(&mut self, server: &mut net::NetServer, player_id: u32, channel: chat::ChatChannel, text: &str) {
+
+        //Only the server gets to talk on the server channel
+        let channel = if channel == chat::ChatChannel::Server { chat::ChatChannel::All } else { channel };
+        let Some(text) = chat::sanitize_chat(text) else {
+            return;
+        };
+
+        let team = if player_id == net::HOST_PLAYER_ID {
+            self.player.get_team()
+        }
+        else {
+            let (Some(remote_player), Some(flood_guard)) = (self.remote_players.get(&player_id), self.chat_flood_guards.get_mut(&player_id)) else {
+                return;
+            };
+            if !flood_guard.allow(Instant::now()) {
+                self.console.borrow_mut().output_to_console(&format!("Dropped chat from player {}, sending too fast: {}", player_id, text));
+                let warning = net::ReliableMessage::Chat { player_id: net::HOST_PLAYER_ID, channel: chat::ChatChannel::Server, text: "You are sending messages too fast".to_string() };
+                if let Err(e) = server.send_reliable(player_id, warning) {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send chat to player {}: {}", player_id, e));
+                }
+                return;
+            }
+            remote_player.get_team()
+        };
+
+        let line = chat::ChatLine { player_id, channel, text, received: Instant::now() };
+        self.console.borrow_mut().output_to_console(&format!("Chat [{}] {}", team.get_name(), line.get_display_text()));
+
+        let message = net::ReliableMessage::Chat { player_id, channel, text: line.text.clone() };
+        for (recipient_id, recipient) in self.remote_players.iter() {
+            if channel == chat::ChatChannel::Team && recipient.get_team() != team {
+                continue;
+            }
+            if let Err(e) = server.send_reliable(*recipient_id, message.clone()) {
+                self.console.borrow_mut().output_to_console(&format!("Failed to send chat to player {}: {}", recipient_id, e));
+            }
+        }
+
+        if !self.dedicated && (channel == chat::ChatChannel::All || self.player.get_team() == team) {
+            self.chat_log.push(line);
+        }
+    }
+
+    //Server side, new players go on whichever team is short
+    fn get_smallest_team(&self) -> player::Team {
+
+        let mut teams: Vec<player::Team> = self.remote_players.values().map(|remote_player| remote_player.get_team()).collect();
+        if !self.dedicated {
+            teams.push(self.player.get_team());
+        }
+        let red_count = teams.iter().filter(|team| **team == player::Team::Red).count();
+
+        if red_count * 2 > teams.len() { player::Team::Blue } else { player::Team::Red }
+    }
+
+    //Own player id is whichever player this game is driving, the host's on a server
+    fn handle_reliable_message(&mut self, message: net::ReliableMessage, own_player_id: u32) {
+
+        let text = match message {
+            net::ReliableMessage::Chat { player_id, channel, text } => {
+                let line = chat::ChatLine { player_id, channel, text, received: Instant::now() };
+                let text = line.get_display_text();
+                self.chat_log.push(line);
+                text
+            }
+            net::ReliableMessage::ConsoleCommand(command) => format!("Remote command: {}", command),
+            net::ReliableMessage::MapChange(map) => {
+                if let Some(level) = self.level.take() {
+                    level.unload(&mut self.entities);
+                }
+                self.level = level::Level::load(&map, &mut self.entities);
+                if self.level.is_some() { format!("Changing map to {}", map) } else { format!("Unknown map {}", map) }
+            }
+            net::ReliableMessage::KillFeed { attacker, victim, weapon } if attacker == victim => format!("Player {} killed themselves with the {}", victim, weapon::WEAPONS[weapon].name),
+            net::ReliableMessage::KillFeed { attacker, victim, weapon } => format!("Player {} killed player {} with the {}", attacker, victim, weapon::WEAPONS[weapon].name),
+            net::ReliableMessage::StateChange { state, round } => {
+                self.set_state(state, "server");
+                self.round = round;
+                return;
+            }
+            net::ReliableMessage::MovementSettings(settings) => {
+                self.movement_settings = settings;
+                return;
+            }
+            net::ReliableMessage::Damage(event) if event.victim == own_player_id => {
+                let lost = self.player.get_mut_health().take_damage(event.damage);
+                format!("Player {} hit you in {} with the {} for {:.0}, {:.0} health left", event.attacker, event.location.get_name(), weapon::WEAPONS[event.weapon].name, lost, self.player.get_health().get_health())
+            }
+            net::ReliableMessage::Damage(event) if event.attacker == own_player_id => format!("You hit player {} in {} for {:.0}", event.victim, event.location.get_name(), event.damage),
+            net::ReliableMessage::Damage(_) => return,
+            net::ReliableMessage::Respawn { player_id, position } if player_id == own_player_id => {
+                self.player.respawn(position);
+                "Respawned".to_string()
+            }
+            net::ReliableMessage::Respawn { .. } => return,
+            net::ReliableMessage::Scoreboard(scoreboard) => {
+                self.scoreboard = scoreboard;
+                return;
+            }
+            net::ReliableMessage::ProjectileLaunch { owner, weapon, origin, direction } => {
+                self.launch_projectile(owner, weapon, origin, direction);
+                return;
+            }
+        };
+
+        self.console.borrow_mut().output_to_console(&text);
+    }
+
+    //Server side, checks a shot against hitboxes rewound to the tick the shooter was seeing
+    //A wall in the way of someone behind it is just cover, shots from the wrong side of one are caught by PlayerValidator::check_shot
+    fn fire_hitscan(&mut self, shooter_id: u32, shot: &weapon::Shot, view_tick: f32, world: &[&collision::TriangleSoup]) {
+
+        let ray = shot.get_ray();
+
+        //Show what the shot was actually tested against
+        self.hit_areas = self.lag_compensation.rewind(view_tick).into_iter().filter(|(player_id, _)| *player_id != shooter_id).flat_map(|(_, hitboxes)| hitboxes).collect();
+
+        let player_hit = self.lag_compensation.trace(&ray, view_tick, shooter_id);
+        if let Some(weapon::ShotHit::Player(hit)) = weapon::trace(&ray, world, player_hit) {
+            self.pending_damage.push(damage::DamageEvent { attacker: shooter_id, victim: hit.player_id, weapon: shot.weapon, location: damage::HitLocation::Body(hit.region), damage: shot.get_definition().damage * hit.region.get_damage_multiplier() });
+        }
+    }
+
+    //Projectiles aren't lag compensated, they're slow enough to dodge
+    fn launch_projectile(&mut self, shooter_id: u32, weapon: usize, origin: glam::f32::Vec3, direction: glam::f32::Vec3) {
+
+        let Some(definition) = weapon::WEAPONS.get(weapon).and_then(|definition| definition.projectile) else {
+            return;
+        };
+
+        let entity_id = self.entities.spawn();
+        self.entities.insert(entity_id, entity::Transform::from_position(origin));
+        self.entities.insert(entity_id, entity::Projectile::new(shooter_id, weapon, definition, direction));
+        self.entities.insert(entity_id, entity::Collider::new(entity::ColliderShape::Sphere(collision::Sphere::new(glam::f32::Vec3::ZERO, definition.radius)), false));
+        self.entities.insert(entity_id, entity::Renderable::Collider);
+    }
+
+    fn explode(&mut self, explosion: &weapon::Explosion, world: &[&collision::TriangleSoup]) {
+
+        for (player_id, body) in self.get_bodies() {
+            if let Some(damage) = explosion.get_splash_damage(&body, world) {
+                self.pending_damage.push(damage::DamageEvent { attacker: explosion.owner, victim: player_id, weapon: explosion.weapon, location: damage::HitLocation::Splash, damage });
+            }
+        }
+    }
+
+    fn get_mut_player_by_id(&mut self, player_id: u32) -> Option<&mut player::Player> {
+
+        if player_id == net::HOST_PLAYER_ID && !self.dedicated {
+            return Some(&mut self.player);
+        }
+
+        self.remote_players.get_mut(&player_id)
+    }
+
+    //Server side, takes this tick's hits off everyone's health and tells the players involved, anyone killed is queued to respawn
+    fn apply_damage(&mut self, server: &mut net::NetServer) {
+
+        for event in std::mem::take(&mut self.pending_damage) {
+            let Some(victim) = self.get_mut_player_by_id(event.victim).filter(|victim| !victim.get_health().is_dead()) else {
+                continue;
+            };
+            let lost = victim.get_mut_health().take_damage(event.damage);
+            let health = *victim.get_health();
+            self.console.borrow_mut().output_to_console(&format!("Player {} hit player {} in {} with the {} for {:.0} damage, {:.0} health left", event.attacker, event.victim, event.location.get_name(), weapon::WEAPONS[event.weapon].name, lost, health.get_health()));
+
+            //The host already knows
+            let mut recipients = vec![event.victim];
+            if event.attacker != event.victim {
+                recipients.push(event.attacker);
+            }
+            for player_id in recipients.into_iter().filter(|player_id| self.remote_players.contains_key(player_id)) {
+                if let Err(e) = server.send_reliable(player_id, net::ReliableMessage::Damage(event)) {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send damage to player {}: {}", player_id, e));
+                }
+            }
+
+            if !health.is_dead() {
+                continue;
+            }
+            self.scoreboard.record_death(event.attacker, event.victim);
+            self.respawn_ticks.insert(event.victim, self.current_tick + damage::get_respawn_ticks());
+            let kill_feed = net::ReliableMessage::KillFeed { attacker: event.attacker, victim: event.victim, weapon: event.weapon };
+            for message in [kill_feed.clone(), net::ReliableMessage::Scoreboard(self.scoreboard.clone())] {
+                if let Err(e) = server.broadcast_reliable(message) {
+                    self.console.borrow_mut().output_to_console(&format!("Failed to send death of player {}: {}", event.victim, e));
+                }
+            }
+            self.handle_reliable_message(kill_feed, net::HOST_PLAYER_ID);
+        }
+    }
+
+    //Server side, brings back whoever has been dead long enough
+    fn respawn_players(&mut self, server: &mut net::NetServer) {
+
+        let due: Vec<u32> = self.respawn_ticks.iter().filter(|(_, tick)| **tick <= self.current_tick).map(|(player_id, _)| *player_id).collect();
+        for player_id in due {
+            self.respawn_ticks.remove(&player_id);
+            let Some(team) = self.get_mut_player_by_id(player_id).map(|player| player.get_team()) else {
+                continue;
+            };
+            let position = self.choose_spawn_point(team);
+            if let Some(player) = self.get_mut_player_by_id(player_id) {
+                player.respawn(position);
+            }
+            //Coming back to life isn't a move the player made
+            if let Some(validator) = self.validators.get_mut(&player_id) {
+                validator.reset_movement();
+            }
+            if let Err(e) = server.broadcast_reliable(net::ReliableMessage::Respawn { player_id, position }) {
+                self.console.borrow_mut().output_to_console(&format!("Failed to send respawn of player {}: {}", player_id, e));
+            }
+        }
+    }
+
+    //Server side, whichever of the map's spawns is furthest from the living players on other teams
+    fn choose_spawn_point(&self, team: player::Team) -> glam::f32::Vec3 {
+
+        let mut players: Vec<&player::Player> = self.remote_players.values().collect();
+        if !self.dedicated {
+            players.push(&self.player);
+        }
+        let enemies: Vec<glam::f32::Vec3> = players.into_iter().filter(|player| player.get_team() != team && !player.get_health().is_dead()).map(|player| *player.get_position()).collect();
+
+        self.level.as_ref().and_then(|level| level::choose_spawn_point(level.get_spawn_points(), &enemies)).unwrap_or(DEFAULT_SPAWN_POINT)
+    }
+
+    //Where every living body is now, for whatever isn't traced against lag compensated hitboxes
+    fn get_bodies(&self) -> Vec<(u32, collision::Capsule)> {
+
+        let mut players: Vec<(u32, &player::Player)> = self.remote_players.iter().map(|(player_id, player)| (*player_id, player)).collect();
+        if !self.dedicated {
+            players.push((net::HOST_PLAYER_ID, &self.player));
+        }
+
+        players.into_iter().filter(|(_, player)| !player.get_health().is_dead()).map(|(player_id, player)| (player_id, player.get_controller().get_world_capsule(*player.get_position()))).collect()
+    }
+
+    //Server side, forgets everything about a client's player
+    fn remove_remote_player(&mut self, player_id: u32) {
+
+        self.remote_players.remove(&player_id);
+        let entity_ids: Vec<entity::EntityId> = self.entities.iter::<entity::PlayerControl>().filter(|(_, control)| **control == entity::PlayerControl::Remote(player_id)).map(|(id, _)| id).collect();
+        for entity_id in entity_ids {
+            self.entities.despawn(entity_id);
+        }
+        self.command_buffers.remove(&player_id);
+        self.validators.remove(&player_id);
+        self.chat_flood_guards.remove(&player_id);
+        self.respawn_ticks.remove(&player_id);
+        self.scoreboard.remove_player(player_id);
     }
 
     fn end_tick(&mut self) {
@@ -165,7 +1223,68 @@ impl GameState {
         &mut self.render_commands
     }
 
+    //How long a caller with nothing else to do can sleep before update has work again
+    pub fn get_time_until_next_tick(&self) -> std::time::Duration {
+
+        std::time::Duration::from_micros(((self.tick_interval - self.tick_time).max(0.0) * 1000.0) as u64)
+    }
+
+    //Current tick plus how far we are towards the next one
+    pub fn get_fractional_tick(&self) -> f64 {
+
+        self.current_tick as f64 + (self.tick_time / self.tick_interval) as f64
+    }
+
+    pub fn get_tick_interval(&self) -> f32 {
+
+        self.tick_interval
+    }
+
+    pub fn get_clock_sync(&self) -> &clock_sync::ClockSync {
+
+        &self.clock_sync
+    }
+
     pub fn get_delta_time(&self) -> f32 {
         self.delta_time
     }
-}
\ No newline at end of file
+
+    pub fn get_network(&self) -> Option<&net::Network> {
+
+        self.network.as_ref()
+    }
+
+    pub fn get_entities(&self) -> &entity::EntityStore {
+
+        &self.entities
+    }
+
+    //Spawning and despawning from outside the level, anything spawned here is left alone on a map change
+    pub fn get_mut_entities(&mut self) -> &mut entity::EntityStore {
+
+        &mut self.entities
+    }
+
+    pub fn get_level(&self) -> Option<&level::Level> {
+
+        self.level.as_ref()
+    }
+}
+
+fn render_remote_player(position: &glam::f32::Vec3, render_commands: &mut Vec<RenderCommands>) {
+
+    let capsule = character_controller::get_player_capsule();
+    collision::Capsule::new(capsule.get_base() + *position, capsule.get_tip() + *position, capsule.get_radius()).render(render_commands);
+}
+
+//Drops whatever the current state doesn't allow, the same on the client and the server so prediction lines up
+fn restrict_command(state: States, command: &mut user_cmd::UserCmd) {
+
+    if !state.accepts_movement() {
+        command.movement = glam::f32::Vec3::ZERO;
+        command.buttons &= !(user_cmd::BUTTON_JUMP | user_cmd::BUTTON_CROUCH);
+    }
+    if !state.accepts_fire() {
+        command.buttons &= !user_cmd::BUTTON_FIRE;
+    }
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+
+use crate::collision;
+use crate::render_commands::RenderCommands;
+
+//Skeleton model players are posed from, each from their own time in its first animation
+pub const PLAYER_MODEL: &str = "Roll_Caskett";
+
+//Which part of the body a hitbox covers, decides how much a hit there hurts
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HitRegion {
+    Head,
+    Chest,
+    Stomach,
+    Arm,
+    Leg,
+}
+
+impl HitRegion {
+
+    pub fn get_name(&self) -> &'static str {
+
+        match self {
+            HitRegion::Head => "head",
+            HitRegion::Chest => "chest",
+            HitRegion::Stomach => "stomach",
+            HitRegion::Arm => "arm",
+            HitRegion::Leg => "leg",
+        }
+    }
+
+    pub fn get_damage_multiplier(&self) -> f32 {
+
+        match self {
+            HitRegion::Head => 2.0,
+            HitRegion::Chest | HitRegion::Stomach => 1.0,
+            HitRegion::Arm | HitRegion::Leg => 0.75,
+        }
+    }
+}
+
+//In meters, capsules run from the bone along its Y axis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BoneShape {
+    Sphere { radius: f32 },
+    Capsule { length: f32, radius: f32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HitboxDefinition {
+    //Name of the bone in the player model's rig
+    pub bone: &'static str,
+    pub region: HitRegion,
+    pub shape: BoneShape,
+    //Where the bone sits relative to the feet and which way it points when standing still, used when there's no skeleton to pose it from
+    pub rest_position: glam::f32::Vec3,
+    pub rest_direction: glam::f32::Vec3,
+}
+
+//Head first
+pub const HITBOXES: [HitboxDefinition; 7] = [
+    HitboxDefinition { bone: "Head", region: HitRegion::Head, shape: BoneShape::Sphere { radius: 0.25 }, rest_position: glam::f32::Vec3::new(0.0, 1.55, 0.0), rest_direction: glam::f32::Vec3::Y },
+    HitboxDefinition { bone: "Spine2", region: HitRegion::Chest, shape: BoneShape::Capsule { length: 0.45, radius: 0.3 }, rest_position: glam::f32::Vec3::new(0.0, 0.95, 0.0), rest_direction: glam::f32::Vec3::Y },
+    HitboxDefinition { bone: "Spine", region: HitRegion::Stomach, shape: BoneShape::Capsule { length: 0.4, radius: 0.28 }, rest_position: glam::f32::Vec3::new(0.0, 0.6, 0.0), rest_direction: glam::f32::Vec3::Y },
+    HitboxDefinition { bone: "LeftArm", region: HitRegion::Arm, shape: BoneShape::Capsule { length: 0.6, radius: 0.1 }, rest_position: glam::f32::Vec3::new(0.38, 1.35, 0.0), rest_direction: glam::f32::Vec3::NEG_Y },
+    HitboxDefinition { bone: "RightArm", region: HitRegion::Arm, shape: BoneShape::Capsule { length: 0.6, radius: 0.1 }, rest_position: glam::f32::Vec3::new(-0.38, 1.35, 0.0), rest_direction: glam::f32::Vec3::NEG_Y },
+    HitboxDefinition { bone: "LeftUpLeg", region: HitRegion::Leg, shape: BoneShape::Capsule { length: 0.85, radius: 0.14 }, rest_position: glam::f32::Vec3::new(0.14, 0.85, 0.0), rest_direction: glam::f32::Vec3::NEG_Y },
+    HitboxDefinition { bone: "RightUpLeg", region: HitRegion::Leg, shape: BoneShape::Capsule { length: 0.85, radius: 0.14 }, rest_position: glam::f32::Vec3::new(-0.14, 0.85, 0.0), rest_direction: glam::f32::Vec3::NEG_Y },
+];
+
+//Bones HITBOXES are posed from that the rig doesn't have, those hitboxes stay at rest
+pub fn get_missing_bones(bone_names: &[String]) -> Vec<&'static str> {
+
+    HITBOXES.iter().map(|definition| definition.bone).filter(|bone| !bone_names.iter().any(|name| name == bone)).collect()
+}
+
+//How the player model's space sits in the player's, the same placement the test level stands the model up with
+pub fn get_model_transform() -> glam::f32::Mat4 {
+
+    glam::f32::Mat4::from_scale_rotation_translation(glam::f32::Vec3::splat(0.1), glam::f32::Quat::from_rotation_x(90.0_f32.to_radians()), glam::f32::Vec3::ZERO)
+}
+
+//A hitbox where it is in the world
+#[derive(Debug, Copy, Clone)]
+pub enum HitboxShape {
+    Sphere(collision::Sphere),
+    Capsule(collision::Capsule),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hitbox {
+    pub region: HitRegion,
+    pub shape: HitboxShape,
+}
+
+impl Hitbox {
+
+    pub fn new(region: HitRegion, shape: HitboxShape) -> Self {
+
+        Self { region, shape }
+    }
+
+    pub fn vs_ray(&self, ray: &collision::Ray) -> collision::CollisionPacket {
+
+        match &self.shape {
+            HitboxShape::Sphere(sphere) => ray.vs_sphere(sphere),
+            HitboxShape::Capsule(capsule) => ray.vs_capsule(capsule),
+        }
+    }
+
+    //Somewhere between this and the same hitbox on another tick, the other's shape wins if they don't match
+    pub fn lerp(&self, other: &Hitbox, t: f32) -> Hitbox {
+
+        let shape = match (self.shape, other.shape) {
+            (HitboxShape::Sphere(a), HitboxShape::Sphere(b)) => HitboxShape::Sphere(collision::Sphere::new(a.get_center().lerp(b.get_center(), t), a.get_radius())),
+            (HitboxShape::Capsule(a), HitboxShape::Capsule(b)) => HitboxShape::Capsule(collision::Capsule::new(a.get_base().lerp(b.get_base(), t), a.get_tip().lerp(b.get_tip(), t), a.get_radius())),
+            (_, shape) => shape,
+        };
+
+        Hitbox::new(self.region, shape)
+    }
+
+    pub fn render(&self, render_commands: &mut Vec<RenderCommands>) {
+
+        match &self.shape {
+            HitboxShape::Sphere(sphere) => sphere.render(render_commands),
+            HitboxShape::Capsule(capsule) => capsule.render(render_commands),
+        }
+    }
+}
+
+//Every hitbox for a player whose feet are at feet and facing yaw, squashed down to height_scale of standing
+//get_bone gives a bone's matrix in model space from the current animation, bones it doesn't know stay at rest
+pub fn pose_hitboxes(feet: glam::f32::Vec3, yaw: f32, height_scale: f32, get_bone: impl Fn(&str) -> Option<glam::f32::Mat4>) -> Vec<Hitbox> {
+
+    //The model faces +Z, turn that to where the player is facing
+    let player_transform = glam::f32::Mat4::from_translation(feet) * glam::f32::Mat4::from_scale(glam::f32::Vec3::new(1.0, height_scale, 1.0)) * glam::f32::Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2 - yaw);
+    let model_transform = get_model_transform();
+
+    HITBOXES.iter().map(|definition| {
+        let (position, direction) = match get_bone(definition.bone) {
+            Some(bone) => {
+                let bone = model_transform * bone;
+                (bone.transform_point3(glam::f32::Vec3::ZERO), bone.transform_vector3(glam::f32::Vec3::Y).try_normalize().unwrap_or(definition.rest_direction))
+            }
+            None => (definition.rest_position, definition.rest_direction),
+        };
+        let shape = match definition.shape {
+            BoneShape::Sphere { radius } => HitboxShape::Sphere(collision::Sphere::new(player_transform.transform_point3(position), radius)),
+            BoneShape::Capsule { length, radius } => HitboxShape::Capsule(collision::Capsule::new(player_transform.transform_point3(position), player_transform.transform_point3(position + direction * length), radius)),
+        };
+
+        Hitbox::new(definition.region, shape)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::model;
+
+    fn get_center(hitbox: &Hitbox) -> glam::f32::Vec3 {
+
+        match &hitbox.shape {
+            HitboxShape::Sphere(sphere) => sphere.get_center(),
+            HitboxShape::Capsule(capsule) => capsule.get_center(),
+        }
+    }
+
+    #[test]
+    fn rest_pose_stands_on_the_feet() {
+
+        let feet = glam::f32::Vec3::new(5.0, 2.0, -3.0);
+        let hitboxes = pose_hitboxes(feet, 0.0, 1.0, |_| None);
+        assert_eq!(hitboxes.len(), HITBOXES.len());
+        assert_eq!(hitboxes[0].region, HitRegion::Head);
+        assert!(get_center(&hitboxes[0]).abs_diff_eq(feet + HITBOXES[0].rest_position, 0.0001));
+
+        //Legs hang down to the feet
+        let HitboxShape::Capsule(leg) = hitboxes[5].shape else {
+            panic!("legs are capsules");
+        };
+        assert!((leg.get_tip().y - feet.y).abs() < 0.0001, "{:?}", leg);
+
+        //Crouching brings the head down
+        let crouched = pose_hitboxes(feet, 0.0, 0.5, |_| None);
+        assert!((get_center(&crouched[0]).y - (feet.y + HITBOXES[0].rest_position.y * 0.5)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn bones_move_their_hitboxes_and_turn_with_the_player() {
+
+        //The head bone moved along the model's X, which is still X once stood up
+        let head = glam::f32::Mat4::from_translation(glam::f32::Vec3::new(10.0, 0.0, 0.0));
+        let get_bone = |name: &str| if name == "Head" { Some(head) } else { None };
+
+        //Facing +Z the model isn't turned
+        let hitboxes = pose_hitboxes(glam::f32::Vec3::ZERO, std::f32::consts::FRAC_PI_2, 1.0, get_bone);
+        assert!(get_center(&hitboxes[0]).abs_diff_eq(glam::f32::Vec3::new(1.0, 0.0, 0.0), 0.0001), "{:?}", get_center(&hitboxes[0]));
+        //Everything else is left where it rests
+        assert!(get_center(&hitboxes[1]).abs_diff_eq(get_center(&pose_hitboxes(glam::f32::Vec3::ZERO, std::f32::consts::FRAC_PI_2, 1.0, |_| None)[1]), 0.0001));
+
+        //Facing +X turns the model's +X to -Z
+        let hitboxes = pose_hitboxes(glam::f32::Vec3::ZERO, 0.0, 1.0, get_bone);
+        assert!(get_center(&hitboxes[0]).abs_diff_eq(glam::f32::Vec3::new(0.0, 0.0, -1.0), 0.0001), "{:?}", get_center(&hitboxes[0]));
+    }
+
+    fn get_bone(name: &str, translations: Vec<glam::f32::Vec3>, child_bones: Vec<model::Bone>) -> model::Bone {
+
+        let frames = translations.len();
+
+        model::Bone { child_bones, transform: glam::f32::Mat4::IDENTITY, name: name.to_string(), id: 0, animations_translation: HashMap::from([("Walk".to_string(), translations)]), animations_rotation: HashMap::from([("Walk".to_string(), vec![glam::f32::Quat::IDENTITY; frames])]), animations_scale: HashMap::from([("Walk".to_string(), vec![glam::f32::Vec3::ONE; frames])]) }
+    }
+
+    #[test]
+    fn posed_hitboxes_follow_the_animation() {
+
+        //The head swings along the model's X between the two keyframes
+        let head = get_bone("Head", vec![glam::f32::Vec3::new(0.0, 150.0, 0.0), glam::f32::Vec3::new(30.0, 150.0, 0.0)], Vec::new());
+        let root = get_bone("Hips", vec![glam::f32::Vec3::ZERO; 2], vec![head]);
+        let skeleton = model::SkeletonData::from_skeleton(model::Skeleton { root_bone: root, inverse_bind_matrices: Vec::new() }, vec!["Walk".to_string()]);
+
+        let pose_at = |time: f32| {
+            let pose = skeleton.get_pose("Walk", time);
+            pose_hitboxes(glam::f32::Vec3::ZERO, 0.0, 1.0, |name| skeleton.get_bone_matrix(&pose, name))
+        };
+        let start = get_center(&pose_at(0.0)[0]);
+        let end = get_center(&pose_at(1.0 / model::ANIMATION_FRAMES_PER_SECOND)[0]);
+        assert!(start.distance(end) > 0.1, "{:?} {:?}", start, end);
+
+        //Bones that aren't in the skeleton stay at rest
+        assert!(get_center(&pose_at(0.0)[1]).abs_diff_eq(get_center(&pose_at(1.0 / model::ANIMATION_FRAMES_PER_SECOND)[1]), 0.0001));
+
+        //No animation by that name leaves everything at rest rather than panicking
+        assert!(skeleton.get_pose("Run", 0.0).is_empty());
+    }
+
+    #[test]
+    fn bones_missing_from_the_rig_are_found() {
+
+        let mut bone_names: Vec<String> = HITBOXES.iter().map(|definition| definition.bone.to_string()).collect();
+        assert!(get_missing_bones(&bone_names).is_empty());
+
+        //Mixamo rigs prefix every bone
+        bone_names[0] = "mixamorig:Head".to_string();
+        assert_eq!(get_missing_bones(&bone_names), vec!["Head"]);
+    }
+
+    #[test]
+    fn headshots_hurt_more() {
+
+        assert_eq!(HitRegion::Head.get_damage_multiplier(), 2.0);
+        assert!(HITBOXES.iter().all(|definition| definition.region.get_damage_multiplier() <= HitRegion::Head.get_damage_multiplier()));
+        assert!(HitRegion::Leg.get_damage_multiplier() < HitRegion::Chest.get_damage_multiplier());
+    }
+}
@@ -13,15 +13,24 @@ pub const FORWARD: KeyCode = KeyCode::KeyW;
 pub const LEFT: KeyCode = KeyCode::KeyA;
 pub const BACKWARD: KeyCode = KeyCode::KeyS;
 pub const RIGHT: KeyCode = KeyCode::KeyD;
-pub const DOWN: KeyCode = KeyCode::KeyQ;
-pub const UP: KeyCode = KeyCode::KeyE;
+pub const JUMP: KeyCode = KeyCode::Space;
+pub const CROUCH: KeyCode = KeyCode::ControlLeft;
+pub const RELOAD: KeyCode = KeyCode::KeyR;
+//One per weapon, in the same order
+pub const WEAPON_SLOTS: [KeyCode; 5] = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5];
+//Raw device button id, 1 is the left mouse button
+pub const FIRE: u32 = 1;
 
 pub const MOUSE_SENSITIVITY: f32 = 0.003;
+//Scrolling by pixels on a touchpad counts a step for this many
+pub const PIXELS_PER_WHEEL_STEP: f32 = 50.0;
 
 pub struct Inputs {
     keyboard_inputs: HashMap<KeyCode, InputState>,
     mouse_buttons: HashMap<u32, InputState>,
     mouse_motion: [f32; 2],
+    //Wheel steps not used yet, positive is away from the user, partial steps carry over until they add up to one
+    mouse_wheel: f32,
 }
 
 impl Inputs {
@@ -32,7 +41,7 @@ impl Inputs {
         let mouse_buttons: HashMap<u32, InputState> = HashMap::new();
         let mouse_motion: [f32; 2] = [0.0, 0.0];
 
-        Self { keyboard_inputs, mouse_buttons, mouse_motion }
+        Self { keyboard_inputs, mouse_buttons, mouse_motion, mouse_wheel: 0.0 }
     }
 
     pub fn keyboard_input(&mut self, input: &KeyEvent) {
@@ -98,6 +107,11 @@ impl Inputs {
         self.mouse_motion[axis as usize] += value as f32;
     }
 
+    pub fn mouse_wheel_input(&mut self, steps: f32) {
+
+        self.mouse_wheel += steps;
+    }
+
     pub fn end_tick_clean(&mut self) {
 
         for (_, input_state) in self.keyboard_inputs.iter_mut() {
@@ -121,6 +135,16 @@ impl Inputs {
         }
 
         self.mouse_motion = [0.0, 0.0];
+        self.mouse_wheel = self.mouse_wheel.fract();
+    }
+
+    //Everything counts as up, e.g. while typing in the chat box so keys held when it opened don't stay held
+    pub fn release_all(&mut self) {
+
+        self.keyboard_inputs.clear();
+        self.mouse_buttons.clear();
+        self.mouse_motion = [0.0, 0.0];
+        self.mouse_wheel = 0.0;
     }
 
     pub fn get_mouse_motion(&self) -> [f32; 2] {
@@ -128,6 +152,12 @@ impl Inputs {
         self.mouse_motion
     }
 
+    //Whole steps only, a partial step on a touchpad waits for the rest of it
+    pub fn get_mouse_wheel_steps(&self) -> i32 {
+
+        self.mouse_wheel.trunc() as i32
+    }
+
     pub fn check_key_down(&self, key: KeyCode) -> bool {
 
         self.keyboard_inputs.get(&key).is_some_and(|input| *input == InputState::JustPressed || *input == InputState::Held)
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+
+use crate::net;
+
+//How many snapshots to hold on to, only the two around the render tick are ever used
+const MAX_BUFFERED_SNAPSHOTS: usize = 32;
+
+//Default delay of 100ms, enough to ride out one or two lost snapshots
+pub const DEFAULT_INTERPOLATION_DELAY: f32 = 6.0;
+//Default extrapolation limit of 250ms, after this remote entities freeze in place
+pub const DEFAULT_MAX_EXTRAPOLATION: f32 = 15.0;
+
+//Anything that comes in server snapshots and needs smoothing between them
+pub trait Interpolate: Clone {
+
+    fn get_id(&self) -> u32;
+
+    //t of 0 is self, 1 is other, over 1 extrapolates past other
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for net::PlayerSnapshot {
+
+    fn get_id(&self) -> u32 {
+
+        self.id
+    }
+
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+
+        //Go the short way round when yaw wraps
+        let mut yaw_difference = (other.yaw - self.yaw) % std::f32::consts::TAU;
+        if yaw_difference > std::f32::consts::PI {
+            yaw_difference -= std::f32::consts::TAU;
+        }
+        else if yaw_difference < -std::f32::consts::PI {
+            yaw_difference += std::f32::consts::TAU;
+        }
+
+        Self { id: self.id, position: self.position.lerp(other.position, t), yaw: self.yaw + yaw_difference * t, pitch: self.pitch + (other.pitch - self.pitch) * t }
+    }
+}
+
+//Buffers server snapshots and samples them a fixed delay in the past so there is always a pair to blend between
+pub struct SnapshotInterpolator<T: Interpolate> {
+    snapshots: VecDeque<(u32, Vec<T>)>,
+    interpolation_delay: f32,
+    max_extrapolation: f32,
+    //Client ticks since the newest snapshot arrived
+    ticks_since_latest: f32,
+}
+
+impl<T: Interpolate> SnapshotInterpolator<T> {
+
+    //Both are in ticks
+    pub fn new(interpolation_delay: f32, max_extrapolation: f32) -> Self {
+
+        Self { snapshots: VecDeque::new(), interpolation_delay, max_extrapolation, ticks_since_latest: 0.0 }
+    }
+
+    pub fn insert(&mut self, tick: u32, entities: Vec<T>) {
+
+        //Keep sorted oldest first, snapshots can turn up out of order
+        let index = match self.snapshots.binary_search_by_key(&tick, |(snapshot_tick, _)| *snapshot_tick) {
+            Ok(_) => return,
+            Err(index) => index,
+        };
+
+        if index == self.snapshots.len() {
+            self.ticks_since_latest = 0.0;
+        }
+
+        self.snapshots.insert(index, (tick, entities));
+
+        while self.snapshots.len() > MAX_BUFFERED_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    //Call once per client tick
+    pub fn advance(&mut self, ticks: f32) {
+
+        self.ticks_since_latest += ticks;
+    }
+
+    //The server tick currently being shown
+    pub fn get_render_tick(&self) -> Option<f32> {
+
+        self.snapshots.back().map(|(latest_tick, _)| *latest_tick as f32 + self.ticks_since_latest - self.interpolation_delay)
+    }
+
+    pub fn sample(&self) -> Vec<T> {
+
+        match self.get_render_tick() {
+            Some(render_tick) => self.sample_at(render_tick),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn sample_at(&self, render_tick: f32) -> Vec<T> {
+
+        let Some((latest_tick, latest)) = self.snapshots.back() else {
+            return Vec::new();
+        };
+
+        //Past the newest snapshot, carry on along the last known motion for a little while
+        if render_tick >= *latest_tick as f32 {
+            let extrapolation = (render_tick - *latest_tick as f32).min(self.max_extrapolation);
+            return latest.iter().map(|entity| self.extrapolate(entity, *latest_tick, extrapolation)).collect();
+        }
+
+        let after_index = self.snapshots.iter().position(|(tick, _)| *tick as f32 > render_tick).unwrap();
+        if after_index == 0 {
+            return self.snapshots[0].1.clone();
+        }
+
+        let (before_tick, before) = &self.snapshots[after_index - 1];
+        let (after_tick, after) = &self.snapshots[after_index];
+        let t = (render_tick - *before_tick as f32) / (*after_tick - *before_tick) as f32;
+
+        before.iter().map(|entity| {
+            match after.iter().find(|other| other.get_id() == entity.get_id()) {
+                Some(other) => entity.interpolate(other, t),
+                //Gone in the next snapshot, hold it where it was until then
+                None => entity.clone(),
+            }
+        }).collect()
+    }
+
+    pub fn set_interpolation_delay(&mut self, interpolation_delay: f32) {
+
+        self.interpolation_delay = interpolation_delay.max(0.0);
+    }
+
+    pub fn get_interpolation_delay(&self) -> f32 {
+
+        self.interpolation_delay
+    }
+
+    pub fn set_max_extrapolation(&mut self, max_extrapolation: f32) {
+
+        self.max_extrapolation = max_extrapolation.max(0.0);
+    }
+
+    pub fn get_max_extrapolation(&self) -> f32 {
+
+        self.max_extrapolation
+    }
+
+    pub fn clear(&mut self) {
+
+        self.snapshots.clear();
+        self.ticks_since_latest = 0.0;
+    }
+
+    fn extrapolate(&self, entity: &T, latest_tick: u32, extrapolation: f32) -> T {
+
+        if extrapolation <= 0.0 {
+            return entity.clone();
+        }
+
+        //Newest older snapshot that still has this entity gives us its velocity
+        let previous = self.snapshots.iter().rev().skip(1).find_map(|(tick, entities)| entities.iter().find(|other| other.get_id() == entity.get_id()).map(|other| (*tick, other)));
+
+        match previous {
+            Some((previous_tick, previous)) => {
+                let span = (latest_tick - previous_tick) as f32;
+                previous.interpolate(entity, 1.0 + extrapolation / span)
+            }
+            None => entity.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: u32, x: f32) -> net::PlayerSnapshot {
+
+        net::PlayerSnapshot { id, position: glam::f32::Vec3::new(x, 0.0, 0.0), yaw: 0.0, pitch: 0.0 }
+    }
+
+    #[test]
+    fn interpolates_between_surrounding_snapshots() {
+
+        let mut interpolator = SnapshotInterpolator::new(2.0, 4.0);
+        interpolator.insert(10, vec![player(1, 0.0)]);
+        interpolator.insert(12, vec![player(1, 4.0)]);
+
+        assert_eq!(interpolator.sample_at(11.0), vec![player(1, 2.0)]);
+        assert_eq!(interpolator.sample_at(11.5), vec![player(1, 3.0)]);
+
+        //Render tick is two behind the newest
+        assert_eq!(interpolator.get_render_tick(), Some(10.0));
+        interpolator.advance(1.0);
+        assert_eq!(interpolator.sample(), vec![player(1, 2.0)]);
+    }
+
+    #[test]
+    fn out_of_order_snapshots_are_sorted() {
+
+        let mut interpolator = SnapshotInterpolator::new(0.0, 0.0);
+        interpolator.insert(20, vec![player(1, 20.0)]);
+        interpolator.insert(10, vec![player(1, 10.0)]);
+        interpolator.insert(10, vec![player(1, 99.0)]);
+
+        assert_eq!(interpolator.sample_at(15.0), vec![player(1, 15.0)]);
+        assert_eq!(interpolator.get_render_tick(), Some(20.0));
+    }
+
+    #[test]
+    fn extrapolation_is_bounded() {
+
+        let mut interpolator = SnapshotInterpolator::new(0.0, 4.0);
+        interpolator.insert(0, vec![player(1, 0.0)]);
+        interpolator.insert(1, vec![player(1, 1.0)]);
+
+        assert_eq!(interpolator.sample_at(3.0), vec![player(1, 3.0)]);
+        assert_eq!(interpolator.sample_at(5.0), vec![player(1, 5.0)]);
+        //Packets stopped coming, don't fly off forever
+        assert_eq!(interpolator.sample_at(100.0), vec![player(1, 5.0)]);
+    }
+
+    #[test]
+    fn entities_missing_from_a_snapshot_are_held() {
+
+        let mut interpolator = SnapshotInterpolator::new(0.0, 0.0);
+        interpolator.insert(0, vec![player(1, 0.0), player(2, 5.0)]);
+        interpolator.insert(2, vec![player(1, 2.0)]);
+
+        assert_eq!(interpolator.sample_at(1.0), vec![player(1, 1.0), player(2, 5.0)]);
+        assert_eq!(interpolator.sample_at(2.0), vec![player(1, 2.0)]);
+    }
+
+    #[test]
+    fn yaw_takes_the_short_way_round() {
+
+        let mut a = player(1, 0.0);
+        a.yaw = 350.0_f32.to_radians();
+        let mut b = player(1, 0.0);
+        b.yaw = 10.0_f32.to_radians();
+
+        let halfway = a.interpolate(&b, 0.5);
+        assert!((halfway.yaw.to_degrees() - 360.0).abs() < 0.001);
+    }
+}
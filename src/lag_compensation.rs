@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+
+use crate::collision;
+use crate::hitbox::{HitRegion, Hitbox};
+
+//About one second of ticks, shots from further back than this are clamped to the oldest we have
+pub const HITBOX_HISTORY_TICKS: usize = 60;
+
+//Player id and that player's hitboxes
+pub type PlayerHitboxes = (u32, Vec<Hitbox>);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HitscanHit {
+    pub player_id: u32,
+    //Index into the hitboxes of that player, see Player::get_hitboxes
+    pub hitbox: usize,
+    pub region: HitRegion,
+    pub position: glam::f32::Vec3,
+    //Fraction along the ray, 0 at the start and 1 at the end
+    pub time: f32,
+}
+
+//Server side, where every player's hitboxes were on each of the last HITBOX_HISTORY_TICKS ticks
+pub struct LagCompensation {
+    history: VecDeque<(u32, Vec<PlayerHitboxes>)>,
+}
+
+impl Default for LagCompensation {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl LagCompensation {
+
+    pub fn new() -> Self {
+
+        Self { history: VecDeque::new() }
+    }
+
+    //Call once per server tick after everyone has moved
+    pub fn record(&mut self, tick: u32, players: Vec<PlayerHitboxes>) {
+
+        if self.history.back().is_some_and(|(latest_tick, _)| tick <= *latest_tick) {
+            return;
+        }
+
+        self.history.push_back((tick, players));
+        while self.history.len() > HITBOX_HISTORY_TICKS {
+            self.history.pop_front();
+        }
+    }
+
+    //Hitboxes as they were at view_tick, blending between the two recorded ticks around it
+    pub fn rewind(&self, view_tick: f32) -> Vec<PlayerHitboxes> {
+
+        let (Some((oldest_tick, _)), Some((latest_tick, latest))) = (self.history.front(), self.history.back()) else {
+            return Vec::new();
+        };
+
+        //Comes from the client so could be anything
+        if !view_tick.is_finite() {
+            return latest.clone();
+        }
+
+        let view_tick = view_tick.clamp(*oldest_tick as f32, *latest_tick as f32);
+        if view_tick >= *latest_tick as f32 {
+            return latest.clone();
+        }
+
+        let after_index = self.history.iter().position(|(tick, _)| *tick as f32 > view_tick).unwrap();
+        let (before_tick, before) = &self.history[after_index - 1];
+        let (after_tick, after) = &self.history[after_index];
+        let t = (view_tick - *before_tick as f32) / (*after_tick - *before_tick) as f32;
+
+        before.iter().map(|(player_id, hitboxes)| {
+            match after.iter().find(|(other_id, other)| other_id == player_id && other.len() == hitboxes.len()) {
+                Some((_, other)) => {
+                    let blended = hitboxes.iter().zip(other).map(|(hitbox, other)| hitbox.lerp(other, t)).collect();
+                    (*player_id, blended)
+                }
+                None => (*player_id, hitboxes.clone()),
+            }
+        }).collect()
+    }
+
+    //The ray is from start to start + direction, the shooter can't hit themselves
+    pub fn trace(&self, ray: &collision::Ray, view_tick: f32, shooter_id: u32) -> Option<HitscanHit> {
+
+        let mut closest: Option<HitscanHit> = None;
+
+        for (player_id, hitboxes) in self.rewind(view_tick) {
+            if player_id == shooter_id {
+                continue;
+            }
+
+            for (index, hitbox) in hitboxes.iter().enumerate() {
+                let collision = hitbox.vs_ray(ray);
+                if !collision.collided || collision.penetration_or_time > 1.0 {
+                    continue;
+                }
+
+                if closest.is_none_or(|closest| collision.penetration_or_time < closest.time) {
+                    closest = Some(HitscanHit { player_id, hitbox: index, region: hitbox.region, position: collision.position, time: collision.penetration_or_time });
+                }
+            }
+        }
+
+        closest
+    }
+
+    pub fn get_oldest_tick(&self) -> Option<u32> {
+
+        self.history.front().map(|(tick, _)| *tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitbox::HitboxShape;
+    use crate::weapon;
+
+    fn sphere(center: glam::f32::Vec3, radius: f32) -> Hitbox {
+
+        Hitbox::new(HitRegion::Chest, HitboxShape::Sphere(collision::Sphere::new(center, radius)))
+    }
+
+    //A head on a capsule body
+    fn hitboxes(x: f32) -> Vec<Hitbox> {
+
+        vec![
+            Hitbox::new(HitRegion::Head, HitboxShape::Sphere(collision::Sphere::new(glam::f32::Vec3::new(x, 1.0, 0.0), 0.25))),
+            Hitbox::new(HitRegion::Chest, HitboxShape::Capsule(collision::Capsule::new(glam::f32::Vec3::new(x, -1.0, 0.0), glam::f32::Vec3::new(x, 0.5, 0.0), 0.5))),
+        ]
+    }
+
+    //Player 2 walks along x one unit a tick
+    fn history() -> LagCompensation {
+
+        let mut lag_compensation = LagCompensation::new();
+        for tick in 0..10 {
+            lag_compensation.record(tick, vec![(1, hitboxes(-100.0)), (2, hitboxes(tick as f32))]);
+        }
+
+        lag_compensation
+    }
+
+    fn shot_at(x: f32, y: f32) -> collision::Ray {
+
+        collision::Ray::new(glam::f32::Vec3::new(x, y, -10.0), glam::f32::Vec3::new(0.0, 0.0, 20.0))
+    }
+
+    #[test]
+    fn shot_hits_where_the_target_was_at_the_view_tick() {
+
+        let lag_compensation = history();
+
+        //Aiming at where player 2 was on tick 3 misses now but hits once rewound
+        assert!(lag_compensation.trace(&shot_at(3.0, 0.0), 9.0, 1).is_none());
+        let hit = lag_compensation.trace(&shot_at(3.0, 0.0), 3.0, 1).unwrap();
+        assert_eq!(hit.player_id, 2);
+        assert_eq!((hit.hitbox, hit.region), (1, HitRegion::Chest));
+        assert!(hit.position.distance(glam::f32::Vec3::new(3.0, 0.0, -0.5)) < 0.0001);
+    }
+
+    #[test]
+    fn fractional_view_ticks_blend_between_records() {
+
+        let lag_compensation = history();
+
+        let hit = lag_compensation.trace(&shot_at(4.5, 1.0), 4.5, 1).unwrap();
+        assert_eq!((hit.hitbox, hit.region), (0, HitRegion::Head));
+        assert!(hit.position.distance(glam::f32::Vec3::new(4.5, 1.0, -0.25)) < 0.0001);
+    }
+
+    #[test]
+    fn closest_hitbox_wins_and_shooter_is_ignored() {
+
+        let mut lag_compensation = LagCompensation::new();
+        lag_compensation.record(0, vec![(1, vec![sphere(glam::f32::Vec3::new(0.0, 0.0, -10.0), 1.0)]), (2, hitboxes(0.0)), (3, vec![sphere(glam::f32::Vec3::new(0.0, 0.0, 5.0), 1.0)])]);
+
+        let hit = lag_compensation.trace(&shot_at(0.0, 0.0), 0.0, 1).unwrap();
+        assert_eq!(hit.player_id, 2);
+    }
+
+    #[test]
+    fn history_is_bounded_and_old_shots_are_clamped() {
+
+        let mut lag_compensation = LagCompensation::new();
+        for tick in 0..(HITBOX_HISTORY_TICKS as u32 * 2) {
+            lag_compensation.record(tick, vec![(2, hitboxes(tick as f32))]);
+        }
+
+        let oldest_tick = lag_compensation.get_oldest_tick().unwrap();
+        assert_eq!(oldest_tick, HITBOX_HISTORY_TICKS as u32);
+        assert!(lag_compensation.trace(&shot_at(0.0, 0.0), 0.0, 1).is_none());
+        assert!(lag_compensation.trace(&shot_at(oldest_tick as f32, 0.0), 0.0, 1).is_some());
+    }
+
+    #[test]
+    fn out_of_range_shots_miss() {
+
+        let lag_compensation = history();
+
+        let short_ray = collision::Ray::new(glam::f32::Vec3::new(9.0, 0.0, -10.0), glam::f32::Vec3::new(0.0, 0.0, 5.0));
+        assert!(lag_compensation.trace(&short_ray, 9.0, 1).is_none());
+    }
+
+    #[test]
+    fn cover_stops_shots_at_players_behind_it() {
+
+        let lag_compensation = history();
+
+        //Wall across the shot between the shooter and player 2
+        let wall = collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(-5.0, -5.0, -5.0), glam::f32::Vec3::new(15.0, -5.0, -5.0), glam::f32::Vec3::new(5.0, 15.0, -5.0)),
+        ]);
+        let ray = shot_at(9.0, 0.0);
+        let player_hit = lag_compensation.trace(&ray, 9.0, 1);
+        assert!(player_hit.is_some());
+
+        //Player 2 is behind it, so the shot stops at the wall and nobody is hurt
+        assert!(matches!(weapon::trace(&ray, &[&wall], player_hit), Some(weapon::ShotHit::World { .. })));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::collision;
+use crate::entity::*;
+
+//Everything a map spawned, so it can all go again when the map changes
+pub struct Level {
+    name: String,
+    entities: Vec<EntityId>,
+    //Eye positions players can come back at
+    spawn_points: Vec<glam::f32::Vec3>,
+}
+
+//Furthest from the nearest enemy wins, the first one when nobody is around
+pub fn choose_spawn_point(spawn_points: &[glam::f32::Vec3], enemies: &[glam::f32::Vec3]) -> Option<glam::f32::Vec3> {
+
+    let get_score = |spawn_point: &glam::f32::Vec3| enemies.iter().map(|enemy| spawn_point.distance(*enemy)).fold(f32::MAX, f32::min);
+
+    spawn_points.iter().copied().reduce(|best, spawn_point| if get_score(&spawn_point) > get_score(&best) { spawn_point } else { best })
+}
+
+impl Level {
+
+    //None if there's no map by that name
+    pub fn load(name: &str, entities: &mut EntityStore) -> Option<Self> {
+
+        let mut level = Self { name: name.to_string(), entities: Vec::new(), spawn_points: Vec::new() };
+
+        match name {
+            "test_triangle" => {
+                level.spawn_points = vec![
+                    glam::f32::Vec3::new(0.0, 1.0, 4.0),
+                    glam::f32::Vec3::new(8.0, 1.0, 8.0),
+                    glam::f32::Vec3::new(-8.0, 1.0, 8.0),
+                    glam::f32::Vec3::new(8.0, 1.0, -8.0),
+                    glam::f32::Vec3::new(-8.0, 1.0, -8.0),
+                ];
+                level.spawn(entities, Transform::from_position(glam::f32::Vec3::ZERO), Some(Renderable::model("test_triangle", "debug")), None);
+
+                let cube = level.spawn(entities, Transform::from_position(glam::f32::Vec3::new(0.0, 7.0, 0.0)), Some(Renderable::model("cube", "tree")), None);
+                entities.insert(cube, Spinner { origin: glam::f32::Vec3::new(0.0, 7.0, 0.0), rotation_rate: glam::f32::Vec3::new(1.0 / 10.0, 1.0 / 10.0, 0.0), sway_amplitude: 2.0, sway_rate: 1.0 / 20.0 });
+                let cube = level.spawn(entities, Transform::from_position(glam::f32::Vec3::new(0.0, 10.0, 0.0)), Some(Renderable::model("cube", "tree")), None);
+                entities.insert(cube, Spinner { origin: glam::f32::Vec3::new(0.0, 10.0, 0.0), rotation_rate: glam::f32::Vec3::new(1.0 / 12.0, 1.0 / 40.0, 0.0), sway_amplitude: 1.5, sway_rate: 1.0 / 10.0 });
+
+                let sphere = collision::Sphere::new(glam::f32::Vec3::ZERO, 1.0);
+                level.spawn(entities, Transform::from_position(glam::f32::Vec3::new(-2.0, 0.0, 0.0)), Some(Renderable::Collider), Some(Collider::new(ColliderShape::Sphere(sphere), false)));
+                let capsule = collision::Capsule::new(glam::f32::Vec3::new(0.0, -2.0, 0.0), glam::f32::Vec3::new(0.0, 2.0, 0.0), 1.0);
+                level.spawn(entities, Transform::from_position(glam::f32::Vec3::new(0.0, 3.0, 0.0)), Some(Renderable::Collider), Some(Collider::new(ColliderShape::Capsule(capsule), true)));
+
+                let transform = Transform::new(glam::f32::Vec3::new(2.0, 0.0, 0.0), glam::f32::Quat::from_rotation_x(90.0_f32.to_radians()), glam::f32::Vec3::new(0.1, 0.1, 0.1));
+                let roll = level.spawn(entities, transform, Some(Renderable::skeleton_model("Roll_Caskett", "Roll_Caskett")), None);
+                entities.insert(roll, SkeletonAnimation { model_name: "Roll_Caskett".to_string(), speed: 1.0 });
+            }
+            _ => return None,
+        }
+
+        Some(level)
+    }
+
+    fn spawn(&mut self, entities: &mut EntityStore, transform: Transform, renderable: Option<Renderable>, collider: Option<Collider>) -> EntityId {
+
+        let id = entities.spawn();
+        entities.insert(id, transform);
+        if let Some(renderable) = renderable {
+            entities.insert(id, renderable);
+        }
+        if let Some(collider) = collider {
+            entities.insert(id, collider);
+        }
+        self.entities.push(id);
+
+        id
+    }
+
+    //Despawns whatever the map spawned that's still around
+    pub fn unload(self, entities: &mut EntityStore) {
+
+        for id in self.entities {
+            entities.despawn(id);
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+
+        &self.name
+    }
+
+    pub fn get_entities(&self) -> &Vec<EntityId> {
+
+        &self.entities
+    }
+
+    pub fn get_spawn_points(&self) -> &Vec<glam::f32::Vec3> {
+
+        &self.spawn_points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unloading_takes_only_the_level_with_it() {
+
+        let mut entities = EntityStore::new();
+        let player = entities.spawn();
+        entities.insert(player, PlayerControl::Local);
+
+        let level = Level::load("test_triangle", &mut entities).unwrap();
+        assert_eq!(level.get_name(), "test_triangle");
+        assert_eq!(entities.get_entity_count(), level.get_entities().len() + 1);
+        assert!(level.get_entities().iter().all(|id| entities.get::<Transform>(*id).is_some()));
+
+        level.unload(&mut entities);
+        assert_eq!(entities.get_entity_count(), 1);
+        assert!(entities.is_alive(player));
+
+        assert!(Level::load("no_such_map", &mut entities).is_none());
+        assert_eq!(entities.get_entity_count(), 1);
+    }
+
+    #[test]
+    fn spawns_away_from_enemies() {
+
+        let spawn_points = [glam::f32::Vec3::ZERO, glam::f32::Vec3::new(10.0, 0.0, 0.0), glam::f32::Vec3::new(-10.0, 0.0, 0.0)];
+
+        assert_eq!(choose_spawn_point(&spawn_points, &[]), Some(spawn_points[0]));
+        assert_eq!(choose_spawn_point(&spawn_points, &[glam::f32::Vec3::new(9.0, 0.0, 0.0)]), Some(spawn_points[2]));
+        //The closest enemy to each is what counts, not how many are near
+        let enemies = [glam::f32::Vec3::new(-9.0, 0.0, 0.0), glam::f32::Vec3::new(1.0, 0.0, 0.0), glam::f32::Vec3::new(2.0, 0.0, 0.0)];
+        assert_eq!(choose_spawn_point(&spawn_points, &enemies), Some(spawn_points[1]));
+        assert_eq!(choose_spawn_point(&[], &enemies), None);
+    }
+}
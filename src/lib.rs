@@ -0,0 +1,37 @@
+#[cfg(feature = "client")]
+pub mod render_state;
+pub mod texture;
+pub mod camera;
+pub mod gpu_types;
+pub mod game_state;
+pub mod render_commands;
+pub mod model;
+pub mod resource_manager;
+pub mod player;
+pub mod collision;
+pub mod input;
+#[cfg(feature = "client")]
+pub mod quad_renderer;
+pub mod collision_world;
+pub mod console;
+#[cfg(feature = "client")]
+pub mod audio;
+pub mod net;
+pub mod dedicated_server;
+pub mod prediction;
+pub mod interpolation;
+pub mod user_cmd;
+pub mod clock_sync;
+pub mod lag_compensation;
+pub mod validation;
+pub mod rcon;
+pub mod chat;
+pub mod states;
+pub mod entity;
+pub mod systems;
+pub mod level;
+pub mod character_controller;
+pub mod movement;
+pub mod weapon;
+pub mod damage;
+pub mod hitbox;
@@ -1,32 +1,46 @@
+#[cfg(feature = "client")]
 use std::{cell::RefCell, rc::Rc};
 
+#[cfg(feature = "client")]
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
-mod render_state;
-mod texture;
-mod camera;
-mod gpu_types;
-mod game_state;
-mod render_commands;
-mod model;
-mod resource_manager;
-mod player;
-mod collision;
-mod input;
-mod quad_renderer;
-mod collision_world;
-mod console;
-mod audio;
+use mp_first_person_shooter::{dedicated_server, net};
+#[cfg(feature = "client")]
+use mp_first_person_shooter::{audio, chat, console, game_state, input, render_state, resource_manager, states};
 
 //Look at cpal for audio
 
 fn main() {
     env_logger::init();
-    
+
+    //--dedicated [address] runs a headless server, no window, GPU or audio, builds without the client feature can't do anything else
+    //--rcon password lets admins run console commands over TCP, --rcon-log file keeps a record of what they did
+    let args: Vec<String> = std::env::args().collect();
+    //--name sets what LAN server browsers show
+    let default_name = "mp_first_person_shooter server".to_string();
+    let name = args.iter().position(|arg| arg == "--name").and_then(|i| args.get(i + 1)).unwrap_or(&default_name);
+    let dedicated = args.iter().position(|arg| arg == "--dedicated");
+
+    #[cfg(feature = "client")]
+    if dedicated.is_none() {
+        run_client(&args, name);
+        return;
+    }
+
+    let default_address = format!("0.0.0.0:{}", net::DEFAULT_PORT);
+    let address = dedicated.and_then(|i| args.get(i + 1)).filter(|arg| !arg.starts_with("--")).unwrap_or(&default_address);
+    let rcon_password = args.iter().position(|arg| arg == "--rcon").and_then(|i| args.get(i + 1)).map(|arg| arg.as_str());
+    let rcon_log = args.iter().position(|arg| arg == "--rcon-log").and_then(|i| args.get(i + 1)).map(|arg| arg.as_str());
+    dedicated_server::run(address, name, rcon_password, rcon_log);
+}
+
+#[cfg(feature = "client")]
+fn run_client(args: &[String], name: &str) {
+
     //I wish this and the resource manager could be global without unsafe 
     let console = Rc::new(RefCell::new(console::Console::new()));
 
@@ -47,6 +61,30 @@ fn main() {
 
     let mut game_state = game_state::GameState::new(console.clone());
 
+    //--host 0.0.0.0:27015 to run a listen server, --connect 127.0.0.1:27015 to join one
+    for i in 0..args.len().saturating_sub(1) {
+        let network = match args[i].as_str() {
+            "--host" => net::NetServer::bind(args[i + 1].as_str()).map(net::Network::Server),
+            "--connect" => match args[i + 1].parse() {
+                Ok(address) => net::NetClient::new(address).map(net::Network::Client),
+                Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)),
+            },
+            _ => continue,
+        };
+        match network {
+            Ok(network) => {
+                if let net::Network::Server(_) = network {
+                    match net::discovery::DiscoveryResponder::bind(("0.0.0.0", net::discovery::DISCOVERY_PORT), name, resource_manager::DEFAULT_MAP) {
+                        Ok(discovery) => game_state.set_discovery(discovery),
+                        Err(e) => console.borrow_mut().output_to_console(&format!("LAN server browsers won't see this server: {}", e)),
+                    }
+                }
+                game_state.set_network(network);
+            }
+            Err(e) => console.borrow_mut().output_to_console(&format!("Failed to start networking: {}", e)),
+        }
+    }
+
     let mut inputs = input::Inputs::new();
 
     let mut audio = audio::AudioState::new();
@@ -62,18 +100,55 @@ fn main() {
 
     let mut console_text = "".to_string();
 
+    //Some while the chat box is open, Enter for all chat and U for team chat
+    let mut chat_input: Option<(chat::ChatChannel, String)> = None;
+
+    //LAN servers, sorted by ping until a column header is clicked
+    let mut server_browser = net::discovery::ServerBrowser::new().ok();
+    let mut server_sort = (net::discovery::ServerSortKey::Ping, false);
+    if let Some(Err(e)) = server_browser.as_mut().map(|server_browser| server_browser.refresh()) {
+        console.borrow_mut().output_to_console(&format!("Failed to look for LAN servers: {}", e));
+    }
+
     event_loop.run(move |event, elwt| match event {
         Event::WindowEvent {
             ref event,
             window_id,
         } if window_id == render_state.get_window().id() => {
 
-            if cursor_visible {
+            if cursor_visible || chat_input.is_some() {
 
                 let _ = platform.on_window_event(render_state.get_window(), event);
             }
 
             match event {
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            state: ElementState::Pressed,
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } if chat_input.is_some() => {
+                    chat_input = None;
+                }
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            state: ElementState::Pressed,
+                            physical_key: winit::keyboard::PhysicalKey::Code(key_code @ (winit::keyboard::KeyCode::Enter | winit::keyboard::KeyCode::KeyU)),
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } if chat_input.is_none() && !cursor_visible => {
+                    let channel = if *key_code == winit::keyboard::KeyCode::KeyU { chat::ChatChannel::Team } else { chat::ChatChannel::All };
+                    chat_input = Some((channel, "".to_string()));
+                    inputs.release_all();
+                }
+                //Everything typed goes to the chat box
+                WindowEvent::KeyboardInput { .. } if chat_input.is_some() => {}
                 WindowEvent::CloseRequested
                 | WindowEvent::KeyboardInput {
                     event:
@@ -134,7 +209,7 @@ fn main() {
                 //    inner_size_writer.request_inner_size(render_state.get_size()).unwrap();
                 //}
                 WindowEvent::KeyboardInput { event, .. } => {
-                    if !cursor_visible {
+                    if !cursor_visible && chat_input.is_none() {
                         inputs.keyboard_input(event);
                     }
                 }
@@ -143,12 +218,61 @@ fn main() {
                     //EGUI
                     let mut selected = resource_manager.get_skeleton_model("Roll_Caskett").unwrap().get_animation_controller().get_current_animation().to_owned();
                     let raw_input = platform.take_egui_input(render_state.get_window());
+                    if let Some(server_browser) = server_browser.as_mut() {
+                        server_browser.poll();
+                    }
+                    let mut join = None;
+                    let mut chat_submit = None;
+                    let mut menu_state = None;
                     let full_output = egui_context.run(raw_input, |egui_context| {
                         egui::Window::new("Debug Stats").show(&egui_context, |ui| {
                             ui.label("Frame time: ".to_string() + &game_state.get_delta_time().to_string());
+                            match game_state.get_state_time_left() {
+                                Some(time_left) => ui.label(format!("State: {}, round {}, {:.0}s left", game_state.get_state().get_name(), game_state.get_round(), time_left)),
+                                None => ui.label(format!("State: {}, round {}", game_state.get_state().get_name(), game_state.get_round())),
+                            };
+                            let health = game_state.get_player().get_health();
+                            if health.is_dead() {
+                                ui.label("Dead, respawning");
+                            }
+                            else {
+                                ui.label(format!("Health: {:.0} Armor: {:.0}", health.get_health(), health.get_armor()));
+                            }
+                            let weapons = game_state.get_player().get_weapons();
+                            let definition = weapons.get_definition();
+                            ui.label(format!("Weapon: {} {}/{}{}", definition.name, weapons.get_ammo(), definition.magazine_size, if weapons.is_reloading() { " (reloading)" } else { "" }));
                             ui.label("FPS: ".to_string() + &(1.0 / (game_state.get_delta_time() / 1000.0)).to_string());
                             ui.label("Number of render commands: ".to_string() + &(game_state.get_render_commands().len().to_string()));
                             ui.label(console.borrow().get_timings_string());
+                            match game_state.get_network() {
+                                Some(net::Network::Server(server)) => {
+                                    ui.label(format!("Hosting: {}/{} clients", server.get_client_count(), server.get_max_clients()));
+                                    let mut player_ids = server.get_player_ids();
+                                    player_ids.sort();
+                                    for player_id in player_ids {
+                                        ui.label(format!("Player {}: last heard {} ms ago", player_id, server.get_time_since_received(player_id).unwrap_or_default().as_millis()));
+                                    }
+                                }
+                                Some(net::Network::Client(client)) => {
+                                    ui.label(format!("Connection: {:?}", client.get_state()));
+                                    if let Some(since_received) = client.get_time_since_received() {
+                                        ui.label(format!("Last heard from server {} ms ago", since_received.as_millis()));
+                                    }
+                                    let clock_sync = game_state.get_clock_sync();
+                                    let now = std::time::Instant::now();
+                                    if let (Some(offset), Some(round_trip), Some(desired_tick_lead)) = (clock_sync.get_offset(game_state.get_fractional_tick(), now), clock_sync.get_round_trip(), clock_sync.get_desired_tick_lead()) {
+                                        ui.label(format!("Clock offset: {:.2} ticks, RTT: {} ms", offset, round_trip.as_millis()));
+                                        ui.label(format!("Tick lead: {:.2} (target {:.2}), tick interval {:.3} ms", -offset, desired_tick_lead, game_state.get_tick_interval()));
+                                    }
+                                }
+                                None => {
+                                    ui.label("Offline");
+                                }
+                            }
+                            if let Some(simulator) = game_state.get_network().and_then(|network| network.get_socket().get_simulator()) {
+                                let conditions = simulator.get_conditions();
+                                ui.label(format!("Simulating {}ms +{}ms jitter, {}% loss, {} dropped, {} duplicated", conditions.latency.as_millis(), conditions.jitter.as_millis(), conditions.loss * 100.0, simulator.get_dropped_count(), simulator.get_duplicated_count()));
+                            }
                             egui::ComboBox::from_label("Current animation").selected_text(format!("{:?}", selected)).show_ui(ui, |ui| {
                                 for anim in resource_manager.get_skeleton_model("Roll_Caskett").unwrap().get_animation_controller().get_animations() {
                                     ui.selectable_value(&mut selected, anim.to_string(), anim);
@@ -161,14 +285,122 @@ fn main() {
                             });
                             if ui.text_edit_singleline(&mut console_text).lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
                                 if !console_text.is_empty() {
-                                    console.borrow_mut().output_to_console(&console_text);
+                                    console.borrow_mut().submit_command(&console_text);
                                     console_text.clear();
 
                                 }
                             }
                         });
+                        let scoreboard_rows = game_state.get_scoreboard().get_rows();
+                        if !scoreboard_rows.is_empty() {
+                            egui::Window::new("Scoreboard").anchor(egui::Align2::RIGHT_TOP, egui::Vec2::new(-10.0, 10.0)).resizable(false).show(egui_context, |ui| {
+                                for (player_id, score) in scoreboard_rows {
+                                    ui.label(format!("Player {}: {} kills, {} deaths", player_id, score.kills, score.deaths));
+                                }
+                            });
+                        }
+                        //Recent lines fade out, the whole history shows while typing
+                        egui::Area::new("Chat").anchor(egui::Align2::LEFT_BOTTOM, egui::Vec2::new(10.0, -40.0)).interactable(chat_input.is_some()).show(egui_context, |ui| {
+                            ui.set_max_width(400.0);
+                            match chat_input.as_mut() {
+                                Some((channel, text)) => {
+                                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                        egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show(ui, |ui| {
+                                            for line in game_state.get_chat_log().get_history() {
+                                                ui.label(line.get_display_text());
+                                            }
+                                        });
+                                        let response = ui.horizontal(|ui| {
+                                            ui.label(format!("{}:", channel.get_name()));
+                                            ui.add(egui::TextEdit::singleline(text).char_limit(chat::MAX_CHAT_LENGTH))
+                                        }).inner;
+                                        response.request_focus();
+                                        if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                            chat_submit = Some((*channel, text.clone()));
+                                        }
+                                    });
+                                }
+                                None => {
+                                    for (line, alpha) in game_state.get_chat_log().get_visible(std::time::Instant::now()) {
+                                        ui.label(egui::RichText::new(line.get_display_text()).color(egui::Color32::WHITE.gamma_multiply(alpha)).background_color(egui::Color32::from_black_alpha((160.0 * alpha) as u8)));
+                                    }
+                                }
+                            }
+                        });
+                        //Menus and loading screens, the world isn't drawn in these states
+                        if !game_state.get_state().renders_world() {
+                            egui::Window::new("mp_first_person_shooter").anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0.0, 40.0)).collapsible(false).resizable(false).show(egui_context, |ui| {
+                                match (game_state.get_state(), game_state.get_network()) {
+                                    (states::States::Connecting, Some(net::Network::Client(client))) => {
+                                        ui.label(format!("Connecting to {}", client.get_server_address()));
+                                    }
+                                    (states::States::Loading, _) => {
+                                        ui.label("Waiting for the server");
+                                    }
+                                    _ => {
+                                        if ui.button("Play offline").clicked() {
+                                            menu_state = Some(states::States::Warmup);
+                                        }
+                                    }
+                                }
+                                if game_state.get_state() != states::States::MainMenu && ui.button("Cancel").clicked() {
+                                    menu_state = Some(states::States::MainMenu);
+                                }
+                            });
+                        }
+                        if game_state.get_state() == states::States::MainMenu {
+                            egui::Window::new("Server Browser").show(egui_context, |ui| {
+                                let Some(server_browser) = server_browser.as_mut() else {
+                                    ui.label("Can't look for LAN servers, see the console");
+                                    return;
+                                };
+                                if ui.button("Refresh").clicked() {
+                                    if let Err(e) = server_browser.refresh() {
+                                        console.borrow_mut().output_to_console(&format!("Failed to look for LAN servers: {}", e));
+                                    }
+                                }
+                                egui::Grid::new("Servers").striped(true).show(ui, |ui| {
+                                    for (label, sort_key) in [("Name", net::discovery::ServerSortKey::Name), ("Map", net::discovery::ServerSortKey::Map), ("Players", net::discovery::ServerSortKey::Players), ("Ping", net::discovery::ServerSortKey::Ping)] {
+                                        let arrow = match server_sort {
+                                            (current, false) if current == sort_key => " ^",
+                                            (current, true) if current == sort_key => " v",
+                                            _ => "",
+                                        };
+                                        if ui.button(format!("{}{}", label, arrow)).clicked() {
+                                            //Clicking the same column again flips it
+                                            server_sort = (sort_key, server_sort.0 == sort_key && !server_sort.1);
+                                        }
+                                    }
+                                    ui.end_row();
+                                    for server in server_browser.get_servers(server_sort.0, server_sort.1) {
+                                        ui.label(&server.info.name);
+                                        ui.label(&server.info.map);
+                                        ui.label(format!("{}/{}", server.info.player_count, server.info.max_players));
+                                        ui.label(format!("{} ms", server.ping.as_millis()));
+                                        let join_button = ui.add_enabled(server.is_compatible(), egui::Button::new("Join"));
+                                        if join_button.on_disabled_hover_text(format!("Server is on protocol version {}, this is version {}", server.info.protocol_version, net::PROTOCOL_VERSION)).clicked() {
+                                            join = Some(server.address);
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                            });
+                        }
                     });
                     resource_manager.get_mut_skeleton_model("Roll_Caskett").unwrap().get_mut_animation_controller().set_current_animation(&selected);
+                    if let Some(state) = menu_state {
+                        game_state.set_state(state, "menu");
+                    }
+                    if let Some((channel, text)) = chat_submit {
+                        game_state.send_chat(channel, &text);
+                        chat_input = None;
+                    }
+                    if let Some(address) = join {
+                        match net::NetClient::new(address) {
+                            Ok(client) => game_state.set_network(net::Network::Client(client)),
+                            Err(e) => console.borrow_mut().output_to_console(&format!("Failed to start networking: {}", e)),
+                        }
+                    }
                     platform.handle_platform_output(render_state.get_window(), full_output.platform_output);
                     let clipped_primitives = egui_context.tessellate(full_output.shapes, full_output.pixels_per_point);
 
@@ -184,7 +416,7 @@ fn main() {
         }
         Event::DeviceEvent { event, .. } => {
 
-            if !cursor_visible {
+            if !cursor_visible && chat_input.is_none() {
                 match event {
                     DeviceEvent::Motion { axis, value } => {
                         inputs.mouse_motion_input(axis, value);
@@ -192,6 +424,12 @@ fn main() {
                     DeviceEvent::Button { button, state } => {
                         inputs.mouse_input(button, state);
                     }
+                    DeviceEvent::MouseWheel { delta: winit::event::MouseScrollDelta::LineDelta(_, y) } => {
+                        inputs.mouse_wheel_input(y);
+                    }
+                    DeviceEvent::MouseWheel { delta: winit::event::MouseScrollDelta::PixelDelta(position) } => {
+                        inputs.mouse_wheel_input(position.y as f32 / input::PIXELS_PER_WHEEL_STEP);
+                    }
                     _ => {}
                 }
             }
@@ -5,17 +5,18 @@ use wgpu::util::DeviceExt;
 use crate::gpu_types;
 use crate::collision;
 
-pub struct Model {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    indices_count: u32,
+//CPU side mesh data straight out of the gltf, no GPU needed so the dedicated server can use it for collision
+pub struct MeshData {
+    vertices: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+    texture_coordinates: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
     textures: Vec<String>,
-    collision: Option<collision::TriangleSoup>,
 }
 
-impl Model {
+impl MeshData {
 
-    pub fn new(device: &wgpu::Device, path: &str, with_collision: bool) -> Self {
+    pub fn new(path: &str) -> Self {
 
         let (document, buffers, _) = gltf::import(path).unwrap();
         let mut vertices: Vec<[f32; 3]> = Vec::new();
@@ -55,10 +56,43 @@ impl Model {
             }
         }
 
+        let mut textures = Vec::new();
+
+        for texture in document.textures() {
+            if texture.source().name().is_some() {
+                textures.push(String::from(texture.source().name().unwrap()));
+            }
+        }
+
+        Self { vertices, indices, texture_coordinates, normals, textures }
+    }
+
+    pub fn generate_triangle_soup(&self) -> collision::TriangleSoup {
+
+        let mut triangles = Vec::new();
+        for i in (0..self.indices.len()).step_by(3) {
+
+            triangles.push(collision::Triangle::new(self.vertices[self.indices[i] as usize].into(), self.vertices[self.indices[i + 1] as usize].into(), self.vertices[self.indices[i + 2] as usize].into()));
+        }
+        collision::TriangleSoup::new(triangles)
+    }
+}
+
+pub struct Model {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    indices_count: u32,
+    textures: Vec<String>,
+}
+
+impl Model {
+
+    pub fn new(device: &wgpu::Device, mesh_data: &MeshData) -> Self {
+
         let mut weaved_vertices: Vec<gpu_types::Vertex> = Vec::new();
 
-        for i in 0..vertices.len() {
-            weaved_vertices.push(gpu_types::Vertex { position: vertices[i], normal: normals[i], texture_coordinates: texture_coordinates[i] });
+        for i in 0..mesh_data.vertices.len() {
+            weaved_vertices.push(gpu_types::Vertex { position: mesh_data.vertices[i], normal: mesh_data.normals[i], texture_coordinates: mesh_data.texture_coordinates[i] });
         }
 
         let vertex_buffer = device.create_buffer_init(
@@ -72,41 +106,12 @@ impl Model {
         let index_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Index buffer"),
-                contents: bytemuck::cast_slice(&indices),
+                contents: bytemuck::cast_slice(&mesh_data.indices),
                 usage: wgpu::BufferUsages::INDEX,
             }
         );
 
-        let mut textures = Vec::new();
-
-        for texture in document.textures() {
-            if texture.source().name().is_some() {
-                textures.push(String::from(texture.source().name().unwrap()));
-            }
-        }
-
-        let mut collision = None;
-
-        if with_collision {
-            collision = Some(Model::generate_triangle_soup(&vertices, &indices));
-        }
-
-        Self { vertex_buffer, index_buffer, indices_count: indices.len() as u32, textures, collision }
-    }
-    
-    pub fn generate_triangle_soup(vertices: &Vec<[f32; 3]>, indices: &Vec<u32>) -> collision::TriangleSoup {
-
-        let mut triangles = Vec::new();
-        for i in (0..indices.len()).step_by(3) {
-
-            triangles.push(collision::Triangle::new(vertices[indices[i] as usize].into(), vertices[indices[i + 1] as usize].into(), vertices[indices[i + 2] as usize].into()));
-        }
-        collision::TriangleSoup::new(triangles)
-    }
-
-    pub fn get_collision(&self) -> &collision::TriangleSoup {
-
-        &self.collision.as_ref().unwrap()
+        Self { vertex_buffer, index_buffer, indices_count: mesh_data.indices.len() as u32, textures: mesh_data.textures.clone() }
     }
 
     pub fn get_vertex_buffer(&self) -> &wgpu::Buffer {
@@ -136,12 +141,152 @@ pub struct JointsUniform {
     pub joints: [[[f32; 4]; 4]; 64], 
 }
 
+//Keyframes are this far apart, the animation controller and get_pose both count time in them
+pub const ANIMATION_FRAMES_PER_SECOND: f32 = 20.0;
+
+//CPU side skeleton and animations, no GPU needed so the dedicated server can pose hitboxes from it
+#[derive(Debug, Clone)]
+pub struct SkeletonData {
+    skeleton: Skeleton,
+    //Same order as the matrices set_joints_to_pose gives back
+    bone_names: Vec<String>,
+    animations: Vec<String>,
+}
+
+impl SkeletonData {
+
+    pub fn new(path: &str) -> Self {
+
+        let (document, buffers, _) = gltf::import(path).unwrap();
+
+        Self::from_document(&document, &buffers)
+    }
+
+    fn from_document(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Self {
+
+        let mut skeletons = Vec::new();
+
+        for skin in document.skins() {
+
+            let mut inverse_bind_matrices: Vec<glam::f32::Mat4> = Vec::new();
+
+            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            if let Some(iter) = reader.read_inverse_bind_matrices() {
+                for inverse_bind_matrix in iter {
+                    inverse_bind_matrices.push(glam::f32::Mat4::from_cols_array_2d(&inverse_bind_matrix));
+                }
+            }
+
+            skeletons.push(Skeleton::new(&skin.joints().nth(0).unwrap(), inverse_bind_matrices));
+        }
+
+        if skeletons.len() > 1 {
+            panic!("More than one skeleton, need to add compatibility for this");
+        }
+
+        let mut animations = Vec::new();
+
+        for animation in document.animations() {
+
+            let animation_name = animation.name().unwrap_or("None");
+
+            animations.push(animation_name.to_string());
+
+            for channel in animation.channels() {
+
+                let bone = skeletons[0].get_bone_by_id(channel.target().node().index()).unwrap();
+
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+
+                if let Some(gltf::animation::util::ReadOutputs::Translations(iter)) = reader.read_outputs() {
+                    for translation in iter {
+                        if bone.animations_translation.contains_key(animation_name) {
+                            bone.animations_translation.get_mut(animation_name).unwrap().push(glam::f32::Vec3::from(translation));
+                        }
+                        else {
+                            bone.animations_translation.insert(animation_name.to_string(), vec![glam::f32::Vec3::from(translation)]);
+                        }
+                    }
+                }
+
+                if let Some(gltf::animation::util::ReadOutputs::Rotations(iter)) = reader.read_outputs() {
+                    for rotation in iter.into_f32() {
+                        if bone.animations_rotation.contains_key(animation_name) {
+                            bone.animations_rotation.get_mut(animation_name).unwrap().push(glam::f32::Quat::from_array(rotation));
+                        }
+                        else {
+                            bone.animations_rotation.insert(animation_name.to_string(), vec![glam::f32::Quat::from_array(rotation)]);
+                        }
+                    }
+                }
+
+                if let Some(gltf::animation::util::ReadOutputs::Scales(iter)) = reader.read_outputs() {
+                    for scale in iter {
+                        if bone.animations_scale.contains_key(animation_name) {
+                            bone.animations_scale.get_mut(animation_name).unwrap().push(glam::f32::Vec3::from(scale));
+                        }
+                        else {
+                            bone.animations_scale.insert(animation_name.to_string(), vec![glam::f32::Vec3::from(scale)]);
+                        }
+                    }
+                }
+            }
+        }
+
+
+        Self::from_skeleton(skeletons.remove(0), animations)
+    }
+
+    pub fn from_skeleton(skeleton: Skeleton, animations: Vec<String>) -> Self {
+
+        let mut bone_names = Vec::new();
+        skeleton.root_bone.get_names(&mut bone_names);
+
+        Self { skeleton, bone_names, animations }
+    }
+
+    pub fn get_skeleton(&self) -> &Skeleton {
+
+        &self.skeleton
+    }
+
+    pub fn get_bone_names(&self) -> &Vec<String> {
+
+        &self.bone_names
+    }
+
+    pub fn get_animations(&self) -> &Vec<String> {
+
+        &self.animations
+    }
+
+    //Model space matrix of every bone at time seconds into the animation, empty if there's no animation by that name
+    pub fn get_pose(&self, animation: &str, time: f32) -> Vec<glam::f32::Mat4> {
+
+        let mut pose = Vec::new();
+        if self.animations.iter().any(|name| name == animation) {
+            self.skeleton.set_joints_to_pose(&mut pose, animation, time * ANIMATION_FRAMES_PER_SECOND);
+        }
+
+        pose
+    }
+
+    //Where the bone is in a pose from get_pose, None if there's no bone by that name
+    pub fn get_bone_matrix(&self, pose: &[glam::f32::Mat4], name: &str) -> Option<glam::f32::Mat4> {
+
+        let index = self.bone_names.iter().position(|bone_name| bone_name == name)?;
+
+        pose.get(index).copied()
+    }
+}
+
 pub struct SkeletonModel {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     indices_count: u32,
     textures: Vec<String>,
-    skeleton: Skeleton,
+    skeleton_data: SkeletonData,
     joints: JointsUniform,
     joints_uniform_buffer: wgpu::Buffer, 
     joints_uniform_bind_group: wgpu::BindGroup,
@@ -236,75 +381,7 @@ impl SkeletonModel {
             }
         }
 
-        let mut skeletons = Vec::new();
-
-        for skin in document.skins() {
-
-            let mut inverse_bind_matrices: Vec<glam::f32::Mat4> = Vec::new();
-
-            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
-
-            if let Some(iter) = reader.read_inverse_bind_matrices() {
-                for inverse_bind_matrix in iter {
-                    inverse_bind_matrices.push(glam::f32::Mat4::from_cols_array_2d(&inverse_bind_matrix));
-                }
-            }
-
-            skeletons.push(Skeleton::new(&skin.joints().nth(0).unwrap(), inverse_bind_matrices));
-        }
-
-        if skeletons.len() > 1 {
-            panic!("More than one skeleton, need to add compatibility for this");
-        }
-
-        let mut animations = Vec::new();
-
-        for animation in document.animations() {
-
-            let animation_name = animation.name().unwrap_or("None");
-
-            animations.push(animation_name.to_string());
-
-            for channel in animation.channels() {
-
-                let bone = skeletons[0].get_bone_by_id(channel.target().node().index()).unwrap();
-
-                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
-
-                if let Some(gltf::animation::util::ReadOutputs::Translations(iter)) = reader.read_outputs() {
-                    for translation in iter {
-                        if bone.animations_translation.contains_key(animation_name) {
-                            bone.animations_translation.get_mut(animation_name).unwrap().push(glam::f32::Vec3::from(translation));
-                        }
-                        else {
-                            bone.animations_translation.insert(animation_name.to_string(), vec![glam::f32::Vec3::from(translation)]);
-                        }
-                    }
-                }
-
-                if let Some(gltf::animation::util::ReadOutputs::Rotations(iter)) = reader.read_outputs() {
-                    for rotation in iter.into_f32() {
-                        if bone.animations_rotation.contains_key(animation_name) {
-                            bone.animations_rotation.get_mut(animation_name).unwrap().push(glam::f32::Quat::from_array(rotation));
-                        }
-                        else {
-                            bone.animations_rotation.insert(animation_name.to_string(), vec![glam::f32::Quat::from_array(rotation)]);
-                        }
-                    }
-                }
-
-                if let Some(gltf::animation::util::ReadOutputs::Scales(iter)) = reader.read_outputs() {
-                    for scale in iter {
-                        if bone.animations_scale.contains_key(animation_name) {
-                            bone.animations_scale.get_mut(animation_name).unwrap().push(glam::f32::Vec3::from(scale));
-                        }
-                        else {
-                            bone.animations_scale.insert(animation_name.to_string(), vec![glam::f32::Vec3::from(scale)]);
-                        }
-                    }
-                }
-            }
-        }
+        let skeleton_data = SkeletonData::from_document(&document, &buffers);
 
         let joints = JointsUniform { joints: [glam::f32::Mat4::IDENTITY.to_cols_array_2d(); 64] };
 
@@ -343,9 +420,9 @@ impl SkeletonModel {
             label: Some("Joint uniform bind group"),
         });
 
-        let animation_controller = AnimationController::new(animations);
+        let animation_controller = AnimationController::new(skeleton_data.get_animations().clone());
 
-        Self { vertex_buffer, index_buffer, indices_count: indices.len() as u32, textures, skeleton: skeletons[0].clone(), joints, joints_uniform_buffer, joints_uniform_bind_group, animation_controller }
+        Self { vertex_buffer, index_buffer, indices_count: indices.len() as u32, textures, skeleton_data, joints, joints_uniform_buffer, joints_uniform_bind_group, animation_controller }
     }
 
     pub fn get_vertex_buffer(&self) -> &wgpu::Buffer {
@@ -367,16 +444,22 @@ impl SkeletonModel {
 
         let mut joints_temp = Vec::new();
 
-        self.animation_controller.update_time(time * 20.0);
+        self.animation_controller.update_time(time * ANIMATION_FRAMES_PER_SECOND);
 
-        self.skeleton.set_joints_to_pose(&mut joints_temp, &self.animation_controller.get_current_animation(), self.animation_controller.time);
+        let skeleton = self.skeleton_data.get_skeleton();
+        skeleton.set_joints_to_pose(&mut joints_temp, self.animation_controller.get_current_animation(), self.animation_controller.time);
 
-        for i in 0..self.skeleton.inverse_bind_matrices.len() {
+        for (i, inverse_bind_matrix) in skeleton.inverse_bind_matrices.iter().enumerate() {
 
-            self.joints.joints[i] = (joints_temp[i] * self.skeleton.inverse_bind_matrices[i]).to_cols_array_2d();
+            self.joints.joints[i] = (joints_temp[i] * *inverse_bind_matrix).to_cols_array_2d();
         }
     }
 
+    pub fn get_skeleton_data(&self) -> &SkeletonData {
+
+        &self.skeleton_data
+    }
+
     pub fn write_skeleton_buffer(&self, queue: &wgpu::Queue) {
 
         queue.write_buffer(&self.joints_uniform_buffer, 0, bytemuck::cast_slice(&[self.joints]));
@@ -454,6 +537,15 @@ impl Bone {
         Self { child_bones, transform: glam::f32::Mat4::from_cols_array_2d(&bone.transform().matrix()), name: bone.name().unwrap_or("None").to_string(), id: bone.index(), animations_translation: HashMap::new(), animations_rotation: HashMap::new(), animations_scale: HashMap::new() }
     }
 
+    //Depth first, the order set_joints_to_pose pushes matrices in
+    pub fn get_names(&self, names: &mut Vec<String>) {
+
+        names.push(self.name.clone());
+        for bone in &self.child_bones {
+            bone.get_names(names);
+        }
+    }
+
     pub fn visit(&self) {
 
         println!("{}", self.name);
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use crate::character_controller::{GRAVITY, MAX_FALL_SPEED};
+
+//Quake style, the ground gets you up to speed and stops you quickly, the air barely adds speed but lets you turn
+//The server owns these and sends them to clients, prediction is wrong as soon as the two disagree
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MovementSettings {
+    //Units per second, the fastest running on the ground gets you
+    pub max_speed: f32,
+    //How many times max_speed is gained per second on the ground
+    pub accelerate: f32,
+    pub air_accelerate: f32,
+    //Speed the air adds up to along the wish direction, small so strafing turns the velocity and bunny-hopping can gain speed
+    pub air_speed_cap: f32,
+    //Fraction of the speed lost per second on the ground
+    pub friction: f32,
+    //Slower than this friction acts as if going this fast, so players actually come to a stop
+    pub stop_speed: f32,
+    //Upward speed a jump starts with
+    pub jump_impulse: f32,
+    //Replaces max_speed while crouched on the ground
+    pub crouch_max_speed: f32,
+    //Crouching at least this fast on the ground starts a slide
+    pub slide_min_speed: f32,
+    //Replaces friction while sliding
+    pub slide_friction: f32,
+}
+
+impl Default for MovementSettings {
+
+    fn default() -> Self {
+
+        Self { max_speed: 5.0, accelerate: 10.0, air_accelerate: 10.0, air_speed_cap: 0.5, friction: 6.0, stop_speed: 1.5, jump_impulse: 7.0, crouch_max_speed: 2.5, slide_min_speed: 4.0, slide_friction: 0.5 }
+    }
+}
+
+impl MovementSettings {
+
+    pub const NAMES: [&'static str; 10] = ["max_speed", "accelerate", "air_accelerate", "air_speed_cap", "friction", "stop_speed", "jump_impulse", "crouch_max_speed", "slide_min_speed", "slide_friction"];
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+
+        match name {
+            "max_speed" => Some(self.max_speed),
+            "accelerate" => Some(self.accelerate),
+            "air_accelerate" => Some(self.air_accelerate),
+            "air_speed_cap" => Some(self.air_speed_cap),
+            "friction" => Some(self.friction),
+            "stop_speed" => Some(self.stop_speed),
+            "jump_impulse" => Some(self.jump_impulse),
+            "crouch_max_speed" => Some(self.crouch_max_speed),
+            "slide_min_speed" => Some(self.slide_min_speed),
+            "slide_friction" => Some(self.slide_friction),
+            _ => None,
+        }
+    }
+
+    //False if there's no setting by that name
+    pub fn set(&mut self, name: &str, value: f32) -> bool {
+
+        let setting = match name {
+            "max_speed" => &mut self.max_speed,
+            "accelerate" => &mut self.accelerate,
+            "air_accelerate" => &mut self.air_accelerate,
+            "air_speed_cap" => &mut self.air_speed_cap,
+            "friction" => &mut self.friction,
+            "stop_speed" => &mut self.stop_speed,
+            "jump_impulse" => &mut self.jump_impulse,
+            "crouch_max_speed" => &mut self.crouch_max_speed,
+            "slide_min_speed" => &mut self.slide_min_speed,
+            "slide_friction" => &mut self.slide_friction,
+            _ => return false,
+        };
+        *setting = value.max(0.0);
+
+        true
+    }
+}
+
+//Crouching and sliding both use the crouched capsule, sliding has no control and barely any friction until it slows to crouching speed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stance {
+    Standing,
+    Crouching,
+    Sliding,
+}
+
+//What the stance becomes this tick, standing up still has to be checked against the ceiling
+pub fn get_next_stance(stance: Stance, crouch: bool, grounded: bool, speed: f32, settings: &MovementSettings) -> Stance {
+
+    match (crouch, stance) {
+        (false, _) => Stance::Standing,
+        (true, Stance::Standing) if grounded && speed >= settings.slide_min_speed => Stance::Sliding,
+        (true, Stance::Standing) => Stance::Crouching,
+        //Slides carry on through the air and only end on the ground
+        (true, Stance::Sliding) if grounded && speed < settings.crouch_max_speed => Stance::Crouching,
+        (true, stance) => stance,
+    }
+}
+
+//One tick of velocity change, wish is the horizontal direction the player is pushing scaled by how hard, up to 1
+//ground_normal is None in the air, returns true if the player jumped and the caller has to take them off the ground
+//Only plain arithmetic on the inputs so the client and server get the same bits
+pub fn update_velocity(velocity: &mut glam::f32::Vec3, wish: glam::f32::Vec3, ground_normal: Option<glam::f32::Vec3>, jump: bool, stance: Stance, settings: &MovementSettings, delta_time: f32) -> bool {
+
+    let max_speed = if stance == Stance::Standing { settings.max_speed } else { settings.crouch_max_speed };
+    let wish_speed = wish.length().min(1.0) * max_speed;
+    let wish_direction = wish.normalize_or_zero();
+
+    let Some(ground_normal) = ground_normal else {
+        accelerate(velocity, wish_direction, wish_speed, settings.air_speed_cap, settings.air_accelerate, delta_time);
+        velocity.y = (velocity.y - GRAVITY * delta_time).max(-MAX_FALL_SPEED);
+        return false;
+    };
+
+    //Jumping the tick you land skips friction, which is all bunny-hopping is
+    if jump {
+        velocity.y = settings.jump_impulse;
+        accelerate(velocity, wish_direction, wish_speed, settings.air_speed_cap, settings.air_accelerate, delta_time);
+        return true;
+    }
+
+    if stance == Stance::Sliding {
+        //Going along the ground rather than flat, so gravity down the slope can keep the slide going
+        *velocity += glam::f32::Vec3::NEG_Y * GRAVITY * delta_time;
+        *velocity -= ground_normal * velocity.dot(ground_normal);
+        apply_friction(velocity, settings.slide_friction, settings.stop_speed, delta_time);
+        return false;
+    }
+
+    velocity.y = 0.0;
+    apply_friction(velocity, settings.friction, settings.stop_speed, delta_time);
+    accelerate(velocity, wish_direction, wish_speed, wish_speed, settings.accelerate, delta_time);
+
+    false
+}
+
+fn apply_friction(velocity: &mut glam::f32::Vec3, friction: f32, stop_speed: f32, delta_time: f32) {
+
+    let speed = velocity.length();
+    if speed <= f32::EPSILON {
+        return;
+    }
+
+    let drop = speed.max(stop_speed) * friction * delta_time;
+    *velocity *= (speed - drop).max(0.0) / speed;
+}
+
+//Adds speed along the wish direction until the velocity along it reaches speed_cap, whatever is going another way is kept
+//How much is added is still based on the full wish speed so a low cap doesn't also mean slow turning
+fn accelerate(velocity: &mut glam::f32::Vec3, wish_direction: glam::f32::Vec3, wish_speed: f32, speed_cap: f32, acceleration: f32, delta_time: f32) {
+
+    let add_speed = wish_speed.min(speed_cap) - velocity.dot(wish_direction);
+    if add_speed <= 0.0 {
+        return;
+    }
+
+    *velocity += wish_direction * (acceleration * wish_speed * delta_time).min(add_speed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+
+    fn get_horizontal_speed(velocity: glam::f32::Vec3) -> f32 {
+
+        glam::f32::Vec2::new(velocity.x, velocity.z).length()
+    }
+
+    #[test]
+    fn running_tops_out_at_max_speed_and_friction_stops_it() {
+
+        let settings = MovementSettings::default();
+        let mut velocity = glam::f32::Vec3::ZERO;
+
+        for _ in 0..120 {
+            update_velocity(&mut velocity, glam::f32::Vec3::X, Some(glam::f32::Vec3::Y), false, Stance::Standing, &settings, DELTA_TIME);
+        }
+        assert!((get_horizontal_speed(velocity) - settings.max_speed).abs() < 0.0001, "{:?}", velocity);
+
+        //Diagonal input is no faster
+        for _ in 0..120 {
+            update_velocity(&mut velocity, glam::f32::Vec3::new(1.0, 0.0, 1.0), Some(glam::f32::Vec3::Y), false, Stance::Standing, &settings, DELTA_TIME);
+        }
+        assert!(get_horizontal_speed(velocity) <= settings.max_speed + 0.0001, "{:?}", velocity);
+
+        for _ in 0..60 {
+            update_velocity(&mut velocity, glam::f32::Vec3::ZERO, Some(glam::f32::Vec3::Y), false, Stance::Standing, &settings, DELTA_TIME);
+        }
+        assert_eq!(velocity, glam::f32::Vec3::ZERO);
+    }
+
+    #[test]
+    fn air_strafing_turns_and_gains_speed_but_holding_forward_does_not() {
+
+        let settings = MovementSettings::default();
+
+        let mut velocity = glam::f32::Vec3::X * settings.max_speed;
+        for _ in 0..60 {
+            update_velocity(&mut velocity, glam::f32::Vec3::X, None, false, Stance::Standing, &settings, DELTA_TIME);
+        }
+        assert!((get_horizontal_speed(velocity) - settings.max_speed).abs() < 0.0001, "{:?}", velocity);
+
+        //Always pushing sideways to where we're going, like turning the mouse while strafing
+        let mut velocity = glam::f32::Vec3::X * settings.max_speed;
+        for _ in 0..60 {
+            let wish = glam::f32::Vec3::Y.cross(velocity).normalize();
+            update_velocity(&mut velocity, wish, None, false, Stance::Standing, &settings, DELTA_TIME);
+        }
+        assert!(get_horizontal_speed(velocity) > settings.max_speed * 1.2, "{:?}", velocity);
+        assert!(velocity.z.abs() > 1.0, "{:?}", velocity);
+    }
+
+    #[test]
+    fn jumping_on_landing_skips_friction() {
+
+        let settings = MovementSettings::default();
+        let fast = glam::f32::Vec3::X * settings.max_speed * 2.0;
+
+        let mut velocity = fast;
+        assert!(update_velocity(&mut velocity, glam::f32::Vec3::ZERO, Some(glam::f32::Vec3::Y), true, Stance::Standing, &settings, DELTA_TIME));
+        assert_eq!(velocity, glam::f32::Vec3::new(fast.x, settings.jump_impulse, 0.0));
+
+        //Landing without jumping loses speed straight away
+        let mut velocity = fast;
+        assert!(!update_velocity(&mut velocity, glam::f32::Vec3::ZERO, Some(glam::f32::Vec3::Y), false, Stance::Standing, &settings, DELTA_TIME));
+        assert!(velocity.x < fast.x && velocity.y == 0.0);
+
+        //No jumping in mid air
+        let mut velocity = fast;
+        assert!(!update_velocity(&mut velocity, glam::f32::Vec3::ZERO, None, true, Stance::Standing, &settings, DELTA_TIME));
+        assert!(velocity.y < 0.0);
+    }
+
+    #[test]
+    fn crouching_is_slower_and_slides_keep_going_downhill() {
+
+        let settings = MovementSettings::default();
+
+        let mut velocity = glam::f32::Vec3::ZERO;
+        for _ in 0..120 {
+            update_velocity(&mut velocity, glam::f32::Vec3::X, Some(glam::f32::Vec3::Y), false, Stance::Crouching, &settings, DELTA_TIME);
+        }
+        assert!((get_horizontal_speed(velocity) - settings.crouch_max_speed).abs() < 0.0001, "{:?}", velocity);
+
+        //Sliding on the flat lasts much longer than stopping normally would
+        let mut velocity = glam::f32::Vec3::X * settings.max_speed;
+        for _ in 0..60 {
+            update_velocity(&mut velocity, glam::f32::Vec3::ZERO, Some(glam::f32::Vec3::Y), false, Stance::Sliding, &settings, DELTA_TIME);
+        }
+        assert!(velocity.x > settings.max_speed * 0.5 && velocity.x < settings.max_speed, "{:?}", velocity);
+
+        //Down a slope facing +X it speeds up and follows the slope rather than going flat
+        let slope = glam::f32::Vec3::new(1.0, 2.0, 0.0).normalize();
+        let mut velocity = glam::f32::Vec3::X * settings.max_speed;
+        velocity -= slope * velocity.dot(slope);
+        let start_speed = velocity.length();
+        for _ in 0..60 {
+            update_velocity(&mut velocity, glam::f32::Vec3::ZERO, Some(slope), false, Stance::Sliding, &settings, DELTA_TIME);
+        }
+        assert!(velocity.length() > start_speed * 1.5, "{:?}", velocity);
+        assert!(velocity.y < 0.0 && velocity.dot(slope).abs() < 0.0001, "{:?}", velocity);
+    }
+
+    #[test]
+    fn crouching_fast_slides_until_slow() {
+
+        let settings = MovementSettings::default();
+
+        assert_eq!(get_next_stance(Stance::Standing, true, true, settings.max_speed, &settings), Stance::Sliding);
+        assert_eq!(get_next_stance(Stance::Standing, true, true, 1.0, &settings), Stance::Crouching);
+        assert_eq!(get_next_stance(Stance::Standing, true, false, settings.max_speed, &settings), Stance::Crouching);
+        assert_eq!(get_next_stance(Stance::Sliding, true, false, 0.0, &settings), Stance::Sliding);
+        assert_eq!(get_next_stance(Stance::Sliding, true, true, settings.crouch_max_speed + 0.1, &settings), Stance::Sliding);
+        assert_eq!(get_next_stance(Stance::Sliding, true, true, 1.0, &settings), Stance::Crouching);
+        assert_eq!(get_next_stance(Stance::Crouching, true, true, settings.max_speed, &settings), Stance::Crouching);
+        assert_eq!(get_next_stance(Stance::Sliding, false, true, settings.max_speed, &settings), Stance::Standing);
+    }
+
+    #[test]
+    fn settings_by_name() {
+
+        let mut settings = MovementSettings::default();
+        for name in MovementSettings::NAMES {
+            assert!(settings.set(name, 3.0));
+            assert_eq!(settings.get(name), Some(3.0));
+        }
+        assert!(!settings.set("gravity", 1.0));
+        assert_eq!(settings.get("gravity"), None);
+
+        settings.set("friction", -1.0);
+        assert_eq!(settings.friction, 0.0);
+    }
+}
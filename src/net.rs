@@ -0,0 +1,806 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::resource_manager;
+use crate::user_cmd::UserCmd;
+use crate::weapon::WeaponState;
+
+pub mod delta;
+pub mod discovery;
+pub mod interest;
+pub mod reliable;
+pub mod simulator;
+
+pub use reliable::ReliableMessage;
+
+//Keep datagrams under the usual internet MTU so they never get fragmented
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+pub const DEFAULT_PORT: u16 = 27015;
+
+//Player id 0 is the host when running a listen server
+pub const HOST_PLAYER_ID: u32 = 0;
+
+//Bump whenever anything that goes over the wire changes
+pub const PROTOCOL_VERSION: u32 = 4;
+
+pub const DEFAULT_MAX_CLIENTS: usize = 16;
+//Send something at least this often so the other end knows we are still here
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+//Nothing heard for this long and the connection is dropped, also how long a handshake can take
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+//Handshake packets get resent this often until the server answers
+const HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    //The client left on its own
+    Quit,
+    Kicked,
+    ServerFull,
+    //Protocol version or asset manifest doesn't match the server
+    VersionMismatch,
+    Timeout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: u32,
+    pub position: glam::f32::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Packet {
+    //Handshake is Connect, Challenge, ChallengeResponse, Accepted
+    //The token proves the client can receive at the address it claims to be sending from
+    Connect { protocol_version: u32, asset_manifest_hash: u64 },
+    Challenge { token: u64 },
+    ChallengeResponse { token: u64 },
+    Accepted { player_id: u32 },
+    //Only sent when nothing else has gone out for a while
+    Keepalive,
+    //snapshot_ack is the newest snapshot the client has, the server diffs against it
+    //commands are the newest USER_CMD_REDUNDANCY commands, oldest first
+    Input { snapshot_ack: Option<u32>, commands: Vec<UserCmd> },
+    //player_id is the id of the player the receiving client controls
+    //input_tick is the tick of the last input from that client the server has applied, weapons is that client's weapons after it
+    Snapshot { tick: u32, player_id: u32, input_tick: Option<u32>, weapons: Option<WeaponState>, delta: delta::WorldDelta },
+    //Client asks what tick the server is on, client_time is echoed back so it can time the round trip
+    ClockRequest { client_time: f64 },
+    //server_tick includes how far the server is through the tick
+    ClockResponse { client_time: f64, server_tick: f64 },
+    Disconnect(DisconnectReason),
+}
+
+//A snapshot once the client has decoded it against its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldSnapshot {
+    pub tick: u32,
+    pub player_id: u32,
+    pub input_tick: Option<u32>,
+    pub weapons: Option<WeaponState>,
+    pub players: Vec<PlayerSnapshot>,
+}
+
+//What actually goes over the wire, sequence is per peer and starts at 1
+//Reliable messages ride along with whatever packet is going out next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Datagram {
+    pub sequence: u32,
+    pub reliable_ack: u32,
+    pub reliable: Vec<(u32, ReliableMessage)>,
+    pub packet: Packet,
+}
+
+//True if sequence a is more recent than b, handles wrap around
+pub fn is_sequence_newer(a: u32, b: u32) -> bool {
+
+    a != b && a.wrapping_sub(b) < u32::MAX / 2
+}
+
+pub struct NetSocket {
+    socket: UdpSocket,
+    local_sequences: HashMap<SocketAddr, u32>,
+    remote_sequences: HashMap<SocketAddr, u32>,
+    reliable_channels: HashMap<SocketAddr, reliable::ReliableChannel>,
+    last_sent: HashMap<SocketAddr, Instant>,
+    last_received: HashMap<SocketAddr, Instant>,
+    simulator: Option<simulator::NetworkSimulator>,
+    buffer: [u8; MAX_PACKET_SIZE],
+}
+
+impl NetSocket {
+
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self { socket, local_sequences: HashMap::new(), remote_sequences: HashMap::new(), reliable_channels: HashMap::new(), last_sent: HashMap::new(), last_received: HashMap::new(), simulator: None, buffer: [0; MAX_PACKET_SIZE] })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.socket.local_addr()
+    }
+
+    //Returns the sequence number the packet was sent with
+    pub fn send(&mut self, packet: &Packet, address: SocketAddr) -> std::io::Result<u32> {
+
+        let sequence = self.local_sequences.entry(address).or_insert(0);
+        *sequence = sequence.wrapping_add(1);
+        let channel = self.reliable_channels.entry(address).or_default();
+        let datagram = Datagram { sequence: *sequence, reliable_ack: channel.get_ack(), reliable: channel.write(std::time::Instant::now()), packet: packet.clone() };
+
+        let bytes = postcard::to_slice(&datagram, &mut self.buffer).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        match &mut self.simulator {
+            Some(simulator) => simulator.send(bytes, address, Instant::now()),
+            None => {
+                self.socket.send_to(bytes, address)?;
+            }
+        }
+        self.last_sent.insert(address, Instant::now());
+        self.flush();
+
+        Ok(datagram.sequence)
+    }
+
+    //Non blocking, returns None once there is nothing left to read this tick
+    pub fn receive(&mut self) -> Option<(SocketAddr, Datagram)> {
+
+        self.receive_from_peers(|_| true)
+    }
+
+    //Sequences, timings and reliable messages are only kept for addresses is_peer accepts
+    //Anyone else's datagrams still come back but leave nothing behind, so strangers can't fill up memory
+    pub fn receive_from_peers(&mut self, is_peer: impl Fn(&SocketAddr) -> bool) -> Option<(SocketAddr, Datagram)> {
+
+        self.flush();
+
+        loop {
+            let (size, address) = self.receive_raw()?;
+
+            //Anything that doesn't decode is not ours, drop it
+            let mut datagram: Datagram = match postcard::from_bytes(&self.buffer[..size]) {
+                Ok(datagram) => datagram,
+                Err(_) => continue,
+            };
+
+            if !is_peer(&address) {
+                datagram.reliable.clear();
+                return Some((address, datagram));
+            }
+
+            let remote_sequence = self.remote_sequences.entry(address).or_insert(0);
+            if is_sequence_newer(datagram.sequence, *remote_sequence) {
+                *remote_sequence = datagram.sequence;
+            }
+            self.last_received.insert(address, Instant::now());
+
+            //Reliable messages are held by the channel until receive_reliable is called
+            self.reliable_channels.entry(address).or_default().read(datagram.reliable_ack, std::mem::take(&mut datagram.reliable));
+
+            return Some((address, datagram));
+        }
+    }
+
+    //Sends anything the simulator has held back long enough, send and receive already call this
+    pub fn flush(&mut self) {
+
+        if let Some(simulator) = &mut self.simulator {
+            for (bytes, address) in simulator.take_outgoing(Instant::now()) {
+                let _ = self.socket.send_to(&bytes, address);
+            }
+        }
+    }
+
+    //Anything still held back by the old simulator is lost
+    pub fn set_simulator(&mut self, simulator: Option<simulator::NetworkSimulator>) {
+
+        self.simulator = simulator;
+    }
+
+    pub fn get_simulator(&self) -> Option<&simulator::NetworkSimulator> {
+
+        self.simulator.as_ref()
+    }
+
+    pub fn get_mut_simulator(&mut self) -> Option<&mut simulator::NetworkSimulator> {
+
+        self.simulator.as_mut()
+    }
+
+    //Most recent sequence number seen from this address, 0 if nothing has arrived yet
+    pub fn get_remote_sequence(&self, address: &SocketAddr) -> u32 {
+
+        *self.remote_sequences.get(address).unwrap_or(&0)
+    }
+
+    //Goes out with the next packet sent to that address and keeps going out until it is acked
+    pub fn send_reliable(&mut self, message: ReliableMessage, address: SocketAddr) -> std::io::Result<()> {
+
+        self.reliable_channels.entry(address).or_default().queue(message)
+    }
+
+    //Every reliable message from that address that is next in order
+    pub fn receive_reliable(&mut self, address: &SocketAddr) -> Vec<ReliableMessage> {
+
+        self.reliable_channels.get_mut(address).map(|channel| channel.drain()).unwrap_or_default()
+    }
+
+    //How many addresses anything is being kept for
+    pub fn get_peer_count(&self) -> usize {
+
+        self.local_sequences.keys().chain(self.remote_sequences.keys()).chain(self.reliable_channels.keys()).chain(self.last_sent.keys()).chain(self.last_received.keys()).collect::<HashSet<&SocketAddr>>().len()
+    }
+
+    //For a datagram that came in before its address was a peer
+    pub fn mark_received(&mut self, address: SocketAddr) {
+
+        self.last_received.insert(address, Instant::now());
+    }
+
+    pub fn forget(&mut self, address: &SocketAddr) {
+
+        self.local_sequences.remove(address);
+        self.remote_sequences.remove(address);
+        self.reliable_channels.remove(address);
+        self.last_sent.remove(address);
+        self.last_received.remove(address);
+    }
+
+    //Next datagram into the buffer, through the simulator if there is one
+    fn receive_raw(&mut self) -> Option<(usize, SocketAddr)> {
+
+        let now = Instant::now();
+
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((size, address)) => match &mut self.simulator {
+                    Some(simulator) => simulator.receive(&self.buffer[..size], address, now),
+                    None => return Some((size, address)),
+                },
+                //Windows reports ICMP port unreachable from an earlier send here
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => continue,
+                Err(_) => break,
+            }
+        }
+
+        let (bytes, address) = self.simulator.as_mut()?.take_incoming(now)?;
+        self.buffer[..bytes.len()].copy_from_slice(&bytes);
+
+        Some((bytes.len(), address))
+    }
+
+    pub fn get_time_since_sent(&self, address: &SocketAddr) -> Option<Duration> {
+
+        self.last_sent.get(address).map(|last_sent| last_sent.elapsed())
+    }
+
+    pub fn get_time_since_received(&self, address: &SocketAddr) -> Option<Duration> {
+
+        self.last_received.get(address).map(|last_received| last_received.elapsed())
+    }
+}
+
+struct ClientConnection {
+    player_id: u32,
+    delta_encoder: delta::DeltaEncoder,
+    priorities: interest::PriorityAccumulator,
+}
+
+pub struct NetServer {
+    socket: NetSocket,
+    clients: HashMap<SocketAddr, ClientConnection>,
+    //Challenge tokens are hashed from the address with this key, so nothing is kept for an address until it answers and spoofed connects can't fill anything up
+    challenge_key: RandomState,
+    started: Instant,
+    next_player_id: u32,
+    max_clients: usize,
+    timeout: Duration,
+    asset_manifest_hash: u64,
+    snapshot_budget: usize,
+}
+
+impl NetServer {
+
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+
+        Ok(Self { socket: NetSocket::bind(address)?, clients: HashMap::new(), challenge_key: RandomState::new(), started: Instant::now(), next_player_id: HOST_PLAYER_ID + 1, max_clients: DEFAULT_MAX_CLIENTS, timeout: CONNECTION_TIMEOUT, asset_manifest_hash: resource_manager::get_asset_manifest_hash(), snapshot_budget: interest::DEFAULT_SNAPSHOT_BUDGET })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.socket.local_addr()
+    }
+
+    //Drains the socket, the handshake is handled here
+    //A finished handshake comes back as Accepted and a lost client as Disconnect so the game can spawn and remove players
+    pub fn receive(&mut self) -> Vec<(u32, Packet)> {
+
+        let mut packets = Vec::new();
+
+        while let Some((address, datagram)) = self.socket.receive_from_peers(|address| self.clients.contains_key(address)) {
+
+            match datagram.packet {
+                Packet::Connect { protocol_version, asset_manifest_hash } => {
+                    if self.clients.contains_key(&address) {
+                        continue;
+                    }
+                    if protocol_version != PROTOCOL_VERSION || asset_manifest_hash != self.asset_manifest_hash {
+                        self.refuse(address, DisconnectReason::VersionMismatch);
+                        continue;
+                    }
+                    if self.clients.len() >= self.max_clients {
+                        self.refuse(address, DisconnectReason::ServerFull);
+                        continue;
+                    }
+
+                    let token = self.get_challenge_token(&address, 0);
+                    let _ = self.socket.send(&Packet::Challenge { token }, address);
+                    self.socket.forget(&address);
+                }
+                Packet::ChallengeResponse { token } => {
+                    //Accepted may have been lost, say it again
+                    if let Some(client) = self.clients.get(&address) {
+                        let player_id = client.player_id;
+                        let _ = self.socket.send(&Packet::Accepted { player_id }, address);
+                        continue;
+                    }
+
+                    //Anything handed out within the last timeout or so is still good
+                    if token != self.get_challenge_token(&address, 0) && token != self.get_challenge_token(&address, 1) {
+                        continue;
+                    }
+
+                    if self.clients.len() >= self.max_clients {
+                        self.refuse(address, DisconnectReason::ServerFull);
+                        continue;
+                    }
+
+                    let player_id = self.next_player_id;
+                    self.next_player_id += 1;
+                    self.clients.insert(address, ClientConnection { player_id, delta_encoder: delta::DeltaEncoder::new(), priorities: interest::PriorityAccumulator::new() });
+                    //It wasn't a peer when the response came in so nothing was noted
+                    self.socket.mark_received(address);
+                    let _ = self.socket.send(&Packet::Accepted { player_id }, address);
+                    packets.push((player_id, Packet::Accepted { player_id }));
+                }
+                Packet::Disconnect(reason) => {
+                    if let Some(client) = self.clients.remove(&address) {
+                        self.socket.forget(&address);
+                        packets.push((client.player_id, Packet::Disconnect(reason)));
+                    }
+                }
+                Packet::Keepalive => {}
+                packet => {
+                    if let Some(client) = self.clients.get_mut(&address) {
+                        if let Packet::Input { snapshot_ack: Some(snapshot_ack), .. } = &packet {
+                            client.delta_encoder.acknowledge(*snapshot_ack);
+                        }
+                        packets.push((client.player_id, packet));
+                    }
+                }
+            }
+        }
+
+        let timed_out: Vec<SocketAddr> = self.clients.keys().filter(|address| self.socket.get_time_since_received(address).is_none_or(|silence| silence >= self.timeout)).copied().collect();
+        for address in timed_out {
+            let client = self.clients.remove(&address).unwrap();
+            let _ = self.socket.send(&Packet::Disconnect(DisconnectReason::Timeout), address);
+            self.socket.forget(&address);
+            packets.push((client.player_id, Packet::Disconnect(DisconnectReason::Timeout)));
+        }
+
+        packets
+    }
+
+    //Call once per tick, only sends anything to clients nothing else has gone to recently
+    pub fn update(&mut self) {
+
+        for address in self.clients.keys() {
+            if self.socket.get_time_since_sent(address).is_none_or(|since_sent| since_sent >= KEEPALIVE_INTERVAL) {
+                let _ = self.socket.send(&Packet::Keepalive, *address);
+            }
+        }
+    }
+
+    //The game has to remove the player itself, nothing is returned from receive for this
+    pub fn kick(&mut self, player_id: u32) -> std::io::Result<()> {
+
+        let address = self.get_address(player_id)?;
+        self.refuse(address, DisconnectReason::Kicked);
+
+        Ok(())
+    }
+
+    pub fn send(&mut self, player_id: u32, packet: &Packet) -> std::io::Result<u32> {
+
+        let address = self.get_address(player_id)?;
+
+        self.socket.send(packet, address)
+    }
+
+    //Every player equally relevant
+    pub fn send_snapshot(&mut self, player_id: u32, tick: u32, input_tick: Option<u32>, weapons: Option<WeaponState>, players: &[PlayerSnapshot]) -> std::io::Result<u32> {
+
+        let relevances = vec![1.0; players.len()];
+
+        self.send_prioritized_snapshot(player_id, tick, input_tick, weapons, players, &relevances)
+    }
+
+    //relevances line up with players, the most overdue players go first until the snapshot budget runs out
+    //Delta compressed against whatever that client last acked
+    pub fn send_prioritized_snapshot(&mut self, player_id: u32, tick: u32, input_tick: Option<u32>, weapons: Option<WeaponState>, players: &[PlayerSnapshot], relevances: &[f32]) -> std::io::Result<u32> {
+
+        let address = self.get_address(player_id)?;
+        let client = self.clients.get_mut(&address).unwrap();
+
+        let relevances: Vec<(u32, f32)> = players.iter().zip(relevances).map(|(player, relevance)| (player.id, *relevance)).collect();
+        let priority = client.priorities.accumulate(&relevances);
+        let (delta, updated) = client.delta_encoder.encode_prioritized(tick, players, &priority, self.snapshot_budget);
+        client.priorities.reset(&updated);
+
+        self.socket.send(&Packet::Snapshot { tick, player_id, input_tick, weapons, delta }, address)
+    }
+
+    pub fn send_reliable(&mut self, player_id: u32, message: ReliableMessage) -> std::io::Result<()> {
+
+        let address = self.get_address(player_id)?;
+
+        self.socket.send_reliable(message, address)
+    }
+
+    pub fn broadcast_reliable(&mut self, message: ReliableMessage) -> std::io::Result<()> {
+
+        for address in self.clients.keys() {
+            self.socket.send_reliable(message.clone(), *address)?;
+        }
+
+        Ok(())
+    }
+
+    //Call after receive, messages from each client come out in order
+    pub fn receive_reliable(&mut self) -> Vec<(u32, ReliableMessage)> {
+
+        let mut messages = Vec::new();
+
+        for (address, client) in &self.clients {
+            messages.extend(self.socket.receive_reliable(address).into_iter().map(|message| (client.player_id, message)));
+        }
+
+        messages
+    }
+
+    pub fn get_player_ids(&self) -> Vec<u32> {
+
+        self.clients.values().map(|client| client.player_id).collect()
+    }
+
+    pub fn get_client_count(&self) -> usize {
+
+        self.clients.len()
+    }
+
+    pub fn get_socket(&self) -> &NetSocket {
+
+        &self.socket
+    }
+
+    pub fn get_mut_socket(&mut self) -> &mut NetSocket {
+
+        &mut self.socket
+    }
+
+    pub fn get_time_since_received(&self, player_id: u32) -> Option<Duration> {
+
+        self.socket.get_time_since_received(&self.get_address(player_id).ok()?)
+    }
+
+    pub fn set_max_clients(&mut self, max_clients: usize) {
+
+        self.max_clients = max_clients;
+    }
+
+    pub fn get_max_clients(&self) -> usize {
+
+        self.max_clients
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+
+        self.timeout = timeout;
+    }
+
+    pub fn set_asset_manifest_hash(&mut self, asset_manifest_hash: u64) {
+
+        self.asset_manifest_hash = asset_manifest_hash;
+    }
+
+    //Bytes of player updates allowed in each snapshot
+    pub fn set_snapshot_budget(&mut self, snapshot_budget: usize) {
+
+        self.snapshot_budget = snapshot_budget;
+    }
+
+    pub fn get_snapshot_budget(&self) -> usize {
+
+        self.snapshot_budget
+    }
+
+    //Tells the address why and drops anything we had for it
+    fn refuse(&mut self, address: SocketAddr, reason: DisconnectReason) {
+
+        let _ = self.socket.send(&Packet::Disconnect(reason), address);
+        self.clients.remove(&address);
+        self.socket.forget(&address);
+    }
+
+    //Changes every timeout, age counts back from the current one
+    fn get_challenge_token(&self, address: &SocketAddr, age: u128) -> u64 {
+
+        let period = self.started.elapsed().as_millis() / self.timeout.as_millis().max(1);
+
+        self.challenge_key.hash_one((address, period.wrapping_sub(age)))
+    }
+
+    fn get_address(&self, player_id: u32) -> std::io::Result<SocketAddr> {
+
+        self.clients.iter().find(|(_, client)| client.player_id == player_id).map(|(address, _)| *address)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotConnected, format!("No client with player id {}", player_id)))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    //Nothing sent yet
+    Idle,
+    Connecting,
+    Challenged { token: u64 },
+    Connected { player_id: u32 },
+    Disconnected(DisconnectReason),
+}
+
+//A ClockResponse and when it arrived, the arrival time is taken as soon as it's read off the socket
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClockResponse {
+    pub client_time: f64,
+    pub server_tick: f64,
+    pub received: Instant,
+}
+
+pub struct NetClient {
+    socket: NetSocket,
+    server_address: SocketAddr,
+    state: ConnectionState,
+    state_changed: Instant,
+    timeout: Duration,
+    asset_manifest_hash: u64,
+    delta_decoder: delta::DeltaDecoder,
+    clock_responses: Vec<ClockResponse>,
+}
+
+impl NetClient {
+
+    //Binds to any free local port, nothing is sent until connect is called
+    pub fn new(server_address: SocketAddr) -> std::io::Result<Self> {
+
+        let bind_address: SocketAddr = if server_address.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { (std::net::Ipv6Addr::UNSPECIFIED, 0).into() };
+
+        Ok(Self { socket: NetSocket::bind(bind_address)?, server_address, state: ConnectionState::Idle, state_changed: Instant::now(), timeout: CONNECTION_TIMEOUT, asset_manifest_hash: resource_manager::get_asset_manifest_hash(), delta_decoder: delta::DeltaDecoder::new(), clock_responses: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.socket.local_addr()
+    }
+
+    //Starts the handshake, update keeps it going
+    pub fn connect(&mut self) -> std::io::Result<u32> {
+
+        self.set_state(ConnectionState::Connecting);
+        self.send_connect()
+    }
+
+    pub fn disconnect(&mut self) -> std::io::Result<u32> {
+
+        self.set_state(ConnectionState::Disconnected(DisconnectReason::Quit));
+        self.socket.send(&Packet::Disconnect(DisconnectReason::Quit), self.server_address)
+    }
+
+    pub fn send(&mut self, packet: &Packet) -> std::io::Result<u32> {
+
+        self.socket.send(packet, self.server_address)
+    }
+
+    //Anything not from the server is ignored, snapshots come back decoded in the order they arrived
+    pub fn receive(&mut self) -> Vec<WorldSnapshot> {
+
+        let mut snapshots = Vec::new();
+
+        let server_address = self.server_address;
+        while let Some((address, datagram)) = self.socket.receive_from_peers(|address| *address == server_address) {
+
+            if address != self.server_address {
+                continue;
+            }
+
+            match (datagram.packet, self.state) {
+                (Packet::Challenge { token }, ConnectionState::Connecting | ConnectionState::Challenged { .. }) => {
+                    self.set_state(ConnectionState::Challenged { token });
+                    let _ = self.socket.send(&Packet::ChallengeResponse { token }, self.server_address);
+                }
+                (Packet::Accepted { player_id }, ConnectionState::Challenged { .. }) => {
+                    self.set_state(ConnectionState::Connected { player_id });
+                }
+                (Packet::Disconnect(reason), ConnectionState::Connecting | ConnectionState::Challenged { .. } | ConnectionState::Connected { .. }) => {
+                    self.set_state(ConnectionState::Disconnected(reason));
+                }
+                //Accepted can get lost, a snapshot means we got in anyway
+                (Packet::Snapshot { tick, player_id, input_tick, weapons, delta }, ConnectionState::Challenged { .. } | ConnectionState::Connected { .. }) => {
+                    if let Some(players) = self.delta_decoder.decode(tick, &delta) {
+                        if !self.is_connected() {
+                            self.set_state(ConnectionState::Connected { player_id });
+                        }
+                        snapshots.push(WorldSnapshot { tick, player_id, input_tick, weapons, players });
+                    }
+                }
+                (Packet::ClockResponse { client_time, server_tick }, ConnectionState::Connected { .. }) => {
+                    self.clock_responses.push(ClockResponse { client_time, server_tick, received: Instant::now() });
+                }
+                _ => {}
+            }
+        }
+
+        snapshots
+    }
+
+    //Call once per tick, resends the handshake, sends keepalives and gives up on a silent server
+    pub fn update(&mut self) {
+
+        match self.state {
+            ConnectionState::Connecting | ConnectionState::Challenged { .. } => {
+                if self.state_changed.elapsed() >= self.timeout {
+                    self.set_state(ConnectionState::Disconnected(DisconnectReason::Timeout));
+                    return;
+                }
+                if self.socket.get_time_since_sent(&self.server_address).is_some_and(|since_sent| since_sent < HANDSHAKE_RESEND_INTERVAL) {
+                    return;
+                }
+                let _ = match self.state {
+                    ConnectionState::Challenged { token } => self.socket.send(&Packet::ChallengeResponse { token }, self.server_address),
+                    _ => self.send_connect(),
+                };
+            }
+            ConnectionState::Connected { .. } => {
+                let silence = self.socket.get_time_since_received(&self.server_address).unwrap_or(self.state_changed.elapsed());
+                if silence >= self.timeout {
+                    self.set_state(ConnectionState::Disconnected(DisconnectReason::Timeout));
+                    return;
+                }
+                if self.socket.get_time_since_sent(&self.server_address).is_none_or(|since_sent| since_sent >= KEEPALIVE_INTERVAL) {
+                    let _ = self.socket.send(&Packet::Keepalive, self.server_address);
+                }
+            }
+            ConnectionState::Idle | ConnectionState::Disconnected(_) => {}
+        }
+    }
+
+    pub fn send_reliable(&mut self, message: ReliableMessage) -> std::io::Result<()> {
+
+        self.socket.send_reliable(message, self.server_address)
+    }
+
+    //Call after receive
+    pub fn receive_reliable(&mut self) -> Vec<ReliableMessage> {
+
+        self.socket.receive_reliable(&self.server_address)
+    }
+
+    //Call after receive
+    pub fn take_clock_responses(&mut self) -> Vec<ClockResponse> {
+
+        std::mem::take(&mut self.clock_responses)
+    }
+
+    //Goes in every input so the server knows which baseline it can diff against
+    pub fn get_snapshot_ack(&self) -> Option<u32> {
+
+        self.delta_decoder.get_ack()
+    }
+
+    pub fn is_connected(&self) -> bool {
+
+        matches!(self.state, ConnectionState::Connected { .. })
+    }
+
+    pub fn get_player_id(&self) -> Option<u32> {
+
+        match self.state {
+            ConnectionState::Connected { player_id } => Some(player_id),
+            _ => None,
+        }
+    }
+
+    pub fn get_socket(&self) -> &NetSocket {
+
+        &self.socket
+    }
+
+    pub fn get_mut_socket(&mut self) -> &mut NetSocket {
+
+        &mut self.socket
+    }
+
+    pub fn get_state(&self) -> ConnectionState {
+
+        self.state
+    }
+
+    pub fn get_time_since_received(&self) -> Option<Duration> {
+
+        self.socket.get_time_since_received(&self.server_address)
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+
+        self.timeout = timeout;
+    }
+
+    pub fn set_asset_manifest_hash(&mut self, asset_manifest_hash: u64) {
+
+        self.asset_manifest_hash = asset_manifest_hash;
+    }
+
+    pub fn get_server_address(&self) -> SocketAddr {
+
+        self.server_address
+    }
+
+    pub fn get_remote_sequence(&self) -> u32 {
+
+        self.socket.get_remote_sequence(&self.server_address)
+    }
+
+    fn send_connect(&mut self) -> std::io::Result<u32> {
+
+        self.socket.send(&Packet::Connect { protocol_version: PROTOCOL_VERSION, asset_manifest_hash: self.asset_manifest_hash }, self.server_address)
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+
+        self.state = state;
+        self.state_changed = Instant::now();
+    }
+}
+
+pub enum Network {
+    Server(NetServer),
+    Client(NetClient),
+}
+
+impl Network {
+
+    pub fn get_socket(&self) -> &NetSocket {
+
+        match self {
+            Network::Server(server) => server.get_socket(),
+            Network::Client(client) => client.get_socket(),
+        }
+    }
+
+    pub fn get_mut_socket(&mut self) -> &mut NetSocket {
+
+        match self {
+            Network::Server(server) => server.get_mut_socket(),
+            Network::Client(client) => client.get_mut_socket(),
+        }
+    }
+}
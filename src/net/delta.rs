@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::net::{PlayerSnapshot, MAX_PACKET_SIZE};
+
+//Snapshots the server remembers per client, an ack older than this means sending everything again
+const ENCODER_HISTORY: usize = 32;
+//Client keeps more than the server so any baseline the server picks is still around
+const DECODER_HISTORY: usize = 64;
+
+//1/512 of a unit, about 2mm
+const POSITION_SCALE: f32 = 512.0;
+const YAW_SCALE: f32 = 65536.0 / std::f32::consts::TAU;
+const PITCH_SCALE: f32 = i16::MAX as f32 / std::f32::consts::FRAC_PI_2;
+
+//What actually gets stored and compared, both ends have to agree on these exact values
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuantizedPlayer {
+    pub id: u32,
+    pub position: [i32; 3],
+    pub yaw: u16,
+    pub pitch: i16,
+}
+
+impl QuantizedPlayer {
+
+    pub fn new(player: &PlayerSnapshot) -> Self {
+
+        let position = (player.position * POSITION_SCALE).round();
+
+        Self {
+            id: player.id,
+            position: [position.x as i32, position.y as i32, position.z as i32],
+            yaw: (player.yaw.rem_euclid(std::f32::consts::TAU) * YAW_SCALE).round() as u32 as u16,
+            pitch: (player.pitch.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2) * PITCH_SCALE).round() as i16,
+        }
+    }
+
+    pub fn to_snapshot(&self) -> PlayerSnapshot {
+
+        PlayerSnapshot {
+            id: self.id,
+            position: glam::f32::Vec3::new(self.position[0] as f32, self.position[1] as f32, self.position[2] as f32) / POSITION_SCALE,
+            yaw: self.yaw as f32 / YAW_SCALE,
+            pitch: self.pitch as f32 / PITCH_SCALE,
+        }
+    }
+}
+
+//Only the fields that changed since the baseline, position is relative to the baseline so it stays a small varint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerDelta {
+    pub id: u32,
+    pub position: Option<[i32; 3]>,
+    pub yaw: Option<u16>,
+    pub pitch: Option<i16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldDelta {
+    //None means everything is in changed and there is nothing to diff against
+    pub baseline_tick: Option<u32>,
+    pub changed: Vec<PlayerDelta>,
+    pub removed: Vec<u32>,
+}
+
+pub fn quantize(players: &[PlayerSnapshot]) -> Vec<QuantizedPlayer> {
+
+    let mut quantized: Vec<QuantizedPlayer> = players.iter().map(QuantizedPlayer::new).collect();
+    quantized.sort_by_key(|player| player.id);
+
+    quantized
+}
+
+pub fn encode(baseline_tick: Option<u32>, baseline: &[QuantizedPlayer], current: &[QuantizedPlayer]) -> WorldDelta {
+
+    let changed = current.iter().filter_map(|player| encode_player(baseline, player)).collect();
+
+    let removed = baseline.iter().filter(|old| !current.iter().any(|player| player.id == old.id)).map(|old| old.id).collect();
+
+    WorldDelta { baseline_tick, changed, removed }
+}
+
+//None if the player is the same as in the baseline
+pub fn encode_player(baseline: &[QuantizedPlayer], player: &QuantizedPlayer) -> Option<PlayerDelta> {
+
+    match baseline.iter().find(|old| old.id == player.id) {
+        Some(old) => {
+            let position = if player.position != old.position {
+                Some([player.position[0].wrapping_sub(old.position[0]), player.position[1].wrapping_sub(old.position[1]), player.position[2].wrapping_sub(old.position[2])])
+            }
+            else {
+                None
+            };
+            let yaw = if player.yaw != old.yaw { Some(player.yaw) } else { None };
+            let pitch = if player.pitch != old.pitch { Some(player.pitch) } else { None };
+
+            if position.is_none() && yaw.is_none() && pitch.is_none() {
+                return None;
+            }
+            Some(PlayerDelta { id: player.id, position, yaw, pitch })
+        }
+        None => Some(PlayerDelta { id: player.id, position: Some(player.position), yaw: Some(player.yaw), pitch: Some(player.pitch) }),
+    }
+}
+
+//Bytes the player takes up in a snapshot against that baseline
+pub fn get_encoded_size(baseline: &[QuantizedPlayer], player: &QuantizedPlayer) -> usize {
+
+    let mut buffer = [0; MAX_PACKET_SIZE];
+
+    encode_player(baseline, player).map_or(0, |player_delta| postcard::to_slice(&player_delta, &mut buffer).map_or(0, |bytes| bytes.len()))
+}
+
+pub fn decode(baseline: &[QuantizedPlayer], delta: &WorldDelta) -> Vec<QuantizedPlayer> {
+
+    let mut players: Vec<QuantizedPlayer> = baseline.iter().filter(|old| !delta.removed.contains(&old.id)).copied().collect();
+
+    for player_delta in &delta.changed {
+
+        let index = match players.iter().position(|player| player.id == player_delta.id) {
+            Some(index) => index,
+            None => {
+                //New since the baseline, deltas are from zero
+                players.push(QuantizedPlayer { id: player_delta.id, position: [0; 3], yaw: 0, pitch: 0 });
+                players.len() - 1
+            }
+        };
+
+        let player = &mut players[index];
+        if let Some(position) = player_delta.position {
+            player.position = [player.position[0].wrapping_add(position[0]), player.position[1].wrapping_add(position[1]), player.position[2].wrapping_add(position[2])];
+        }
+        if let Some(yaw) = player_delta.yaw {
+            player.yaw = yaw;
+        }
+        if let Some(pitch) = player_delta.pitch {
+            player.pitch = pitch;
+        }
+    }
+
+    players.sort_by_key(|player| player.id);
+
+    players
+}
+
+//Server side, one per client
+pub struct DeltaEncoder {
+    history: VecDeque<(u32, Vec<QuantizedPlayer>)>,
+    acked_tick: Option<u32>,
+}
+
+impl Default for DeltaEncoder {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl DeltaEncoder {
+
+    pub fn new() -> Self {
+
+        Self { history: VecDeque::new(), acked_tick: None }
+    }
+
+    //Acks can arrive out of order, an older one never replaces a newer baseline
+    pub fn acknowledge(&mut self, tick: u32) {
+
+        if self.acked_tick.is_none_or(|acked_tick| tick > acked_tick) {
+            self.acked_tick = Some(tick);
+        }
+    }
+
+    pub fn encode(&mut self, tick: u32, players: &[PlayerSnapshot]) -> WorldDelta {
+
+        let priority: Vec<u32> = players.iter().map(|player| player.id).collect();
+
+        self.encode_prioritized(tick, players, &priority, usize::MAX).0
+    }
+
+    //Players are brought up to date in priority order while the changes fit in budget bytes
+    //The rest keep what the client was last sent, or aren't sent at all if they're new, also returns the ids that were updated
+    pub fn encode_prioritized(&mut self, tick: u32, players: &[PlayerSnapshot], priority: &[u32], budget: usize) -> (WorldDelta, Vec<u32>) {
+
+        let current = quantize(players);
+
+        //Fall back to a full snapshot if the client hasn't acked anything we still have
+        let (baseline_tick, baseline) = match self.acked_tick.and_then(|acked_tick| self.history.iter().find(|(history_tick, _)| *history_tick == acked_tick)) {
+            Some((baseline_tick, baseline)) => (Some(*baseline_tick), baseline.as_slice()),
+            None => (None, [].as_slice()),
+        };
+
+        let mut sent: Vec<QuantizedPlayer> = match self.history.back() {
+            Some((_, previous)) => previous.iter().filter(|old| current.iter().any(|player| player.id == old.id)).copied().collect(),
+            None => Vec::new(),
+        };
+        //Carrying on with old values isn't free until the client acks them
+        let mut size: usize = sent.iter().map(|player| get_encoded_size(baseline, player)).sum();
+        let mut updated = Vec::new();
+
+        for id in priority {
+            let Some(player) = current.iter().find(|player| player.id == *id) else {
+                continue;
+            };
+            let index = sent.iter().position(|old| old.id == *id);
+            let old_size = index.map_or(0, |index| get_encoded_size(baseline, &sent[index]));
+            let new_size = get_encoded_size(baseline, player);
+            //Something further down might still fit
+            if (size - old_size).saturating_add(new_size) > budget {
+                continue;
+            }
+
+            size = size - old_size + new_size;
+            match index {
+                Some(index) => sent[index] = *player,
+                None => sent.push(*player),
+            }
+            updated.push(*id);
+        }
+        sent.sort_by_key(|player| player.id);
+
+        let delta = encode(baseline_tick, baseline, &sent);
+
+        //Remember what the client will actually end up with, not what we were given
+        self.history.push_back((tick, sent));
+        while self.history.len() > ENCODER_HISTORY {
+            self.history.pop_front();
+        }
+
+        (delta, updated)
+    }
+
+    pub fn get_acked_tick(&self) -> Option<u32> {
+
+        self.acked_tick
+    }
+}
+
+//Client side
+pub struct DeltaDecoder {
+    history: VecDeque<(u32, Vec<QuantizedPlayer>)>,
+    latest_tick: Option<u32>,
+}
+
+impl Default for DeltaDecoder {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl DeltaDecoder {
+
+    pub fn new() -> Self {
+
+        Self { history: VecDeque::new(), latest_tick: None }
+    }
+
+    //None if the baseline is one we no longer have, the server will move on to a newer one once it sees our acks
+    pub fn decode(&mut self, tick: u32, delta: &WorldDelta) -> Option<Vec<PlayerSnapshot>> {
+
+        let players = match delta.baseline_tick {
+            Some(baseline_tick) => {
+                let (_, baseline) = self.history.iter().find(|(history_tick, _)| *history_tick == baseline_tick)?;
+                decode(baseline, delta)
+            }
+            None => decode(&[], delta),
+        };
+
+        let snapshots = players.iter().map(QuantizedPlayer::to_snapshot).collect();
+
+        if !self.history.iter().any(|(history_tick, _)| *history_tick == tick) {
+            self.history.push_back((tick, players));
+            self.history.make_contiguous().sort_by_key(|(history_tick, _)| *history_tick);
+            while self.history.len() > DECODER_HISTORY {
+                self.history.pop_front();
+            }
+        }
+
+        if self.latest_tick.is_none_or(|latest_tick| tick > latest_tick) {
+            self.latest_tick = Some(tick);
+        }
+
+        Some(snapshots)
+    }
+
+    //Newest snapshot we can use as a baseline, sent back to the server with every input
+    pub fn get_ack(&self) -> Option<u32> {
+
+        self.latest_tick
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::net::{MAX_PACKET_SIZE, PROTOCOL_VERSION};
+
+//Separate from the game port so servers on any port can be found with one broadcast
+pub const DISCOVERY_PORT: u16 = 27016;
+//Servers that stop answering drop off the list after this
+pub const SERVER_BROWSER_TIMEOUT: Duration = Duration::from_secs(10);
+//Anything not starting with this is ignored, must never change or old and new builds can't see each other's protocol version
+const DISCOVERY_MAGIC: [u8; 4] = *b"MPFD";
+//Most queries a server answers per poll, a broadcast storm shouldn't hold up the tick
+const MAX_QUERIES_PER_POLL: usize = 32;
+//Each address gets at most one answer this often, however fast it asks
+pub const DISCOVERY_REPLY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    //First so a client can always tell a server is too old or new to join
+    pub protocol_version: u32,
+    pub name: String,
+    pub map: String,
+    pub player_count: u32,
+    pub max_players: u32,
+    //The game socket's port, the address is wherever the response came from
+    pub game_port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiscoveryPacket {
+    //The nonce comes back in the response so the client can time the round trip
+    Query { nonce: u64 },
+    Response { nonce: u64, info: ServerInfo },
+}
+
+//Fails if a response won't fit in a packet, e.g. a really long server name
+//Queries are padded out to a whole packet so a spoofed one can't get back more than it sent
+pub fn encode(packet: &DiscoveryPacket) -> std::io::Result<Vec<u8>> {
+
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    buffer[..DISCOVERY_MAGIC.len()].copy_from_slice(&DISCOVERY_MAGIC);
+    let size = postcard::to_slice(packet, &mut buffer[DISCOVERY_MAGIC.len()..]).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?.len();
+
+    match packet {
+        DiscoveryPacket::Query { .. } => Ok(buffer.to_vec()),
+        DiscoveryPacket::Response { .. } => Ok(buffer[..DISCOVERY_MAGIC.len() + size].to_vec()),
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Option<DiscoveryPacket> {
+
+    postcard::from_bytes(bytes.strip_prefix(&DISCOVERY_MAGIC)?).ok()
+}
+
+//Server side, answers queries on the discovery port
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+    name: String,
+    map: String,
+    //When each address was last answered
+    last_replies: HashMap<IpAddr, Instant>,
+}
+
+impl DiscoveryResponder {
+
+    pub fn bind<A: ToSocketAddrs>(address: A, name: &str, map: &str) -> std::io::Result<Self> {
+
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self { socket, name: name.to_string(), map: map.to_string(), last_replies: HashMap::new() })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.socket.local_addr()
+    }
+
+    pub fn set_map(&mut self, map: &str) {
+
+        self.map = map.to_string();
+    }
+
+    //Call once per tick, returns how many queries were answered
+    //Anything that would get back more than it sent or asks again too soon is ignored, so nobody can bounce traffic off us
+    pub fn poll(&mut self, player_count: u32, max_players: u32, game_port: u16) -> usize {
+
+        let now = Instant::now();
+        let mut answered = 0;
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        self.last_replies.retain(|_, last_reply| now.duration_since(*last_reply) < DISCOVERY_REPLY_INTERVAL);
+
+        for _ in 0..MAX_QUERIES_PER_POLL {
+            let Ok((size, address)) = self.socket.recv_from(&mut buffer) else {
+                break;
+            };
+            let Some(DiscoveryPacket::Query { nonce }) = decode(&buffer[..size]) else {
+                continue;
+            };
+
+            let info = ServerInfo { protocol_version: PROTOCOL_VERSION, name: self.name.clone(), map: self.map.clone(), player_count, max_players, game_port };
+            let Ok(bytes) = encode(&DiscoveryPacket::Response { nonce, info }) else {
+                break;
+            };
+            if bytes.len() > size || self.last_replies.contains_key(&address.ip()) {
+                continue;
+            }
+            self.last_replies.insert(address.ip(), now);
+            if self.socket.send_to(&bytes, address).is_ok() {
+                answered += 1;
+            }
+        }
+
+        answered
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    //Game address, join with this
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    pub ping: Duration,
+    pub last_seen: Instant,
+}
+
+impl DiscoveredServer {
+
+    pub fn is_compatible(&self) -> bool {
+
+        self.info.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ServerSortKey {
+    Name,
+    Map,
+    Players,
+    Ping,
+}
+
+pub fn sort_servers(servers: &mut [&DiscoveredServer], sort_key: ServerSortKey, descending: bool) {
+
+    //Address last so servers that tie don't swap places every frame
+    servers.sort_by(|a, b| {
+        let order = match sort_key {
+            ServerSortKey::Name => a.info.name.to_lowercase().cmp(&b.info.name.to_lowercase()),
+            ServerSortKey::Map => a.info.map.to_lowercase().cmp(&b.info.map.to_lowercase()),
+            ServerSortKey::Players => a.info.player_count.cmp(&b.info.player_count),
+            ServerSortKey::Ping => a.ping.cmp(&b.ping),
+        };
+        if descending { order.reverse() } else { order }.then(a.address.cmp(&b.address))
+    });
+}
+
+//Client side, broadcasts queries and collects the answers
+pub struct ServerBrowser {
+    socket: UdpSocket,
+    //Nonce to when the query went out
+    queries: HashMap<u64, Instant>,
+    servers: HashMap<SocketAddr, DiscoveredServer>,
+}
+
+impl ServerBrowser {
+
+    pub fn new() -> std::io::Result<Self> {
+
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_nonblocking(true)?;
+        socket.set_broadcast(true)?;
+
+        Ok(Self { socket, queries: HashMap::new(), servers: HashMap::new() })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.socket.local_addr()
+    }
+
+    //Asks everything on the LAN
+    pub fn refresh(&mut self) -> std::io::Result<()> {
+
+        self.query(SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)))
+    }
+
+    //Asks one address, broadcast or not
+    pub fn query(&mut self, address: SocketAddr) -> std::io::Result<()> {
+
+        let nonce = rand::random();
+        self.socket.send_to(&encode(&DiscoveryPacket::Query { nonce })?, address)?;
+        self.queries.insert(nonce, Instant::now());
+
+        Ok(())
+    }
+
+    //Call every frame
+    pub fn poll(&mut self) {
+
+        let now = Instant::now();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        while let Ok((size, address)) = self.socket.recv_from(&mut buffer) {
+            let Some(DiscoveryPacket::Response { nonce, info }) = decode(&buffer[..size]) else {
+                continue;
+            };
+            //Only answers to our own queries, anything else can't be timed
+            let Some(sent) = self.queries.get(&nonce) else {
+                continue;
+            };
+
+            let address = SocketAddr::new(address.ip(), info.game_port);
+            self.servers.insert(address, DiscoveredServer { address, info, ping: now.duration_since(*sent), last_seen: now });
+        }
+
+        self.queries.retain(|_, sent| now.duration_since(*sent) < SERVER_BROWSER_TIMEOUT);
+        self.servers.retain(|_, server| now.duration_since(server.last_seen) < SERVER_BROWSER_TIMEOUT);
+    }
+
+    pub fn get_servers(&self, sort_key: ServerSortKey, descending: bool) -> Vec<&DiscoveredServer> {
+
+        let mut servers: Vec<&DiscoveredServer> = self.servers.values().collect();
+        sort_servers(&mut servers, sort_key, descending);
+
+        servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(port: u16, name: &str, player_count: u32, ping: u64) -> DiscoveredServer {
+
+        let info = ServerInfo { protocol_version: PROTOCOL_VERSION, name: name.to_string(), map: "test_triangle".to_string(), player_count, max_players: 16, game_port: port };
+
+        DiscoveredServer { address: SocketAddr::from((Ipv4Addr::LOCALHOST, port)), info, ping: Duration::from_millis(ping), last_seen: Instant::now() }
+    }
+
+    #[test]
+    fn packets_survive_encoding_and_garbage_is_ignored() {
+
+        let info = ServerInfo { protocol_version: PROTOCOL_VERSION, name: "LAN party".to_string(), map: "test_triangle".to_string(), player_count: 3, max_players: 16, game_port: 27015 };
+        let response = DiscoveryPacket::Response { nonce: 42, info };
+
+        assert_eq!(decode(&encode(&response).unwrap()), Some(response));
+        let query = encode(&DiscoveryPacket::Query { nonce: 7 }).unwrap();
+        assert_eq!(decode(&query), Some(DiscoveryPacket::Query { nonce: 7 }));
+        assert_eq!(decode(b"MPFD"), None);
+        assert_eq!(decode(&query[DISCOVERY_MAGIC.len()..]), None);
+        //Padding is never smaller than the biggest answer
+        assert_eq!(query.len(), MAX_PACKET_SIZE);
+
+        let info = ServerInfo { protocol_version: PROTOCOL_VERSION, name: "x".repeat(MAX_PACKET_SIZE), map: "test_triangle".to_string(), player_count: 3, max_players: 16, game_port: 27015 };
+        assert!(encode(&DiscoveryPacket::Response { nonce: 42, info }).is_err());
+    }
+
+    #[test]
+    fn sorts_by_any_column_either_way() {
+
+        let servers = [server(1, "bravo", 4, 30), server(2, "Alpha", 8, 10), server(3, "charlie", 0, 20)];
+        let mut sorted: Vec<&DiscoveredServer> = servers.iter().collect();
+        let ports = |sorted: &[&DiscoveredServer]| sorted.iter().map(|server| server.address.port()).collect::<Vec<u16>>();
+
+        sort_servers(&mut sorted, ServerSortKey::Name, false);
+        assert_eq!(ports(&sorted), vec![2, 1, 3]);
+        sort_servers(&mut sorted, ServerSortKey::Players, true);
+        assert_eq!(ports(&sorted), vec![2, 1, 3]);
+        sort_servers(&mut sorted, ServerSortKey::Ping, false);
+        assert_eq!(ports(&sorted), vec![2, 3, 1]);
+        sort_servers(&mut sorted, ServerSortKey::Ping, true);
+        assert_eq!(ports(&sorted), vec![1, 3, 2]);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::collision;
+
+//Bytes of player updates per snapshot, what's left of a datagram after reliable messages and headers
+pub const DEFAULT_SNAPSHOT_BUDGET: usize = 600;
+//Anything closer than this is fully relevant, past it relevance falls off with distance
+pub const FULL_RELEVANCE_DISTANCE: f32 = 15.0;
+//Relevance is scaled by this when there's a wall in the way
+pub const OCCLUDED_RELEVANCE_SCALE: f32 = 0.25;
+//Nothing drops below this so far away and hidden players still get the odd update instead of going stale
+pub const MIN_RELEVANCE: f32 = 0.02;
+//Sent every snapshot no matter what, e.g. the player the client controls
+pub const ALWAYS_RELEVANT: f32 = f32::INFINITY;
+
+//Points checked for line of sight as offsets down from the eye, so a head over a wall or legs under a gap still count
+const VISIBILITY_OFFSETS: [f32; 3] = [0.0, -0.7, -1.4];
+
+//Nothing in the world between the two points
+pub fn is_visible(from: glam::f32::Vec3, to: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> bool {
+
+    let ray = collision::Ray::new(from, to - from);
+
+    world.iter().all(|triangle_soup| {
+        let collision_packet = ray.vs_triangle_soup(triangle_soup);
+        !collision_packet.collided || collision_packet.penetration_or_time >= 1.0
+    })
+}
+
+//How much a viewer cares about a player, both positions are eyes
+pub fn get_relevance(viewer: glam::f32::Vec3, target: glam::f32::Vec3, world: &[&collision::TriangleSoup]) -> f32 {
+
+    let distance = viewer.distance(target);
+    let mut relevance = if distance <= FULL_RELEVANCE_DISTANCE { 1.0 } else { FULL_RELEVANCE_DISTANCE / distance };
+
+    if !VISIBILITY_OFFSETS.iter().any(|offset| is_visible(viewer, target + glam::f32::Vec3::Y * *offset, world)) {
+        relevance *= OCCLUDED_RELEVANCE_SCALE;
+    }
+
+    relevance.max(MIN_RELEVANCE)
+}
+
+//One per client, every tick each player builds up priority by how relevant it is and the highest go out first
+//Sending a player resets it, so less relevant players still get their turn, just less often
+pub struct PriorityAccumulator {
+    priorities: HashMap<u32, f32>,
+}
+
+impl Default for PriorityAccumulator {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl PriorityAccumulator {
+
+    pub fn new() -> Self {
+
+        Self { priorities: HashMap::new() }
+    }
+
+    //Call once per snapshot with every player that could be sent, returns their ids highest priority first
+    pub fn accumulate(&mut self, relevances: &[(u32, f32)]) -> Vec<u32> {
+
+        //Players that have gone start from nothing if they come back
+        self.priorities.retain(|id, _| relevances.iter().any(|(relevant_id, _)| relevant_id == id));
+
+        for (id, relevance) in relevances {
+            *self.priorities.entry(*id).or_insert(0.0) += relevance.max(0.0);
+        }
+
+        let mut order: Vec<(u32, f32)> = self.priorities.iter().map(|(id, priority)| (*id, *priority)).collect();
+        order.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        order.into_iter().map(|(id, _)| id).collect()
+    }
+
+    //Call with the ids that made it into the snapshot
+    pub fn reset(&mut self, ids: &[u32]) {
+
+        for id in ids {
+            if let Some(priority) = self.priorities.get_mut(id) {
+                *priority = 0.0;
+            }
+        }
+    }
+
+    pub fn get_priority(&self, id: u32) -> Option<f32> {
+
+        self.priorities.get(&id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall() -> collision::TriangleSoup {
+
+        //Floor to ceiling across x = 5
+        collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(5.0, -10.0, -10.0), glam::f32::Vec3::new(5.0, 10.0, -10.0), glam::f32::Vec3::new(5.0, -10.0, 10.0)),
+            collision::Triangle::new(glam::f32::Vec3::new(5.0, 10.0, -10.0), glam::f32::Vec3::new(5.0, 10.0, 10.0), glam::f32::Vec3::new(5.0, -10.0, 10.0)),
+        ])
+    }
+
+    #[test]
+    fn walls_block_line_of_sight() {
+
+        let wall = wall();
+        let world = [&wall];
+
+        assert!(!is_visible(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(10.0, 0.0, 0.0), &world));
+        //Short of the wall, and the wall behind the viewer doesn't count
+        assert!(is_visible(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(4.0, 0.0, 0.0), &world));
+        assert!(is_visible(glam::f32::Vec3::new(6.0, 0.0, 0.0), glam::f32::Vec3::new(10.0, 0.0, 0.0), &world));
+    }
+
+    #[test]
+    fn relevance_falls_off_with_distance_and_walls() {
+
+        let wall = wall();
+        let world = [&wall];
+
+        let near = get_relevance(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(0.0, 0.0, 3.0), &world);
+        let far = get_relevance(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(0.0, 0.0, -60.0), &world);
+        let hidden = get_relevance(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(10.0, 0.0, 0.0), &world);
+
+        assert_eq!(near, 1.0);
+        assert!((far - 0.25).abs() < 0.001);
+        assert_eq!(hidden, OCCLUDED_RELEVANCE_SCALE);
+        assert!(get_relevance(glam::f32::Vec3::ZERO, glam::f32::Vec3::new(1000.0, 0.0, 0.0), &world) >= MIN_RELEVANCE);
+    }
+
+    #[test]
+    fn less_relevant_players_still_get_a_turn() {
+
+        let mut accumulator = PriorityAccumulator::new();
+        let relevances = [(1, ALWAYS_RELEVANT), (2, 1.0), (3, 0.25)];
+        let mut sent_counts = HashMap::new();
+
+        //Room for the always relevant one and one other each snapshot
+        for _ in 0..100 {
+            let sent: Vec<u32> = accumulator.accumulate(&relevances).into_iter().take(2).collect();
+            for id in &sent {
+                *sent_counts.entry(*id).or_insert(0) += 1;
+            }
+            accumulator.reset(&sent);
+        }
+
+        assert_eq!(sent_counts[&1], 100);
+        assert_eq!(sent_counts[&2] + sent_counts[&3], 100);
+        assert!(sent_counts[&2] > sent_counts[&3] * 3);
+        assert!(sent_counts[&3] > 0);
+    }
+
+    #[test]
+    fn players_that_leave_are_forgotten() {
+
+        let mut accumulator = PriorityAccumulator::new();
+
+        accumulator.accumulate(&[(1, 1.0), (2, 1.0)]);
+        assert_eq!(accumulator.accumulate(&[(2, 1.0)]), vec![2]);
+        assert_eq!(accumulator.get_priority(1), None);
+        assert_eq!(accumulator.get_priority(2), Some(2.0));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::ChatChannel;
+use crate::damage::{DamageEvent, Scoreboard};
+use crate::movement::MovementSettings;
+use crate::net::MAX_PACKET_SIZE;
+use crate::states::States;
+
+//How long to wait for an ack before sending a message again
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+//Share of each datagram reliable messages can use, the rest is left for the packet they ride along with
+pub const MAX_RELIABLE_BYTES: usize = 512;
+//Messages further ahead than this of the next one we need are dropped, the sender will resend them
+const MAX_RECEIVE_WINDOW: u32 = 1024;
+
+//Things that have to arrive exactly once and in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReliableMessage {
+    Chat { player_id: u32, channel: ChatChannel, text: String },
+    ConsoleCommand(String),
+    MapChange(String),
+    KillFeed { attacker: u32, victim: u32, weapon: usize },
+    StateChange { state: States, round: u32 },
+    MovementSettings(MovementSettings),
+    //Sent to the attacker and the victim
+    Damage(DamageEvent),
+    Respawn { player_id: u32, position: glam::f32::Vec3 },
+    Scoreboard(Scoreboard),
+    //Everyone flies their own copy from where it left the barrel, only the server's does damage
+    ProjectileLaunch { owner: u32, weapon: usize, origin: glam::f32::Vec3, direction: glam::f32::Vec3 },
+}
+
+struct PendingMessage {
+    id: u32,
+    message: ReliableMessage,
+    size: usize,
+    last_sent: Option<Instant>,
+}
+
+//One per peer, message ids count up from 0 and the ack is the next id we are waiting on so it covers everything before it
+pub struct ReliableChannel {
+    next_send_id: u32,
+    pending: VecDeque<PendingMessage>,
+    next_receive_id: u32,
+    out_of_order: BTreeMap<u32, ReliableMessage>,
+    received: VecDeque<ReliableMessage>,
+    resend_interval: Duration,
+}
+
+impl Default for ReliableChannel {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl ReliableChannel {
+
+    pub fn new() -> Self {
+
+        Self { next_send_id: 0, pending: VecDeque::new(), next_receive_id: 0, out_of_order: BTreeMap::new(), received: VecDeque::new(), resend_interval: RESEND_INTERVAL }
+    }
+
+    //Fails if the message could never fit in a datagram
+    pub fn queue(&mut self, message: ReliableMessage) -> std::io::Result<()> {
+
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let size = postcard::to_slice(&(self.next_send_id, &message), &mut buffer).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?.len();
+        if size > MAX_RELIABLE_BYTES {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Reliable message is {} bytes, the limit is {}", size, MAX_RELIABLE_BYTES)));
+        }
+
+        self.pending.push_back(PendingMessage { id: self.next_send_id, message, size, last_sent: None });
+        self.next_send_id += 1;
+
+        Ok(())
+    }
+
+    //Messages to put in the next outgoing datagram, oldest first, anything sent recently is left for its ack to come back
+    pub fn write(&mut self, now: Instant) -> Vec<(u32, ReliableMessage)> {
+
+        let mut messages = Vec::new();
+        let mut bytes = 0;
+
+        for pending in self.pending.iter_mut() {
+            if pending.last_sent.is_some_and(|last_sent| now.duration_since(last_sent) < self.resend_interval) {
+                continue;
+            }
+            if bytes + pending.size > MAX_RELIABLE_BYTES {
+                break;
+            }
+
+            bytes += pending.size;
+            pending.last_sent = Some(now);
+            messages.push((pending.id, pending.message.clone()));
+        }
+
+        messages
+    }
+
+    //ack and messages come from a datagram the peer sent
+    pub fn read(&mut self, ack: u32, messages: Vec<(u32, ReliableMessage)>) {
+
+        while self.pending.front().is_some_and(|pending| pending.id < ack) {
+            self.pending.pop_front();
+        }
+
+        for (id, message) in messages {
+            //Already delivered or too far ahead to hold on to
+            if id < self.next_receive_id || id - self.next_receive_id >= MAX_RECEIVE_WINDOW {
+                continue;
+            }
+            self.out_of_order.insert(id, message);
+        }
+
+        while let Some(message) = self.out_of_order.remove(&self.next_receive_id) {
+            self.received.push_back(message);
+            self.next_receive_id += 1;
+        }
+    }
+
+    //Goes in every outgoing datagram
+    pub fn get_ack(&self) -> u32 {
+
+        self.next_receive_id
+    }
+
+    //Messages ready to be handled, in the order they were sent
+    pub fn drain(&mut self) -> Vec<ReliableMessage> {
+
+        self.received.drain(..).collect()
+    }
+
+    //Sent but not acked yet
+    pub fn get_pending_count(&self) -> usize {
+
+        self.pending.len()
+    }
+
+    pub fn set_resend_interval(&mut self, resend_interval: Duration) {
+
+        self.resend_interval = resend_interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(number: u32) -> ReliableMessage {
+
+        ReliableMessage::Chat { player_id: 1, channel: ChatChannel::All, text: format!("Message {}", number) }
+    }
+
+    #[test]
+    fn resends_until_acked() {
+
+        let mut sender = ReliableChannel::new();
+        let mut receiver = ReliableChannel::new();
+        let start = Instant::now();
+
+        sender.queue(chat(0)).unwrap();
+        //First one gets lost
+        assert_eq!(sender.write(start).len(), 1);
+        //Nothing again until the resend timer is up
+        assert!(sender.write(start + RESEND_INTERVAL / 2).is_empty());
+
+        let resent = sender.write(start + RESEND_INTERVAL);
+        assert_eq!(resent.len(), 1);
+        receiver.read(sender.get_ack(), resent);
+        assert_eq!(receiver.drain(), vec![chat(0)]);
+
+        sender.read(receiver.get_ack(), Vec::new());
+        assert_eq!(sender.get_pending_count(), 0);
+        assert!(sender.write(start + RESEND_INTERVAL * 10).is_empty());
+    }
+
+    #[test]
+    fn out_of_order_and_duplicates_are_delivered_once_in_order() {
+
+        let mut receiver = ReliableChannel::new();
+
+        receiver.read(0, vec![(2, chat(2)), (1, chat(1))]);
+        assert!(receiver.drain().is_empty());
+        assert_eq!(receiver.get_ack(), 0);
+
+        receiver.read(0, vec![(0, chat(0)), (1, chat(1))]);
+        assert_eq!(receiver.drain(), vec![chat(0), chat(1), chat(2)]);
+        assert_eq!(receiver.get_ack(), 3);
+
+        receiver.read(0, vec![(0, chat(0)), (2, chat(2))]);
+        assert!(receiver.drain().is_empty());
+    }
+
+    #[test]
+    fn writes_stay_within_the_byte_budget() {
+
+        let mut sender = ReliableChannel::new();
+        for number in 0..200 {
+            sender.queue(chat(number)).unwrap();
+        }
+
+        let written = sender.write(Instant::now());
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        assert!(postcard::to_slice(&written, &mut buffer).unwrap().len() <= MAX_RELIABLE_BYTES + 2);
+        assert_eq!(written[0].0, 0);
+        assert!(written.len() < 200);
+    }
+
+    #[test]
+    fn oversized_messages_are_rejected() {
+
+        let mut sender = ReliableChannel::new();
+
+        assert!(sender.queue(ReliableMessage::ConsoleCommand("a".repeat(MAX_RELIABLE_BYTES))).is_err());
+        assert_eq!(sender.get_pending_count(), 0);
+    }
+}
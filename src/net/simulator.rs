@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+
+//Extra hold back for a packet picked to be reordered, on top of latency and jitter
+pub const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+//Applied to each direction separately, so a 50ms latency adds 100ms to the round trip
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    //Each packet gets a random extra delay between 0 and this
+    pub jitter: Duration,
+    //Chances between 0 and 1
+    pub loss: f32,
+    pub duplication: f32,
+    pub reordering: f32,
+}
+
+struct DelayedDatagram {
+    deliver_at: Instant,
+    bytes: Vec<u8>,
+    address: SocketAddr,
+}
+
+//Sits between NetSocket and the real socket and makes localhost behave like the internet
+pub struct NetworkSimulator {
+    conditions: NetworkConditions,
+    seed: u64,
+    random: rand::rngs::StdRng,
+    outgoing: VecDeque<DelayedDatagram>,
+    incoming: VecDeque<DelayedDatagram>,
+    dropped: u64,
+    duplicated: u64,
+}
+
+impl NetworkSimulator {
+
+    //Same seed and the same packets gives the same losses and duplicates
+    pub fn new(conditions: NetworkConditions, seed: u64) -> Self {
+
+        Self { conditions, seed, random: rand::rngs::StdRng::seed_from_u64(seed), outgoing: VecDeque::new(), incoming: VecDeque::new(), dropped: 0, duplicated: 0 }
+    }
+
+    pub fn send(&mut self, bytes: &[u8], address: SocketAddr, now: Instant) {
+
+        let delays = self.roll();
+        Self::enqueue(&mut self.outgoing, bytes, address, now, delays);
+    }
+
+    pub fn receive(&mut self, bytes: &[u8], address: SocketAddr, now: Instant) {
+
+        let delays = self.roll();
+        Self::enqueue(&mut self.incoming, bytes, address, now, delays);
+    }
+
+    //Outgoing datagrams that are due to hit the real socket
+    pub fn take_outgoing(&mut self, now: Instant) -> Vec<(Vec<u8>, SocketAddr)> {
+
+        let mut due = Vec::new();
+        while self.outgoing.front().is_some_and(|delayed| delayed.deliver_at <= now) {
+            let delayed = self.outgoing.pop_front().unwrap();
+            due.push((delayed.bytes, delayed.address));
+        }
+
+        due
+    }
+
+    //Next incoming datagram that is due to be handed to the game
+    pub fn take_incoming(&mut self, now: Instant) -> Option<(Vec<u8>, SocketAddr)> {
+
+        if self.incoming.front().is_some_and(|delayed| delayed.deliver_at <= now) {
+            return self.incoming.pop_front().map(|delayed| (delayed.bytes, delayed.address));
+        }
+
+        None
+    }
+
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+
+        self.conditions = conditions;
+    }
+
+    pub fn get_conditions(&self) -> &NetworkConditions {
+
+        &self.conditions
+    }
+
+    //Starts the random sequence over
+    pub fn set_seed(&mut self, seed: u64) {
+
+        self.seed = seed;
+        self.random = rand::rngs::StdRng::seed_from_u64(seed);
+    }
+
+    pub fn get_seed(&self) -> u64 {
+
+        self.seed
+    }
+
+    pub fn get_dropped_count(&self) -> u64 {
+
+        self.dropped
+    }
+
+    pub fn get_duplicated_count(&self) -> u64 {
+
+        self.duplicated
+    }
+
+    pub fn get_queued_count(&self) -> usize {
+
+        self.outgoing.len() + self.incoming.len()
+    }
+
+    //Delay for each copy that should be delivered, empty if lost
+    //Always draws the same amount of randomness so one packet's fate never shifts the next one's
+    fn roll(&mut self) -> Vec<Duration> {
+
+        let lost = self.random.gen::<f32>() < self.conditions.loss;
+        let duplicated = self.random.gen::<f32>() < self.conditions.duplication;
+        let reordered = self.random.gen::<f32>() < self.conditions.reordering;
+        let jitter = [self.random.gen::<f32>(), self.random.gen::<f32>()];
+
+        if lost {
+            self.dropped += 1;
+            return Vec::new();
+        }
+
+        let mut delays = vec![self.conditions.latency + self.conditions.jitter.mul_f32(jitter[0])];
+        if reordered {
+            delays[0] += REORDER_DELAY;
+        }
+        if duplicated {
+            self.duplicated += 1;
+            delays.push(self.conditions.latency + self.conditions.jitter.mul_f32(jitter[1]));
+        }
+
+        delays
+    }
+
+    fn enqueue(queue: &mut VecDeque<DelayedDatagram>, bytes: &[u8], address: SocketAddr, now: Instant, delays: Vec<Duration>) {
+
+        for delay in delays {
+            let deliver_at = now + delay;
+            //Kept sorted by delivery time, equal times stay in the order they were sent
+            let index = queue.partition_point(|delayed| delayed.deliver_at <= deliver_at);
+            queue.insert(index, DelayedDatagram { deliver_at, bytes: bytes.to_vec(), address });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> SocketAddr {
+
+        ([127, 0, 0, 1], 27015).into()
+    }
+
+    #[test]
+    fn latency_holds_packets_back() {
+
+        let mut simulator = NetworkSimulator::new(NetworkConditions { latency: Duration::from_millis(100), ..Default::default() }, 0);
+        let start = Instant::now();
+
+        simulator.send(&[1], address(), start);
+        simulator.send(&[2], address(), start + Duration::from_millis(10));
+
+        assert!(simulator.take_outgoing(start + Duration::from_millis(99)).is_empty());
+        assert_eq!(simulator.take_outgoing(start + Duration::from_millis(105)), vec![(vec![1], address())]);
+        assert_eq!(simulator.take_outgoing(start + Duration::from_millis(110)), vec![(vec![2], address())]);
+    }
+
+    #[test]
+    fn reordered_packets_arrive_after_later_ones() {
+
+        let mut simulator = NetworkSimulator::new(NetworkConditions { reordering: 1.0, ..Default::default() }, 0);
+        let start = Instant::now();
+
+        simulator.receive(&[1], address(), start);
+        simulator.set_conditions(NetworkConditions::default());
+        simulator.receive(&[2], address(), start);
+
+        let later = start + REORDER_DELAY;
+        assert_eq!(simulator.take_incoming(later), Some((vec![2], address())));
+        assert_eq!(simulator.take_incoming(later), Some((vec![1], address())));
+        assert_eq!(simulator.take_incoming(later), None);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_losses() {
+
+        let conditions = NetworkConditions { loss: 0.3, duplication: 0.1, ..Default::default() };
+        let run = |seed: u64| {
+            let mut simulator = NetworkSimulator::new(conditions, seed);
+            let now = Instant::now();
+            for i in 0..200_u8 {
+                simulator.send(&[i], address(), now);
+            }
+            simulator.take_outgoing(now).into_iter().map(|(bytes, _)| bytes[0]).collect::<Vec<u8>>()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+}
@@ -1,19 +1,64 @@
-use crate::{collision, input::{Inputs, MOUSE_SENSITIVITY}};
+use serde::{Deserialize, Serialize};
+
+use crate::{character_controller::{self, CharacterController}, collision, damage, game_state::TICK_RATE_SECONDS, hitbox, input::{Inputs, MOUSE_SENSITIVITY}, movement, user_cmd::{self, UserCmd}, weapon};
+
+//Looking straight up or down flips the camera
+pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+//How fast the eye moves between standing and crouched height, units per second
+pub const VIEW_HEIGHT_SPEED: f32 = 6.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+
+    pub fn get_name(&self) -> &'static str {
+
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+}
+
+//Everything a tick of simulation starts from besides the command, so a prediction can be rolled back to it
+#[derive(Debug, Copy, Clone)]
+pub struct PlayerState {
+    pub position: glam::f32::Vec3,
+    pub velocity: glam::f32::Vec3,
+    pub controller: CharacterController,
+    pub stance: movement::Stance,
+    pub view_height: f32,
+    pub weapons: weapon::WeaponState,
+}
 
 pub struct Player {
     position: glam::f32::Vec3,
     velocity: glam::f32::Vec3,
+    controller: CharacterController,
+    stance: movement::Stance,
+    //Offset of the eye from the position, goes below zero while crouching
+    view_height: f32,
+    weapons: weapon::WeaponState,
+    //Only the server changes it, clients hear about each hit
+    health: damage::Health,
     forward: glam::f32::Vec3,
     right: glam::f32::Vec3,
     yaw: f32,
     pitch: f32,
+    team: Team,
+    //Seconds into the player model's animation, the server poses hitboxes from it
+    animation_time: f32,
 }
 
 impl Player {
     
     pub fn new(position: glam::f32::Vec3, yaw: f32) -> Self {
 
-        Self { position, velocity: glam::f32::Vec3::ZERO, forward: glam::f32::Vec3::Z, right: glam::f32::Vec3::X, yaw, pitch: 0.0 }
+        Self { position, velocity: glam::f32::Vec3::ZERO, controller: CharacterController::default(), stance: movement::Stance::Standing, view_height: 0.0, weapons: weapon::WeaponState::new(), health: damage::Health::new(), forward: glam::f32::Vec3::Z, right: glam::f32::Vec3::X, yaw, pitch: 0.0, team: Team::Red, animation_time: 0.0 }
     }
 
     pub fn translate(&mut self, translation: glam::f32::Vec3) {
@@ -27,25 +72,149 @@ impl Player {
         self.position -= translation.x * glam::f32::Vec3::Y.cross(self.forward).normalize_or_zero();
     }
 
+    //One tick of movement and shooting, has to give the same result on the client and the server so prediction lines up
+    //Returns the shot if a weapon went off, it's up to the caller what it hits
+    pub fn simulate(&mut self, command: &UserCmd, settings: &movement::MovementSettings, world: &[&collision::TriangleSoup]) -> Option<weapon::Shot> {
+
+        self.set_view(command.yaw, command.pitch);
+
+        //The dead can look around until they respawn
+        if self.health.is_dead() {
+            return None;
+        }
+
+        self.animation_time += TICK_RATE_SECONDS;
+
+        //Walking only ever goes along the ground whatever the pitch
+        let forward = glam::f32::Vec3::new(self.yaw.cos(), 0.0, self.yaw.sin());
+        let right = forward.cross(glam::f32::Vec3::Y);
+        let movement = command.movement.clamp(glam::f32::Vec3::NEG_ONE, glam::f32::Vec3::ONE);
+        let wish = forward * movement.z + right * movement.x;
+
+        let grounded = self.controller.is_grounded();
+        let stance = movement::get_next_stance(self.stance, command.is_pressed(user_cmd::BUTTON_CROUCH), grounded, self.velocity.length(), settings);
+        match stance {
+            movement::Stance::Standing => {
+                //Stays down until there's room above
+                if self.controller.stand_up(self.position, world) {
+                    self.stance = stance;
+                }
+            }
+            _ => {
+                self.controller.crouch();
+                self.stance = stance;
+            }
+        }
+
+        let ground_normal = if grounded { Some(*self.controller.get_ground_normal()) } else { None };
+        if movement::update_velocity(&mut self.velocity, wish, ground_normal, command.is_pressed(user_cmd::BUTTON_JUMP), self.stance, settings, TICK_RATE_SECONDS) {
+            self.controller.leave_ground();
+        }
+
+        self.controller.move_and_slide(&mut self.position, &mut self.velocity, TICK_RATE_SECONDS, world);
+
+        let target_view_height = if self.controller.is_crouched() { character_controller::CROUCH_HEIGHT - character_controller::PLAYER_HEIGHT } else { 0.0 };
+        let max_change = VIEW_HEIGHT_SPEED * TICK_RATE_SECONDS;
+        self.view_height += (target_view_height - self.view_height).clamp(-max_change, max_change);
+
+        let direction = self.weapons.update(command, self.forward)?;
+
+        Some(weapon::Shot { weapon: self.weapons.get_current(), origin: self.get_eye_position(), direction })
+    }
+
+    pub fn get_state(&self) -> PlayerState {
+
+        PlayerState { position: self.position, velocity: self.velocity, controller: self.controller, stance: self.stance, view_height: self.view_height, weapons: self.weapons }
+    }
+
+    pub fn set_state(&mut self, state: PlayerState) {
+
+        self.position = state.position;
+        self.velocity = state.velocity;
+        self.controller = state.controller;
+        self.stance = state.stance;
+        self.view_height = state.view_height;
+        self.weapons = state.weapons;
+    }
+
+    pub fn get_controller(&self) -> &CharacterController {
+
+        &self.controller
+    }
+
+    pub fn get_weapons(&self) -> &weapon::WeaponState {
+
+        &self.weapons
+    }
+
+    pub fn get_health(&self) -> &damage::Health {
+
+        &self.health
+    }
+
+    pub fn get_mut_health(&mut self) -> &mut damage::Health {
+
+        &mut self.health
+    }
+
+    //Back at full health with full magazines and nothing carried over from the last life
+    pub fn respawn(&mut self, position: glam::f32::Vec3) {
+
+        self.set_state(PlayerState { position, velocity: glam::f32::Vec3::ZERO, controller: CharacterController::default(), stance: movement::Stance::Standing, view_height: 0.0, weapons: weapon::WeaponState::new() });
+        self.health = damage::Health::new();
+        self.animation_time = 0.0;
+    }
+
+    pub fn get_animation_time(&self) -> f32 {
+
+        self.animation_time
+    }
+
+    pub fn get_stance(&self) -> movement::Stance {
+
+        self.stance
+    }
+
+    //Where the camera goes, lower than the position while crouched
+    pub fn get_eye_position(&self) -> glam::f32::Vec3 {
+
+        self.position + glam::f32::Vec3::Y * self.view_height
+    }
+
+    pub fn set_position(&mut self, position: glam::f32::Vec3) {
+
+        self.position = position;
+    }
+
     pub fn get_position(&self) -> &glam::f32::Vec3 {
 
         &self.position
     }
 
+    pub fn get_velocity(&self) -> &glam::f32::Vec3 {
+
+        &self.velocity
+    }
+
     pub fn input(&mut self, inputs: &mut Inputs) {
         
         let mouse_motion = inputs.get_mouse_motion();
 
-        self.yaw = (self.yaw + (mouse_motion[0] * MOUSE_SENSITIVITY)) % 360.0_f32.to_radians();
+        self.set_view((self.yaw + (mouse_motion[0] * MOUSE_SENSITIVITY)) % 360.0_f32.to_radians(), self.pitch - (mouse_motion[1] * MOUSE_SENSITIVITY));
+    }
+
+    pub fn set_view(&mut self, yaw: f32, pitch: f32) {
 
-        self.pitch = (self.pitch - (mouse_motion[1] * MOUSE_SENSITIVITY)).clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+        self.yaw = yaw;
+
+        self.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
 
         self.forward = glam::f32::Vec3::new(self.pitch.cos() * self.yaw.cos(), self.pitch.sin(), self.pitch.cos() * self.yaw.sin()).normalize_or_zero();
     }
 
     pub fn get_camera_transform(&self) -> glam::f32::Mat4 {
 
-        glam::f32::Mat4::from_rotation_translation(glam::f32::Quat::from_rotation_y(self.yaw), self.position)
+        glam::f32::Mat4::from_rotation_translation(glam::f32::Quat::from_rotation_y(self.yaw), self.get_eye_position())
     }
 
     pub fn get_yaw(&self) -> f32 {
@@ -62,4 +231,24 @@ impl Player {
 
         &self.forward
     }
+
+    pub fn set_team(&mut self, team: Team) {
+
+        self.team = team;
+    }
+
+    pub fn get_team(&self) -> Team {
+
+        self.team
+    }
+
+    //Posed from the player model's bones, get_bone gives None for any it can't, see hitbox::pose_hitboxes
+    pub fn get_hitboxes(&self, get_bone: impl Fn(&str) -> Option<glam::f32::Mat4>) -> Vec<hitbox::Hitbox> {
+
+        //Squashed towards the feet as the eye comes down
+        let scale = (character_controller::EYE_HEIGHT + self.view_height) / character_controller::EYE_HEIGHT;
+        let feet = self.position - glam::f32::Vec3::Y * character_controller::EYE_HEIGHT;
+
+        hitbox::pose_hitboxes(feet, self.yaw, scale, get_bone)
+    }
 }
\ No newline at end of file
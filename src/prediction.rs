@@ -0,0 +1,240 @@
+use crate::collision;
+use crate::movement::MovementSettings;
+use crate::player::{PlayerState, Player};
+use crate::user_cmd::UserCmd;
+use crate::weapon;
+
+//About two seconds of ticks, anything older than this can't be reconciled
+pub const PREDICTION_BUFFER_SIZE: usize = 128;
+
+//Below this the server and client are considered to agree, has to be above the snapshot position quantization
+const MAX_PREDICTION_ERROR: f32 = 0.01;
+//Further than this and smoothing would look worse than just snapping, e.g. a teleport
+const MAX_SMOOTHED_CORRECTION: f32 = 2.0;
+//Fraction of the visual correction left after each tick
+const CORRECTION_DECAY: f32 = 0.85;
+
+#[derive(Debug, Copy, Clone)]
+struct PredictedTick {
+    command: UserCmd,
+    //Where the player ended up after the input was applied, and how it was moving
+    state: PlayerState,
+}
+
+//Ring buffer of local inputs and the state they produced, keyed by GameState::current_tick
+pub struct Prediction {
+    history: Vec<Option<PredictedTick>>,
+    latest_tick: Option<u32>,
+    correction_offset: glam::f32::Vec3,
+}
+
+impl Default for Prediction {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl Prediction {
+
+    pub fn new() -> Self {
+
+        Self { history: vec![None; PREDICTION_BUFFER_SIZE], latest_tick: None, correction_offset: glam::f32::Vec3::ZERO }
+    }
+
+    //Applies the command to the player and remembers it so it can be replayed, returns the shot if the player fired
+    pub fn predict(&mut self, player: &mut Player, command: UserCmd, settings: &MovementSettings, world: &[&collision::TriangleSoup]) -> Option<weapon::Shot> {
+
+        let shot = player.simulate(&command, settings, world);
+
+        self.history[command.tick as usize % PREDICTION_BUFFER_SIZE] = Some(PredictedTick { command, state: player.get_state() });
+        self.latest_tick = Some(command.tick);
+
+        shot
+    }
+
+    //Server says the player was at server_position with server_weapons after it applied our input for acked_tick
+    //Returns true if the prediction was wrong and the pending inputs were replayed
+    pub fn reconcile(&mut self, player: &mut Player, acked_tick: u32, server_position: glam::f32::Vec3, server_weapons: Option<weapon::WeaponState>, settings: &MovementSettings, world: &[&collision::TriangleSoup]) -> bool {
+
+        let Some(latest_tick) = self.latest_tick else {
+            return false;
+        };
+
+        let Some(acked) = self.get(acked_tick) else {
+            return false;
+        };
+
+        let weapons = server_weapons.unwrap_or(acked.state.weapons);
+        if acked.state.position.distance(server_position) <= MAX_PREDICTION_ERROR && acked.state.weapons == weapons {
+            return false;
+        }
+
+        let predicted_position = *player.get_position();
+
+        //Roll back to what the server had and replay everything it hasn't seen yet, the velocity and ground are ours since the server doesn't send them
+        //Shots that come out of the replay already went off when they were first predicted
+        let rolled_back = PlayerState { position: server_position, weapons, ..acked.state };
+        player.set_state(rolled_back);
+        self.history[acked_tick as usize % PREDICTION_BUFFER_SIZE].as_mut().unwrap().state = rolled_back;
+
+        for tick in acked_tick.wrapping_add(1)..=latest_tick {
+            if let Some(pending) = self.get(tick) {
+                player.simulate(&pending.command, settings, world);
+                self.history[tick as usize % PREDICTION_BUFFER_SIZE].as_mut().unwrap().state = player.get_state();
+            }
+        }
+
+        //Render from where we were and ease towards the corrected position
+        self.correction_offset += predicted_position - *player.get_position();
+        if self.correction_offset.length() > MAX_SMOOTHED_CORRECTION {
+            self.correction_offset = glam::f32::Vec3::ZERO;
+        }
+
+        true
+    }
+
+    //Call once per tick
+    pub fn decay_correction(&mut self) {
+
+        self.correction_offset *= CORRECTION_DECAY;
+        if self.correction_offset.length() < MAX_PREDICTION_ERROR {
+            self.correction_offset = glam::f32::Vec3::ZERO;
+        }
+    }
+
+    //Add to the simulated position when rendering
+    pub fn get_correction_offset(&self) -> &glam::f32::Vec3 {
+
+        &self.correction_offset
+    }
+
+    pub fn get_latest_tick(&self) -> Option<u32> {
+
+        self.latest_tick
+    }
+
+    fn get(&self, tick: u32) -> Option<PredictedTick> {
+
+        //Slot may hold a tick from a lap ago
+        self.history[tick as usize % PREDICTION_BUFFER_SIZE].filter(|predicted| predicted.command.tick == tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_cmd;
+
+    fn forward(tick: u32) -> UserCmd {
+
+        UserCmd::new(tick, glam::f32::Vec3::Z, 0.0, 0.0, 0, 0.0)
+    }
+
+    #[test]
+    fn matching_server_state_needs_no_correction() {
+
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut server_player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut prediction = Prediction::new();
+
+        for tick in 0..10 {
+            prediction.predict(&mut player, forward(tick), &MovementSettings::default(), &[]);
+        }
+        for _ in 0..5 {
+            server_player.simulate(&forward(0), &MovementSettings::default(), &[]);
+        }
+
+        assert!(!prediction.reconcile(&mut player, 4, *server_player.get_position(), None, &MovementSettings::default(), &[]));
+        assert_eq!(*prediction.get_correction_offset(), glam::f32::Vec3::ZERO);
+    }
+
+    #[test]
+    fn divergence_replays_pending_inputs_from_server_state() {
+
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut prediction = Prediction::new();
+
+        for tick in 0..10 {
+            prediction.predict(&mut player, forward(tick), &MovementSettings::default(), &[]);
+        }
+        let predicted = *player.get_position();
+
+        //Server got bumped half a unit sideways at tick 4
+        let mut server_player = Player::new(glam::f32::Vec3::new(0.0, 0.0, 0.5), 0.0);
+        for _ in 0..5 {
+            server_player.simulate(&forward(0), &MovementSettings::default(), &[]);
+        }
+
+        assert!(prediction.reconcile(&mut player, 4, *server_player.get_position(), None, &MovementSettings::default(), &[]));
+
+        //Five replayed ticks on top of the server position
+        for _ in 5..10 {
+            server_player.simulate(&forward(0), &MovementSettings::default(), &[]);
+        }
+        assert!(player.get_position().distance(*server_player.get_position()) < 0.0001);
+
+        //Rendering starts where we were, not where we snapped to
+        let rendered = *player.get_position() + *prediction.get_correction_offset();
+        assert!(rendered.distance(predicted) < 0.0001);
+
+        for _ in 0..100 {
+            prediction.decay_correction();
+        }
+        assert_eq!(*prediction.get_correction_offset(), glam::f32::Vec3::ZERO);
+    }
+
+    #[test]
+    fn inputs_older_than_the_buffer_are_ignored() {
+
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut prediction = Prediction::new();
+
+        for tick in 0..(PREDICTION_BUFFER_SIZE as u32 * 2) {
+            prediction.predict(&mut player, forward(tick), &MovementSettings::default(), &[]);
+        }
+
+        assert!(!prediction.reconcile(&mut player, 3, glam::f32::Vec3::splat(100.0), None, &MovementSettings::default(), &[]));
+    }
+
+    #[test]
+    fn weapon_divergence_replays_too() {
+
+        let firing = |tick: u32| UserCmd::new(tick, glam::f32::Vec3::ZERO, 0.0, 0.0, user_cmd::BUTTON_FIRE, 0.0);
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut prediction = Prediction::new();
+
+        for tick in 0..10 {
+            prediction.predict(&mut player, firing(tick), &MovementSettings::default(), &[]);
+        }
+        //Semi automatic, only the first tick fired
+        assert_eq!(player.get_weapons().get_ammo(), weapon::WEAPONS[0].magazine_size - 1);
+
+        //Server never saw fire on the first tick, so it fired on the second instead
+        let mut server_player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        server_player.simulate(&forward(0), &MovementSettings::default(), &[]);
+        for tick in 1..5 {
+            server_player.simulate(&firing(tick), &MovementSettings::default(), &[]);
+        }
+
+        assert!(prediction.reconcile(&mut player, 4, *server_player.get_position(), Some(*server_player.get_weapons()), &MovementSettings::default(), &[]));
+        for tick in 5..10 {
+            server_player.simulate(&firing(tick), &MovementSettings::default(), &[]);
+        }
+        assert_eq!(player.get_weapons(), server_player.get_weapons());
+    }
+
+    #[test]
+    fn large_corrections_snap() {
+
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let mut prediction = Prediction::new();
+
+        prediction.predict(&mut player, forward(0), &MovementSettings::default(), &[]);
+
+        assert!(prediction.reconcile(&mut player, 0, glam::f32::Vec3::splat(50.0), None, &MovementSettings::default(), &[]));
+        assert_eq!(*player.get_position(), glam::f32::Vec3::splat(50.0));
+        assert_eq!(*prediction.get_correction_offset(), glam::f32::Vec3::ZERO);
+    }
+}
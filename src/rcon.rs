@@ -0,0 +1,289 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::console::Console;
+
+//Line based so netcat works as a client
+//The first line is the password, every line after that is a console command
+//Each reply is the command's output followed by an empty line, output never has empty lines of its own
+pub const MAX_RCON_CONNECTIONS: usize = 4;
+//Connections one address can have waiting on a password, so nobody can sit on every slot
+pub const MAX_RCON_PENDING_PER_ADDRESS: usize = 1;
+//Admin actions kept in memory, the log file has all of them
+pub const MAX_RCON_ADMIN_LOG: usize = 1024;
+//Longer than this without a newline and the connection is dropped
+pub const MAX_RCON_LINE_LENGTH: usize = 1024;
+//Commands each connection can run per second on average, with bursts up to RCON_COMMAND_BURST
+pub const RCON_COMMANDS_PER_SECOND: f32 = 4.0;
+pub const RCON_COMMAND_BURST: f32 = 8.0;
+//Wrong passwords from one address before it is locked out for RCON_LOCKOUT
+pub const MAX_RCON_AUTH_FAILURES: u32 = 3;
+pub const RCON_LOCKOUT: Duration = Duration::from_secs(60);
+//Connections that never authenticate are dropped after this
+const RCON_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const RCON_AUTHENTICATED: &str = "Authenticated";
+pub const RCON_BAD_PASSWORD: &str = "Bad password";
+pub const RCON_LOCKED_OUT: &str = "Too many bad passwords, try again later";
+pub const RCON_RATE_LIMITED: &str = "Too many commands, slow down";
+
+//A command from an authenticated connection, the output goes back with send_output
+#[derive(Debug, Clone, PartialEq)]
+pub struct RconCommand {
+    pub connection_id: u32,
+    pub address: SocketAddr,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminAction {
+    pub time: SystemTime,
+    pub address: SocketAddr,
+    pub action: String,
+}
+
+struct RconConnection {
+    id: u32,
+    stream: TcpStream,
+    address: SocketAddr,
+    connected: Instant,
+    authenticated: bool,
+    received: Vec<u8>,
+    outgoing: Vec<u8>,
+    //Token bucket for rate limiting
+    command_allowance: f32,
+    last_command: Instant,
+    closing: bool,
+}
+
+impl RconConnection {
+
+    fn queue_reply(&mut self, reply: &str) {
+
+        for line in reply.lines().filter(|line| !line.is_empty()) {
+            self.outgoing.extend_from_slice(line.as_bytes());
+            self.outgoing.push(b'\n');
+        }
+        self.outgoing.push(b'\n');
+    }
+
+    //Writes as much as the socket will take, false if the connection is gone
+    fn flush(&mut self) -> bool {
+
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return false,
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+//Server side, lets an admin run console commands over TCP
+pub struct RconServer {
+    listener: TcpListener,
+    password: String,
+    connections: Vec<RconConnection>,
+    next_connection_id: u32,
+    auth_failures: HashMap<IpAddr, (u32, Instant)>,
+    admin_log: VecDeque<AdminAction>,
+    log_file: Option<std::fs::File>,
+    console: Rc<RefCell<Console>>,
+}
+
+impl RconServer {
+
+    pub fn bind<A: ToSocketAddrs>(address: A, password: &str, console: Rc<RefCell<Console>>) -> std::io::Result<Self> {
+
+        if password.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "RCON needs a password"));
+        }
+
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self { listener, password: password.to_string(), connections: Vec::new(), next_connection_id: 0, auth_failures: HashMap::new(), admin_log: VecDeque::new(), log_file: None, console })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+
+        self.listener.local_addr()
+    }
+
+    //Admin actions are appended to this file as well as the newest MAX_RCON_ADMIN_LOG being kept in memory
+    pub fn set_log_file(&mut self, path: &str) -> std::io::Result<()> {
+
+        self.log_file = Some(std::fs::OpenOptions::new().create(true).append(true).open(path)?);
+
+        Ok(())
+    }
+
+    //Call once per tick, accepts connections, checks passwords and returns commands that are ready to run
+    pub fn poll(&mut self) -> Vec<RconCommand> {
+
+        self.accept();
+
+        let mut commands = Vec::new();
+        let mut actions = Vec::new();
+        let now = Instant::now();
+
+        self.auth_failures.retain(|_, (_, last_failure)| now.duration_since(*last_failure) < RCON_LOCKOUT);
+
+        for connection in self.connections.iter_mut() {
+            let mut buffer = [0; 512];
+            loop {
+                match connection.stream.read(&mut buffer) {
+                    Ok(0) => {
+                        connection.closing = true;
+                        break;
+                    }
+                    Ok(size) => connection.received.extend_from_slice(&buffer[..size]),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        connection.closing = true;
+                        break;
+                    }
+                }
+            }
+
+            while let Some(end) = connection.received.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = connection.received.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+
+                if !connection.authenticated {
+                    let failures = self.auth_failures.get(&connection.address.ip()).map_or(0, |(failures, _)| *failures);
+                    if failures >= MAX_RCON_AUTH_FAILURES {
+                        connection.queue_reply(RCON_LOCKED_OUT);
+                        connection.closing = true;
+                        break;
+                    }
+                    if is_password_correct(&line, &self.password) {
+                        connection.authenticated = true;
+                        connection.queue_reply(RCON_AUTHENTICATED);
+                        actions.push((connection.address, "Authenticated".to_string()));
+                    }
+                    else {
+                        let entry = self.auth_failures.entry(connection.address.ip()).or_insert((0, now));
+                        *entry = (entry.0 + 1, now);
+                        connection.queue_reply(RCON_BAD_PASSWORD);
+                        actions.push((connection.address, format!("Bad password, attempt {}", entry.0)));
+                        if entry.0 >= MAX_RCON_AUTH_FAILURES {
+                            connection.closing = true;
+                            break;
+                        }
+                    }
+                    continue;
+                }
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                connection.command_allowance = (connection.command_allowance + now.duration_since(connection.last_command).as_secs_f32() * RCON_COMMANDS_PER_SECOND).min(RCON_COMMAND_BURST);
+                connection.last_command = now;
+                if connection.command_allowance < 1.0 {
+                    connection.queue_reply(RCON_RATE_LIMITED);
+                    actions.push((connection.address, format!("Rate limited: {}", line)));
+                    continue;
+                }
+                connection.command_allowance -= 1.0;
+
+                actions.push((connection.address, format!("Command: {}", line)));
+                commands.push(RconCommand { connection_id: connection.id, address: connection.address, command: line });
+            }
+
+            if connection.received.len() > MAX_RCON_LINE_LENGTH {
+                actions.push((connection.address, "Line too long, disconnected".to_string()));
+                connection.closing = true;
+            }
+            if !connection.authenticated && now.duration_since(connection.connected) >= RCON_AUTH_TIMEOUT {
+                connection.closing = true;
+            }
+        }
+
+        for (address, action) in actions {
+            self.log_action(address, action);
+        }
+
+        //Let any last reply go out before closing
+        self.connections.retain_mut(|connection| connection.flush() && !connection.closing);
+
+        commands
+    }
+
+    //Output of a command returned by poll
+    pub fn send_output(&mut self, connection_id: u32, output: &str) {
+
+        if let Some(connection) = self.connections.iter_mut().find(|connection| connection.id == connection_id) {
+            connection.queue_reply(output);
+            if !connection.flush() {
+                connection.closing = true;
+            }
+        }
+    }
+
+    pub fn get_connection_count(&self) -> usize {
+
+        self.connections.len()
+    }
+
+    //Oldest first
+    pub fn get_admin_log(&self) -> &VecDeque<AdminAction> {
+
+        &self.admin_log
+    }
+
+    fn accept(&mut self) {
+
+        while let Ok((stream, address)) = self.listener.accept() {
+            let pending = self.connections.iter().filter(|connection| !connection.authenticated && connection.address.ip() == address.ip()).count();
+            if self.connections.len() >= MAX_RCON_CONNECTIONS || pending >= MAX_RCON_PENDING_PER_ADDRESS || stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+
+            let now = Instant::now();
+            self.connections.push(RconConnection { id: self.next_connection_id, stream, address, connected: now, authenticated: false, received: Vec::new(), outgoing: Vec::new(), command_allowance: RCON_COMMAND_BURST, last_command: now, closing: false });
+            self.next_connection_id += 1;
+        }
+    }
+
+    fn log_action(&mut self, address: SocketAddr, action: String) {
+
+        let line = format!("RCON {}: {}", address, action);
+        self.console.borrow_mut().output_to_console(&line);
+        if let Some(log_file) = self.log_file.as_mut() {
+            let seconds = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            let _ = writeln!(log_file, "{} {}", seconds, line);
+        }
+
+        if self.admin_log.len() >= MAX_RCON_ADMIN_LOG {
+            self.admin_log.pop_front();
+        }
+        self.admin_log.push_back(AdminAction { time: SystemTime::now(), address, action });
+    }
+}
+
+//Takes the same time however much of the password is right
+fn is_password_correct(attempt: &str, password: &str) -> bool {
+
+    let attempt = attempt.as_bytes();
+    let password = password.as_bytes();
+
+    let mut difference = attempt.len() ^ password.len();
+    for (i, byte) in password.iter().enumerate() {
+        difference |= (*byte ^ attempt.get(i).copied().unwrap_or(0)) as usize;
+    }
+
+    difference == 0
+}
@@ -2,17 +2,54 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::audio::WavAudioData;
+use crate::collision;
 use crate::console::*;
+use crate::hitbox;
 use crate::model;
 use crate::texture;
+#[cfg(feature = "client")]
 use crate::audio;
 
+//Make just scan folder, need better way of telling we should generate collision
+//(path, generate collision, has animation)
+const ASSETS: [(&str, bool, bool); 11] = [("./assets/cube.glb", false, false),
+    ("./assets/sphere.glb", false, false),
+    ("./assets/capsule.glb", false, false),
+    ("./assets/cylinder.glb", false, false),
+    ("./assets/test_triangle.glb", true, false),
+    ("./assets/Roll_Caskett.glb", false, true),
+    ("./assets/Roll_Caskett.png", false, false),
+    ("./assets/dot_crosshair.png", false, false),
+    ("./assets/tree.jpg", false, false),
+    ("./assets/debug.png", false, false),
+    ("./assets/hitsound480.wav", false, false)];
+
+//Only one level so far, it's whatever collides in ASSETS
+pub const DEFAULT_MAP: &str = "test_triangle";
+
+//Both ends of a connection have to agree on this, FNV-1a so it doesn't change between builds
+pub fn get_asset_manifest_hash() -> u64 {
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (path, collide, animated) in ASSETS {
+        for byte in path.bytes().chain([collide as u8, animated as u8]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    hash
+}
+
 pub struct ResourceManager {
     models: HashMap<String, model::Model>,
+    collisions: HashMap<String, collision::TriangleSoup>,
     skeleton_models: HashMap<String, model::SkeletonModel>,
+    //Also filled in by load_skeleton_model, the server poses hitboxes from these
+    skeletons: HashMap<String, model::SkeletonData>,
     textures: HashMap<String, texture::Texture>,
-    sounds: HashMap<String, WavAudioData>,
+    #[cfg(feature = "client")]
+    sounds: HashMap<String, audio::WavAudioData>,
     console: Rc<RefCell<Console>>,
 }
 
@@ -20,7 +57,7 @@ impl ResourceManager {
 
     pub fn new(console: Rc<RefCell<Console>>) -> Self {
         
-        Self { models: HashMap::new(), skeleton_models: HashMap::new(), textures: HashMap::new(), sounds: HashMap::new(), console }
+        Self { models: HashMap::new(), collisions: HashMap::new(), skeleton_models: HashMap::new(), skeletons: HashMap::new(), textures: HashMap::new(), #[cfg(feature = "client")] sounds: HashMap::new(), console }
     }
 
     pub fn load_model(&mut self, device: &wgpu::Device, path: &str, with_collision: bool) {
@@ -34,13 +71,70 @@ impl ResourceManager {
         }
         else {
             self.console.borrow_mut().output_to_console(&format!("Now loading {} at path {}", name, path));
-            self.models.insert(name.to_string(), model::Model::new(device, path, with_collision));
+            let mesh_data = model::MeshData::new(path);
+            if with_collision {
+                self.collisions.insert(name.to_string(), mesh_data.generate_triangle_soup());
+            }
+            self.models.insert(name.to_string(), model::Model::new(device, &mesh_data));
+        }
+
+        let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
+        self.console.borrow_mut().output_to_console(&format!("{} took {}ms to load", name, milli_time));
+    }
+
+    //Collision only, used by the dedicated server which has no GPU
+    pub fn load_collision(&mut self, path: &str) {
+
+        let start = std::time::Instant::now();
+
+        let name = path.split("/").last().unwrap().split(".").nth(0).unwrap();
+
+        if self.collisions.contains_key(name) {
+            self.console.borrow_mut().output_to_console(&format!("Already loaded collision: {} at {}", name, path));
+        }
+        else {
+            self.console.borrow_mut().output_to_console(&format!("Now loading collision {} at path {}", name, path));
+            self.collisions.insert(name.to_string(), model::MeshData::new(path).generate_triangle_soup());
         }
 
         let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
         self.console.borrow_mut().output_to_console(&format!("{} took {}ms to load", name, milli_time));
     }
 
+    //Skeleton and animations only, used by the dedicated server which has no GPU
+    pub fn load_skeleton(&mut self, path: &str) {
+
+        let start = std::time::Instant::now();
+
+        let name = path.split("/").last().unwrap().split(".").nth(0).unwrap();
+
+        if self.skeletons.contains_key(name) {
+            self.console.borrow_mut().output_to_console(&format!("Already loaded skeleton: {} at {}", name, path));
+        }
+        else {
+            self.console.borrow_mut().output_to_console(&format!("Now loading skeleton {} at path {}", name, path));
+            self.skeletons.insert(name.to_string(), model::SkeletonData::new(path));
+            self.check_hitbox_bones(name);
+        }
+
+        let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
+        self.console.borrow_mut().output_to_console(&format!("{} took {}ms to load", name, milli_time));
+    }
+
+    //A renamed bone silently leaves its hitbox at rest, say so while loading instead
+    fn check_hitbox_bones(&self, name: &str) {
+
+        if name != hitbox::PLAYER_MODEL {
+            return;
+        }
+
+        if let Some(skeleton) = self.skeletons.get(name) {
+            for bone in hitbox::get_missing_bones(skeleton.get_bone_names()) {
+                self.console.borrow_mut().output_to_console(&format!("{} has no bone named {}, its hitbox won't be animated", name, bone));
+            }
+        }
+    }
+
     pub fn load_skeleton_model(&mut self, device: &wgpu::Device, path: &str) {
 
         let start = std::time::Instant::now();
@@ -52,7 +146,10 @@ impl ResourceManager {
         }
         else {
             self.console.borrow_mut().output_to_console(&format!("Now loading {} at path {}", name, path));
-            self.skeleton_models.insert(name.to_string(), model::SkeletonModel::new(device, path));
+            let skeleton_model = model::SkeletonModel::new(device, path);
+            self.skeletons.insert(name.to_string(), skeleton_model.get_skeleton_data().clone());
+            self.skeleton_models.insert(name.to_string(), skeleton_model);
+            self.check_hitbox_bones(name);
         }
 
         let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
@@ -78,6 +175,7 @@ impl ResourceManager {
         self.console.borrow_mut().output_to_console(&format!("{} took {}ms to load", name, milli_time));
     }
 
+    #[cfg(feature = "client")]
     pub fn load_wav(&mut self, path: &str) {
 
         let start = std::time::Instant::now();
@@ -102,11 +200,27 @@ impl ResourceManager {
         self.models.get(name)
     }
 
+    pub fn get_collision(&self, name: &str) -> Option<&collision::TriangleSoup> {
+
+        self.collisions.get(name)
+    }
+
+    //Everything loaded with collision, i.e. the world
+    pub fn get_collisions(&self) -> Vec<&collision::TriangleSoup> {
+
+        self.collisions.values().collect()
+    }
+
     pub fn get_skeleton_model(&self, name: &str) -> Option<&model::SkeletonModel> {
 
         self.skeleton_models.get(name)
     }
 
+    pub fn get_skeleton(&self, name: &str) -> Option<&model::SkeletonData> {
+
+        self.skeletons.get(name)
+    }
+
     pub fn get_mut_skeleton_model(&mut self, name: &str) -> Option<&mut model::SkeletonModel> {
 
         self.skeleton_models.get_mut(name)
@@ -117,6 +231,7 @@ impl ResourceManager {
         self.textures.get(name)
     }
 
+    #[cfg(feature = "client")]
     pub fn get_sound(&self, name: &str) -> Option<&audio::WavAudioData> {
 
         self.sounds.get(name)
@@ -126,20 +241,7 @@ impl ResourceManager {
 
         let start = std::time::Instant::now();
         
-        //Make just scan folder, need better way of telling we should generate collision
-        let things_to_load: Vec<(&str, bool, bool)> = vec![("./assets/cube.glb", false, false),
-            ("./assets/sphere.glb", false, false),
-            ("./assets/capsule.glb", false, false),
-            ("./assets/cylinder.glb", false, false),
-            ("./assets/test_triangle.glb", true, false),
-            ("./assets/Roll_Caskett.glb", false, true),
-            ("./assets/Roll_Caskett.png", false, false),
-            ("./assets/dot_crosshair.png", false, false),
-            ("./assets/tree.jpg", false, false),
-            ("./assets/debug.png", false, false),
-            ("./assets/hitsound480.wav", false, false)];
-
-        for (path, collide, has_animation) in things_to_load {
+        for (path, collide, has_animation) in ASSETS {
             if path.contains(".glb") {
                 if has_animation {
                     self.load_skeleton_model(device, path);
@@ -149,6 +251,7 @@ impl ResourceManager {
                 }
             }
             else if path.contains(".wav") {
+                #[cfg(feature = "client")]
                 self.load_wav(path);
             }
             else {
@@ -159,4 +262,22 @@ impl ResourceManager {
         let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
         self.console.borrow_mut().output_to_console(&format!("Bulk load took {}ms to load", milli_time));
     }
-}
\ No newline at end of file
+
+    //What the dedicated server needs, collision to move players around and skeletons to pose their hitboxes
+    pub fn bulk_load_headless(&mut self) {
+
+        let start = std::time::Instant::now();
+
+        for (path, collide, has_animation) in ASSETS {
+            if collide {
+                self.load_collision(path);
+            }
+            if has_animation {
+                self.load_skeleton(path);
+            }
+        }
+
+        let milli_time = start.elapsed().as_micros() as f32 / 1000.0;
+        self.console.borrow_mut().output_to_console(&format!("Bulk headless load took {}ms to load", milli_time));
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::TICK_RATE_SECONDS;
+
+//Where the game is, the server decides for everyone connected to it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum States {
+    MainMenu,
+    //Waiting on the handshake
+    Connecting,
+    //Connected, waiting for the server to say what state the match is in
+    Loading,
+    //Free play until the first round starts
+    Warmup,
+    InRound,
+    //Scores are in, everyone can still run around but not shoot
+    RoundEnd,
+    MatchEnd,
+    Paused,
+}
+
+impl States {
+
+    pub const ALL: [States; 8] = [States::MainMenu, States::Connecting, States::Loading, States::Warmup, States::InRound, States::RoundEnd, States::MatchEnd, States::Paused];
+
+    pub fn get_name(&self) -> &'static str {
+
+        match self {
+            States::MainMenu => "main_menu",
+            States::Connecting => "connecting",
+            States::Loading => "loading",
+            States::Warmup => "warmup",
+            States::InRound => "in_round",
+            States::RoundEnd => "round_end",
+            States::MatchEnd => "match_end",
+            States::Paused => "paused",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<States> {
+
+        States::ALL.into_iter().find(|state| state.get_name() == name)
+    }
+
+    //Players move, physics runs and the server takes commands
+    pub fn ticks_world(&self) -> bool {
+
+        matches!(self, States::Warmup | States::InRound | States::RoundEnd)
+    }
+
+    //Anything other than menus and loading screens
+    pub fn renders_world(&self) -> bool {
+
+        matches!(self, States::Warmup | States::InRound | States::RoundEnd | States::MatchEnd | States::Paused)
+    }
+
+    //Looking around still works when this is false
+    pub fn accepts_movement(&self) -> bool {
+
+        matches!(self, States::Warmup | States::InRound | States::RoundEnd)
+    }
+
+    pub fn accepts_fire(&self) -> bool {
+
+        matches!(self, States::Warmup | States::InRound)
+    }
+
+    //Part of a match, so the server tells clients about it
+    pub fn is_match_state(&self) -> bool {
+
+        matches!(self, States::Warmup | States::InRound | States::RoundEnd | States::MatchEnd | States::Paused)
+    }
+}
+
+//How long the timed states last, the server moves on by itself when they run out
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StateSettings {
+    pub warmup_time: f32,
+    pub round_time: f32,
+    pub round_end_time: f32,
+    pub match_end_time: f32,
+    pub rounds_per_match: u32,
+}
+
+impl Default for StateSettings {
+
+    fn default() -> Self {
+
+        Self { warmup_time: 30.0, round_time: 180.0, round_end_time: 5.0, match_end_time: 15.0, rounds_per_match: 10 }
+    }
+}
+
+impl StateSettings {
+
+    //None for states that last until something else moves them on
+    pub fn get_duration_ticks(&self, state: States) -> Option<u32> {
+
+        let seconds = match state {
+            States::Warmup => self.warmup_time,
+            States::InRound => self.round_time,
+            States::RoundEnd => self.round_end_time,
+            States::MatchEnd => self.match_end_time,
+            _ => return None,
+        };
+
+        Some((seconds / TICK_RATE_SECONDS).round() as u32)
+    }
+
+    //Where a timed state goes when it runs out, round is the one that just finished
+    pub fn get_next_state(&self, state: States, round: u32) -> Option<States> {
+
+        match state {
+            States::Warmup => Some(States::InRound),
+            States::InRound => Some(States::RoundEnd),
+            States::RoundEnd if round >= self.rounds_per_match => Some(States::MatchEnd),
+            States::RoundEnd => Some(States::InRound),
+            States::MatchEnd => Some(States::Warmup),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+
+        for state in States::ALL {
+            assert_eq!(States::from_name(state.get_name()), Some(state));
+        }
+        assert_eq!(States::from_name("lobby"), None);
+    }
+
+    #[test]
+    fn only_live_states_take_input() {
+
+        for state in States::ALL {
+            //Nothing simulates without rendering and nothing fires without moving
+            assert!(!state.ticks_world() || state.renders_world());
+            assert!(!state.accepts_fire() || state.accepts_movement());
+        }
+        assert!(!States::Paused.ticks_world());
+        assert!(States::Paused.renders_world());
+        assert!(States::RoundEnd.accepts_movement() && !States::RoundEnd.accepts_fire());
+        assert!(!States::MainMenu.renders_world());
+    }
+
+    #[test]
+    fn timed_states_run_through_a_match() {
+
+        let settings = StateSettings { rounds_per_match: 2, ..StateSettings::default() };
+        let mut state = States::Warmup;
+        let mut round = 0;
+        let mut visited = vec![state];
+
+        while visited.len() < 7 {
+            if state == States::InRound {
+                round += 1;
+            }
+            state = settings.get_next_state(state, round).unwrap();
+            visited.push(state);
+        }
+
+        assert_eq!(visited, vec![States::Warmup, States::InRound, States::RoundEnd, States::InRound, States::RoundEnd, States::MatchEnd, States::Warmup]);
+        assert_eq!(settings.get_next_state(States::Paused, 0), None);
+        assert_eq!(settings.get_duration_ticks(States::RoundEnd), Some((5.0 / TICK_RATE_SECONDS).round() as u32));
+        assert_eq!(settings.get_duration_ticks(States::Loading), None);
+    }
+}
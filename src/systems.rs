@@ -0,0 +1,334 @@
+use crate::character_controller::GRAVITY;
+use crate::collision;
+use crate::entity::*;
+use crate::game_state::TICK_RATE_SECONDS;
+use crate::player;
+use crate::render_commands::*;
+use crate::resource_manager;
+use crate::weapon;
+
+//How far dynamic colliders drop each tick until they land on the world
+const FALL_PER_TICK: f32 = 2.0;
+
+//Run once per simulated tick, in this order, before rendering
+pub fn spin(entities: &mut EntityStore, tick: u32) {
+
+    let spinners: Vec<(EntityId, Spinner)> = entities.iter::<Spinner>().map(|(id, spinner)| (id, *spinner)).collect();
+
+    for (id, spinner) in spinners {
+        let Some(transform) = entities.get_mut::<Transform>(id) else {
+            continue;
+        };
+        let rotation = spinner.rotation_rate * tick as f32;
+        transform.rotation = glam::f32::Quat::from_euler(glam::EulerRot::XYZ, rotation.x, rotation.y, rotation.z);
+        transform.position = spinner.origin + glam::f32::Vec3::X * (tick as f32 * spinner.sway_rate).sin() * spinner.sway_amplitude;
+    }
+}
+
+//Drops dynamic colliders onto the world, whichever triangle soup they hit first stops them
+pub fn fall(entities: &mut EntityStore, world: &[&collision::TriangleSoup]) {
+
+    let colliders: Vec<(EntityId, Collider)> = entities.iter::<Collider>().filter(|(_, collider)| collider.dynamic).map(|(id, collider)| (id, *collider)).collect();
+    let velocity = glam::f32::Vec3::NEG_Y * FALL_PER_TICK;
+
+    for (id, collider) in colliders {
+        let Some(transform) = entities.get_mut::<Transform>(id) else {
+            continue;
+        };
+
+        let world_shape = collider.get_world_shape(transform);
+        let first_hit = world.iter().map(|triangle_soup| match world_shape {
+            ColliderShape::Sphere(sphere) => sphere.vs_while_moving_triangle_soup(&velocity, triangle_soup),
+            ColliderShape::Capsule(capsule) => capsule.vs_while_moving_triangle_soup(&velocity, triangle_soup),
+        }).filter(|t| t.collided).min_by(|a, b| a.penetration_or_time.total_cmp(&b.penetration_or_time));
+
+        match first_hit {
+            Some(t) => transform.position += velocity * t.penetration_or_time + t.normal * f32::EPSILON,
+            None => transform.position += velocity,
+        }
+    }
+}
+
+//Sweeps projectiles along their path so nothing thin gets skipped, bodies are every player's capsule in the world
+//Hitting a player or running out of bounces or time sets them off, returns where
+pub fn fly_projectiles(entities: &mut EntityStore, world: &[&collision::TriangleSoup], bodies: &[(u32, collision::Capsule)]) -> Vec<weapon::Explosion> {
+
+    let projectiles: Vec<EntityId> = entities.iter::<Projectile>().map(|(id, _)| id).collect();
+    let mut explosions = Vec::new();
+
+    for id in projectiles {
+        let (Some(mut projectile), Some(position)) = (entities.get::<Projectile>(id).copied(), entities.get::<Transform>(id).map(|transform| transform.position)) else {
+            continue;
+        };
+        projectile.velocity += glam::f32::Vec3::NEG_Y * GRAVITY * projectile.definition.gravity_scale * TICK_RATE_SECONDS;
+        let movement = projectile.velocity * TICK_RATE_SECONDS;
+        let sphere = collision::Sphere::new(position, projectile.definition.radius);
+
+        let world_hit = world.iter()
+            .map(|triangle_soup| sphere.vs_while_moving_triangle_soup(&movement, triangle_soup))
+            .filter(|t| t.collided)
+            .min_by(|a, b| a.penetration_or_time.total_cmp(&b.penetration_or_time));
+
+        //Growing the bodies by the projectile's radius lets a ray stand in for the sphere
+        let ray = collision::Ray::new(position, movement);
+        let body_hit = bodies.iter()
+            .filter(|(player_id, _)| *player_id != projectile.owner)
+            .map(|(_, body)| ray.vs_capsule(&collision::Capsule::new(body.get_base(), body.get_tip(), body.get_radius() + projectile.definition.radius)))
+            .filter(|t| t.collided && t.penetration_or_time <= 1.0)
+            .map(|t| t.penetration_or_time)
+            .min_by(|a, b| a.total_cmp(b));
+
+        let mut new_position = position + movement;
+        let mut exploded = false;
+        match (world_hit, body_hit) {
+            (Some(world_hit), body_time) if body_time.is_none_or(|body_time| world_hit.penetration_or_time < body_time) => {
+                let normal = world_hit.normal.normalize_or_zero();
+                new_position = position + movement * world_hit.penetration_or_time + normal * f32::EPSILON;
+                if projectile.bounces_left > 0 {
+                    projectile.bounces_left -= 1;
+                    let into_surface = projectile.velocity.dot(normal).min(0.0);
+                    projectile.velocity -= normal * into_surface * (1.0 + projectile.definition.restitution);
+                }
+                else {
+                    exploded = true;
+                }
+            }
+            (_, Some(body_time)) => {
+                new_position = position + movement * body_time;
+                exploded = true;
+            }
+            _ => {}
+        }
+
+        projectile.ticks_left = projectile.ticks_left.saturating_sub(1);
+        if exploded || projectile.ticks_left == 0 {
+            entities.despawn(id);
+            explosions.push(weapon::Explosion { owner: projectile.owner, weapon: projectile.weapon, position: new_position });
+            continue;
+        }
+
+        if let Some(transform) = entities.get_mut::<Transform>(id) {
+            transform.position = new_position;
+        }
+        entities.insert(id, projectile);
+    }
+
+    explosions
+}
+
+//Moves player controlled entities to wherever their player is, and shapes their collider like the player's body
+pub fn follow_players<'a>(entities: &mut EntityStore, get_player: impl Fn(PlayerControl) -> Option<&'a player::Player>) {
+
+    let controls: Vec<(EntityId, PlayerControl)> = entities.iter::<PlayerControl>().map(|(id, control)| (id, *control)).collect();
+
+    for (id, control) in controls {
+        let Some(player) = get_player(control) else {
+            continue;
+        };
+        if let Some(transform) = entities.get_mut::<Transform>(id) {
+            transform.position = *player.get_position();
+        }
+        if let Some(collider) = entities.get_mut::<Collider>(id) {
+            collider.shape = ColliderShape::Capsule(*player.get_controller().get_capsule());
+        }
+    }
+}
+
+pub fn animate(entities: &EntityStore, resource_manager: &mut resource_manager::ResourceManager) {
+
+    for (_, animation) in entities.iter::<SkeletonAnimation>() {
+        if let Some(skeleton_model) = resource_manager.get_mut_skeleton_model(&animation.model_name) {
+            skeleton_model.update_skeleton(TICK_RATE_SECONDS * animation.speed);
+        }
+    }
+}
+
+pub fn render(entities: &EntityStore, render_commands: &mut Vec<RenderCommands>) {
+
+    for (id, renderable) in entities.iter::<Renderable>() {
+        let Some(transform) = entities.get::<Transform>(id) else {
+            continue;
+        };
+
+        match renderable {
+            Renderable::Model { model_name, texture_name } => render_commands.push(RenderCommands::Model(ModelRenderCommand::new(transform.get_matrix(), model_name, texture_name))),
+            Renderable::SkeletonModel { model_name, texture_name } => render_commands.push(RenderCommands::SkeletonModel(SkeletonModelRenderCommand::new(transform.get_matrix(), model_name, texture_name))),
+            Renderable::Collider => match entities.get::<Collider>(id).map(|collider| collider.get_world_shape(transform)) {
+                Some(ColliderShape::Sphere(sphere)) => sphere.render(render_commands),
+                Some(ColliderShape::Capsule(capsule)) => capsule.render(render_commands),
+                None => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floor(height: f32) -> collision::TriangleSoup {
+
+        collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(-10.0, height, -10.0), glam::f32::Vec3::new(-10.0, height, 10.0), glam::f32::Vec3::new(10.0, height, -10.0)),
+            collision::Triangle::new(glam::f32::Vec3::new(10.0, height, -10.0), glam::f32::Vec3::new(-10.0, height, 10.0), glam::f32::Vec3::new(10.0, height, 10.0)),
+        ])
+    }
+
+    fn spawn_sphere(entities: &mut EntityStore, position: glam::f32::Vec3, dynamic: bool) -> EntityId {
+
+        let id = entities.spawn();
+        entities.insert(id, Transform::from_position(position));
+        entities.insert(id, Collider::new(ColliderShape::Sphere(collision::Sphere::new(glam::f32::Vec3::ZERO, 1.0)), dynamic));
+
+        id
+    }
+
+    #[test]
+    fn dynamic_colliders_land_on_the_world() {
+
+        let mut entities = EntityStore::new();
+        let dynamic = spawn_sphere(&mut entities, glam::f32::Vec3::new(0.0, 10.0, 0.0), true);
+        let fixed = spawn_sphere(&mut entities, glam::f32::Vec3::new(3.0, 10.0, 0.0), false);
+        let floor = floor(0.0);
+
+        for _ in 0..20 {
+            fall(&mut entities, &[&floor]);
+        }
+
+        //Resting with the bottom of the sphere on the floor
+        let landed = entities.get::<Transform>(dynamic).unwrap().position;
+        assert!((landed.y - 1.0).abs() < 0.01, "{:?}", landed);
+        assert_eq!(entities.get::<Transform>(fixed).unwrap().position.y, 10.0);
+
+        //Nothing to land on
+        fall(&mut entities, &[]);
+        assert!((entities.get::<Transform>(dynamic).unwrap().position.y - (landed.y - FALL_PER_TICK)).abs() < 0.001);
+    }
+
+    fn launch(entities: &mut EntityStore, position: glam::f32::Vec3, direction: glam::f32::Vec3, definition: weapon::ProjectileDefinition) -> EntityId {
+
+        let id = entities.spawn();
+        entities.insert(id, Transform::from_position(position));
+        entities.insert(id, Projectile::new(1, 3, definition, direction));
+
+        id
+    }
+
+    fn rocket(speed: f32) -> weapon::ProjectileDefinition {
+
+        weapon::ProjectileDefinition { speed, gravity_scale: 0.0, lifetime: 5.0, max_bounces: 0, restitution: 0.0, radius: 0.2, splash_radius: 4.0 }
+    }
+
+    #[test]
+    fn fast_projectiles_cant_skip_thin_walls() {
+
+        //Two triangles at z = 5 facing back at the shooter
+        let wall = collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(-10.0, -10.0, 5.0), glam::f32::Vec3::new(10.0, -10.0, 5.0), glam::f32::Vec3::new(-10.0, 10.0, 5.0)),
+            collision::Triangle::new(glam::f32::Vec3::new(10.0, -10.0, 5.0), glam::f32::Vec3::new(10.0, 10.0, 5.0), glam::f32::Vec3::new(-10.0, 10.0, 5.0)),
+        ]);
+        let mut entities = EntityStore::new();
+        //Far more than the whole gap in one tick
+        let id = launch(&mut entities, glam::f32::Vec3::ZERO, glam::f32::Vec3::Z, rocket(50.0 / TICK_RATE_SECONDS));
+
+        let explosions = fly_projectiles(&mut entities, &[&wall], &[]);
+        assert_eq!(explosions.len(), 1);
+        assert!((explosions[0].position.z - 4.8).abs() < 0.01, "{:?}", explosions[0].position);
+        assert_eq!((explosions[0].owner, explosions[0].weapon), (1, 3));
+        assert!(!entities.is_alive(id));
+    }
+
+    #[test]
+    fn grenades_bounce_until_they_run_out() {
+
+        let floor = floor(0.0);
+        let definition = weapon::ProjectileDefinition { speed: 10.0, gravity_scale: 0.0, lifetime: 60.0, max_bounces: 2, restitution: 0.5, radius: 0.1, splash_radius: 5.0 };
+        let mut entities = EntityStore::new();
+        let direction = glam::f32::Vec3::new(1.0, -1.0, 0.0).normalize();
+        let id = launch(&mut entities, glam::f32::Vec3::new(0.0, 0.5, 0.0), direction, definition);
+
+        let mut explosions = Vec::new();
+        let mut bounces = Vec::new();
+        for _ in 0..200 {
+            let velocity = entities.get::<Projectile>(id).map(|projectile| projectile.velocity);
+            explosions.extend(fly_projectiles(&mut entities, &[&floor], &[]));
+            if let (Some(before), Some(after)) = (velocity, entities.get::<Projectile>(id).map(|projectile| projectile.velocity)) {
+                if before != after {
+                    bounces.push(after);
+                }
+            }
+            //Back down at the floor after each bounce
+            if let Some(projectile) = entities.get_mut::<Projectile>(id) {
+                projectile.velocity.y = -projectile.velocity.y.abs();
+            }
+        }
+
+        //Along the floor is kept, into it comes back out at half the speed
+        assert_eq!(bounces.len(), 2);
+        let along = direction.x * 10.0;
+        assert!(bounces[0].abs_diff_eq(glam::f32::Vec3::new(along, along * 0.5, 0.0), 0.001), "{:?}", bounces[0]);
+        assert!(bounces[1].abs_diff_eq(glam::f32::Vec3::new(along, along * 0.25, 0.0), 0.001), "{:?}", bounces[1]);
+        assert_eq!(explosions.len(), 1);
+        assert!(explosions[0].position.y > 0.0 && explosions[0].position.y < 0.11);
+    }
+
+    #[test]
+    fn projectiles_go_off_on_bodies_and_when_they_run_out_of_time() {
+
+        let body = |x: f32| collision::Capsule::new(glam::f32::Vec3::new(x, -1.0, 0.0), glam::f32::Vec3::new(x, 1.0, 0.0), 0.5);
+        let mut entities = EntityStore::new();
+        launch(&mut entities, glam::f32::Vec3::ZERO, glam::f32::Vec3::X, rocket(1.0 / TICK_RATE_SECONDS));
+
+        //The owner is standing on it, the other player is in the way
+        let bodies = [(1, body(0.0)), (2, body(1.5))];
+        let mut explosions = Vec::new();
+        for _ in 0..3 {
+            explosions.extend(fly_projectiles(&mut entities, &[], &bodies));
+        }
+        assert_eq!(explosions.len(), 1);
+        assert!((explosions[0].position.x - 0.8).abs() < 0.01, "{:?}", explosions[0].position);
+
+        let mut definition = rocket(1.0);
+        definition.lifetime = TICK_RATE_SECONDS * 3.0;
+        launch(&mut entities, glam::f32::Vec3::ZERO, glam::f32::Vec3::X, definition);
+        assert!(fly_projectiles(&mut entities, &[], &[]).is_empty());
+        assert!(fly_projectiles(&mut entities, &[], &[]).is_empty());
+        assert_eq!(fly_projectiles(&mut entities, &[], &[]).len(), 1);
+        assert_eq!(entities.iter::<Projectile>().count(), 0);
+    }
+
+    #[test]
+    fn spinners_follow_the_tick() {
+
+        let mut entities = EntityStore::new();
+        let id = entities.spawn();
+        entities.insert(id, Transform::from_position(glam::f32::Vec3::ZERO));
+        entities.insert(id, Spinner { origin: glam::f32::Vec3::new(0.0, 7.0, 0.0), rotation_rate: glam::f32::Vec3::new(0.1, 0.1, 0.0), sway_amplitude: 2.0, sway_rate: 0.05 });
+
+        spin(&mut entities, 30);
+        let transform = entities.get::<Transform>(id).unwrap();
+        assert!(transform.position.abs_diff_eq(glam::f32::Vec3::new(1.5_f32.sin() * 2.0, 7.0, 0.0), 0.0001));
+        assert!(transform.rotation.abs_diff_eq(glam::f32::Quat::from_euler(glam::EulerRot::XYZ, 3.0, 3.0, 0.0), 0.0001));
+    }
+
+    #[test]
+    fn renders_everything_with_a_transform() {
+
+        let mut entities = EntityStore::new();
+        let cube = entities.spawn();
+        entities.insert(cube, Transform::from_position(glam::f32::Vec3::Y));
+        entities.insert(cube, Renderable::model("cube", "tree"));
+        let sphere = spawn_sphere(&mut entities, glam::f32::Vec3::X, false);
+        entities.insert(sphere, Renderable::Collider);
+        //Can't be drawn anywhere
+        let nowhere = entities.spawn();
+        entities.insert(nowhere, Renderable::model("cube", "tree"));
+
+        let mut render_commands = Vec::new();
+        render(&entities, &mut render_commands);
+
+        assert_eq!(render_commands.len(), 2);
+        assert!(matches!(&render_commands[0], RenderCommands::Model(command) if command.model_name == "cube"));
+        assert!(matches!(&render_commands[1], RenderCommands::Model(command) if command.model_name == "sphere"));
+    }
+}
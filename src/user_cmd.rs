@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::*;
+use crate::weapon;
+
+//Bits in UserCmd::buttons
+pub const BUTTON_FIRE: u32 = 1 << 0;
+//Held, so holding it jumps again on landing
+pub const BUTTON_JUMP: u32 = 1 << 1;
+//Held, crouches while down and slides if already moving fast
+pub const BUTTON_CROUCH: u32 = 1 << 2;
+pub const BUTTON_RELOAD: u32 = 1 << 3;
+
+//Every input packet carries this many of the newest commands so one lost packet loses nothing
+pub const USER_CMD_REDUNDANCY: usize = 3;
+//Commands the server waits for before it starts feeding a player, soaks up uneven packet arrival
+pub const JITTER_BUFFER_TICKS: usize = 2;
+//Past this the client is too far ahead of the server, the oldest are dropped to get back to JITTER_BUFFER_TICKS
+const MAX_BUFFERED_COMMANDS: usize = 8;
+
+//Everything the player did on one client tick, the only thing the server simulates a player from
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserCmd {
+    pub tick: u32,
+    pub movement: glam::f32::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub buttons: u32,
+    //Index into weapon::WEAPONS of the weapon the player wants out, None keeps the current one
+    pub weapon: Option<u8>,
+    //Server tick the client was showing other players at, for lag compensation
+    pub view_tick: f32,
+}
+
+impl UserCmd {
+
+    pub fn new(tick: u32, movement: glam::f32::Vec3, yaw: f32, pitch: f32, buttons: u32, view_tick: f32) -> Self {
+
+        Self { tick, movement, yaw, pitch, buttons, weapon: None, view_tick }
+    }
+
+    //View comes from the player since mouse motion has already been applied to it, as does the weapon the wheel steps from
+    pub fn from_inputs(tick: u32, inputs: &Inputs, yaw: f32, pitch: f32, current_weapon: usize, view_tick: f32) -> Self {
+
+        let mut movement = glam::f32::Vec3::ZERO;
+        if inputs.check_key_down(RIGHT) {
+            movement += glam::f32::Vec3::X;
+        }
+        if inputs.check_key_down(LEFT) {
+            movement += glam::f32::Vec3::NEG_X;
+        }
+        if inputs.check_key_down(FORWARD) {
+            movement += glam::f32::Vec3::Z;
+        }
+        if inputs.check_key_down(BACKWARD) {
+            movement += glam::f32::Vec3::NEG_Z;
+        }
+
+        let mut buttons = 0;
+        if inputs.check_mouse_down(FIRE) {
+            buttons |= BUTTON_FIRE;
+        }
+        if inputs.check_key_down(JUMP) {
+            buttons |= BUTTON_JUMP;
+        }
+        if inputs.check_key_down(CROUCH) {
+            buttons |= BUTTON_CROUCH;
+        }
+        if inputs.check_key_down(RELOAD) {
+            buttons |= BUTTON_RELOAD;
+        }
+
+        //Number keys pick one, scrolling up goes back through the list and down goes on
+        let weapon = match WEAPON_SLOTS.iter().position(|key| inputs.check_key_down(*key)) {
+            Some(slot) => Some(slot as u8),
+            None if inputs.get_mouse_wheel_steps() != 0 => Some((current_weapon as i32 - inputs.get_mouse_wheel_steps()).rem_euclid(weapon::WEAPONS.len() as i32) as u8),
+            None => None,
+        };
+
+        Self { tick, movement, yaw, pitch, buttons, weapon, view_tick }
+    }
+
+    pub fn is_pressed(&self, button: u32) -> bool {
+
+        self.buttons & button != 0
+    }
+}
+
+//Server side, one per player, hands out exactly one command each tick
+pub struct UserCmdBuffer {
+    commands: VecDeque<UserCmd>,
+    last_command: Option<UserCmd>,
+    started: bool,
+}
+
+impl Default for UserCmdBuffer {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl UserCmdBuffer {
+
+    pub fn new() -> Self {
+
+        Self { commands: VecDeque::new(), last_command: None, started: false }
+    }
+
+    //Commands come in three at a time and out of order, anything already used or buffered is ignored
+    pub fn insert(&mut self, command: UserCmd) {
+
+        if self.last_command.is_some_and(|last_command| command.tick <= last_command.tick) {
+            return;
+        }
+
+        let index = match self.commands.binary_search_by_key(&command.tick, |buffered| buffered.tick) {
+            Ok(_) => return,
+            Err(index) => index,
+        };
+        self.commands.insert(index, command);
+
+        if self.commands.len() > MAX_BUFFERED_COMMANDS {
+            self.commands.drain(..self.commands.len() - JITTER_BUFFER_TICKS);
+        }
+    }
+
+    //Call once per server tick
+    pub fn take_next(&mut self) -> Option<UserCmd> {
+
+        if !self.started {
+            if self.commands.len() < JITTER_BUFFER_TICKS {
+                return None;
+            }
+            self.started = true;
+        }
+
+        let command = match self.commands.pop_front() {
+            Some(command) => command,
+            //Ran dry, carry on doing the same thing and count it as the next tick so the real one is dropped when it turns up
+            None => {
+                let mut repeated = self.last_command?;
+                repeated.tick = repeated.tick.wrapping_add(1);
+                repeated.buttons &= !BUTTON_FIRE;
+                repeated
+            }
+        };
+        self.last_command = Some(command);
+
+        Some(command)
+    }
+
+    //Tick of the last command handed out, goes back to the client so it knows what to replay
+    pub fn get_last_tick(&self) -> Option<u32> {
+
+        self.last_command.map(|last_command| last_command.tick)
+    }
+
+    pub fn get_buffered_count(&self) -> usize {
+
+        self.commands.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(tick: u32) -> UserCmd {
+
+        UserCmd::new(tick, glam::f32::Vec3::Z, tick as f32, 0.0, 0, 0.0)
+    }
+
+    #[test]
+    fn waits_for_the_buffer_to_fill_then_feeds_one_per_tick() {
+
+        let mut buffer = UserCmdBuffer::new();
+
+        buffer.insert(command(10));
+        assert_eq!(buffer.take_next(), None);
+
+        buffer.insert(command(11));
+        assert_eq!(buffer.take_next().unwrap().tick, 10);
+        assert_eq!(buffer.take_next().unwrap().tick, 11);
+        assert_eq!(buffer.get_last_tick(), Some(11));
+    }
+
+    #[test]
+    fn redundant_and_out_of_order_commands_are_used_once() {
+
+        let mut buffer = UserCmdBuffer::new();
+
+        //Each packet has the newest three, the second packet arrived first
+        for tick in [3, 4, 5, 1, 2, 3] {
+            buffer.insert(command(tick));
+        }
+
+        let ticks: Vec<u32> = (0..5).map(|_| buffer.take_next().unwrap().tick).collect();
+        assert_eq!(ticks, vec![1, 2, 3, 4, 5]);
+
+        buffer.insert(command(4));
+        assert_eq!(buffer.get_buffered_count(), 0);
+    }
+
+    #[test]
+    fn running_dry_repeats_the_last_command_without_firing() {
+
+        let mut buffer = UserCmdBuffer::new();
+
+        let mut firing = command(1);
+        firing.buttons = BUTTON_FIRE;
+        buffer.insert(firing);
+        buffer.insert(command(2));
+        buffer.take_next();
+        let last = buffer.take_next().unwrap();
+
+        let repeated = buffer.take_next().unwrap();
+        assert_eq!(repeated.tick, 3);
+        assert_eq!(repeated.movement, last.movement);
+        assert!(!repeated.is_pressed(BUTTON_FIRE));
+
+        //The real tick 3 is too late now
+        buffer.insert(command(3));
+        buffer.insert(command(4));
+        assert_eq!(buffer.take_next().unwrap().tick, 4);
+    }
+
+    #[test]
+    fn a_client_too_far_ahead_is_caught_up() {
+
+        let mut buffer = UserCmdBuffer::new();
+
+        for tick in 0..20 {
+            buffer.insert(command(tick));
+        }
+
+        assert!(buffer.get_buffered_count() <= MAX_BUFFERED_COMMANDS);
+        assert_eq!(buffer.take_next().unwrap().tick, 20 - buffer.get_buffered_count() as u32 - 1);
+    }
+
+    #[test]
+    fn slow_touchpad_scrolling_adds_up_to_a_switch() {
+
+        let mut inputs = Inputs::new();
+        let mut weapons = Vec::new();
+
+        //Less than a step each tick, one switch once it's added up to a whole step
+        for tick in 0..5 {
+            inputs.mouse_wheel_input(0.4);
+            weapons.push(UserCmd::from_inputs(tick, &inputs, 0.0, 0.0, 0, 0.0).weapon);
+            inputs.end_tick_clean();
+        }
+
+        assert_eq!(weapons, vec![None, None, Some(weapon::WEAPONS.len() as u8 - 1), None, Some(weapon::WEAPONS.len() as u8 - 1)]);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::game_state::TICK_RATE_SECONDS;
+use crate::character_controller::MAX_FALL_SPEED;
+use crate::collision;
+use crate::movement::MovementSettings;
+use crate::player::{Player, MAX_PITCH};
+use crate::user_cmd::UserCmd;
+use crate::weapon;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ViolationKind {
+    //NaN or infinite numbers, or movement outside -1 to 1
+    InvalidInput,
+    Pitch,
+    TurnRate,
+    Speed,
+    Acceleration,
+    Teleport,
+    //The shot left from the other side of the world to where the eye was last tick
+    ShotThroughWall,
+}
+
+impl ViolationKind {
+
+    pub const ALL: [ViolationKind; 7] = [ViolationKind::InvalidInput, ViolationKind::Pitch, ViolationKind::TurnRate, ViolationKind::Speed, ViolationKind::Acceleration, ViolationKind::Teleport, ViolationKind::ShotThroughWall];
+
+    //Name used by the validation console command
+    pub fn get_name(&self) -> &'static str {
+
+        match self {
+            ViolationKind::InvalidInput => "invalid_input",
+            ViolationKind::Pitch => "pitch",
+            ViolationKind::TurnRate => "turn_rate",
+            ViolationKind::Speed => "speed",
+            ViolationKind::Acceleration => "acceleration",
+            ViolationKind::Teleport => "teleport",
+            ViolationKind::ShotThroughWall => "shot_through_wall",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+
+        Self::ALL.into_iter().find(|kind| kind.get_name() == name)
+    }
+
+    fn get_index(&self) -> usize {
+
+        Self::ALL.iter().position(|kind| kind == self).unwrap()
+    }
+}
+
+//value is what the player did, limit is what was allowed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub value: f32,
+    pub limit: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ValidationSettings {
+    //Units per second
+    pub max_speed: f32,
+    //Units per second squared
+    pub max_acceleration: f32,
+    //Furthest a player can move in one tick, anything further is a teleport
+    pub max_teleport_distance: f32,
+    //Radians per second
+    pub max_turn_rate: f32,
+    //Violations of each kind before the player is kicked, None never kicks
+    pub kick_thresholds: [Option<u32>; ViolationKind::ALL.len()],
+}
+
+impl Default for ValidationSettings {
+
+    fn default() -> Self {
+
+        //Bunny-hopping a few times past running speed while falling as fast as possible is the fastest legal movement, turning round in a tick is the hardest acceleration
+        let max_horizontal_speed = MovementSettings::default().max_speed * 3.0;
+        let max_speed = (max_horizontal_speed * max_horizontal_speed + MAX_FALL_SPEED * MAX_FALL_SPEED).sqrt() * 1.1;
+
+        Self {
+            max_speed,
+            max_acceleration: max_speed * 2.0 / TICK_RATE_SECONDS,
+            max_teleport_distance: max_speed * TICK_RATE_SECONDS * 2.0,
+            //A very fast flick is around 3000 degrees a second
+            max_turn_rate: 5000.0_f32.to_radians(),
+            kick_thresholds: [Some(1), Some(5), Some(30), Some(20), Some(20), Some(5), Some(5)],
+        }
+    }
+}
+
+impl ValidationSettings {
+
+    pub fn get_kick_threshold(&self, kind: ViolationKind) -> Option<u32> {
+
+        self.kick_thresholds[kind.get_index()]
+    }
+
+    pub fn set_kick_threshold(&mut self, kind: ViolationKind, threshold: Option<u32>) {
+
+        self.kick_thresholds[kind.get_index()] = threshold;
+    }
+}
+
+//Server side, one per remote player, checks each command and what it did before it's trusted
+pub struct PlayerValidator {
+    last_command: Option<UserCmd>,
+    last_velocity: glam::f32::Vec3,
+    //Where the eye was after the last move that passed, None until there is one
+    last_eye_position: Option<glam::f32::Vec3>,
+    counts: [u32; ViolationKind::ALL.len()],
+}
+
+impl Default for PlayerValidator {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl PlayerValidator {
+
+    pub fn new() -> Self {
+
+        Self { last_command: None, last_velocity: glam::f32::Vec3::ZERO, last_eye_position: None, counts: [0; ViolationKind::ALL.len()] }
+    }
+
+    //Call before simulating, anything impossible is fixed up in place so the command can still be used
+    pub fn check_command(&mut self, settings: &ValidationSettings, command: &mut UserCmd) -> Vec<Violation> {
+
+        let mut violations = Vec::new();
+
+        if !command.movement.is_finite() || !command.yaw.is_finite() || !command.pitch.is_finite() || !command.view_tick.is_finite() {
+            violations.push(Violation { kind: ViolationKind::InvalidInput, value: f32::NAN, limit: 0.0 });
+            let (yaw, pitch) = self.last_command.map_or((0.0, 0.0), |last_command| (last_command.yaw, last_command.pitch));
+            *command = UserCmd::new(command.tick, glam::f32::Vec3::ZERO, yaw, pitch, 0, 0.0);
+        }
+
+        if let Some(weapon) = command.weapon.filter(|weapon| *weapon as usize >= weapon::WEAPONS.len()) {
+            violations.push(Violation { kind: ViolationKind::InvalidInput, value: weapon as f32, limit: (weapon::WEAPONS.len() - 1) as f32 });
+            command.weapon = None;
+        }
+
+        let largest_movement = command.movement.abs().max_element();
+        if largest_movement > 1.0 {
+            violations.push(Violation { kind: ViolationKind::InvalidInput, value: largest_movement, limit: 1.0 });
+            command.movement = command.movement.clamp(glam::f32::Vec3::NEG_ONE, glam::f32::Vec3::ONE);
+        }
+
+        //The client clamps too so anything past it was never a real mouse
+        if command.pitch.abs() > MAX_PITCH {
+            violations.push(Violation { kind: ViolationKind::Pitch, value: command.pitch.abs().to_degrees(), limit: MAX_PITCH.to_degrees() });
+            command.pitch = command.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        //The server takes one command a tick, the tick number is the client's word and can't stretch the time a turn took
+        if let Some(last_command) = self.last_command {
+            let turn_rate = get_turn_angle(&last_command, command) / TICK_RATE_SECONDS;
+            if turn_rate > settings.max_turn_rate {
+                violations.push(Violation { kind: ViolationKind::TurnRate, value: turn_rate.to_degrees(), limit: settings.max_turn_rate.to_degrees() });
+            }
+        }
+        self.last_command = Some(*command);
+
+        violations
+    }
+
+    //Call with the shot a command fired before check_movement, a shot with violations shouldn't count
+    //The eye can't have gone through the world to get from where it was last tick to where the shot left from
+    pub fn check_shot(&self, shot: &weapon::Shot, world: &[&collision::TriangleSoup]) -> Vec<Violation> {
+
+        let Some(last_eye_position) = self.last_eye_position else {
+            return Vec::new();
+        };
+
+        let ray = collision::Ray::new(last_eye_position, shot.origin - last_eye_position);
+        let blocked = world.iter().map(|triangle_soup| triangle_soup.vs_ray(&ray)).find(|packet| packet.collided && packet.penetration_or_time <= 1.0);
+
+        match blocked {
+            Some(packet) => vec![Violation { kind: ViolationKind::ShotThroughWall, value: packet.position.distance(shot.origin), limit: 0.0 }],
+            None => Vec::new(),
+        }
+    }
+
+    //Call after simulating, a player with violations should be put back where it was
+    pub fn check_movement(&mut self, settings: &ValidationSettings, previous_position: glam::f32::Vec3, player: &Player) -> Vec<Violation> {
+
+        let mut violations = Vec::new();
+
+        let distance = previous_position.distance(*player.get_position());
+        if distance > settings.max_teleport_distance {
+            violations.push(Violation { kind: ViolationKind::Teleport, value: distance, limit: settings.max_teleport_distance });
+        }
+
+        let velocity = *player.get_velocity();
+        let speed = velocity.length();
+        if speed > settings.max_speed {
+            violations.push(Violation { kind: ViolationKind::Speed, value: speed, limit: settings.max_speed });
+        }
+
+        let acceleration = (velocity - self.last_velocity).length() / TICK_RATE_SECONDS;
+        if acceleration > settings.max_acceleration {
+            violations.push(Violation { kind: ViolationKind::Acceleration, value: acceleration, limit: settings.max_acceleration });
+        }
+
+        //A rejected move leaves the player standing still
+        self.last_velocity = if violations.is_empty() { velocity } else { glam::f32::Vec3::ZERO };
+        if violations.is_empty() {
+            self.last_eye_position = Some(player.get_eye_position());
+        }
+
+        violations
+    }
+
+    //Adds to the counters, returns the kind that has gone over its kick threshold if any
+    pub fn record(&mut self, settings: &ValidationSettings, violations: &[Violation]) -> Option<ViolationKind> {
+
+        for violation in violations {
+            self.counts[violation.kind.get_index()] += 1;
+        }
+
+        ViolationKind::ALL.into_iter().find(|kind| settings.get_kick_threshold(*kind).is_some_and(|threshold| self.get_count(*kind) >= threshold))
+    }
+
+    pub fn get_count(&self, kind: ViolationKind) -> u32 {
+
+        self.counts[kind.get_index()]
+    }
+
+    pub fn get_total_count(&self) -> u32 {
+
+        self.counts.iter().sum()
+    }
+
+    //After the server moves the player itself, e.g. a respawn
+    pub fn reset_movement(&mut self) {
+
+        self.last_velocity = glam::f32::Vec3::ZERO;
+        self.last_eye_position = None;
+    }
+}
+
+//Angle between where the two commands were looking
+fn get_turn_angle(from: &UserCmd, to: &UserCmd) -> f32 {
+
+    let direction = |command: &UserCmd| glam::f32::Vec3::new(command.pitch.cos() * command.yaw.cos(), command.pitch.sin(), command.pitch.cos() * command.yaw.sin());
+
+    direction(from).angle_between(direction(to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(tick: u32, yaw: f32, pitch: f32) -> UserCmd {
+
+        UserCmd::new(tick, glam::f32::Vec3::Z, yaw, pitch, 0, 0.0)
+    }
+
+    #[test]
+    fn normal_play_passes() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+
+        for tick in 0..120 {
+            let mut command = UserCmd::new(tick, glam::f32::Vec3::new(1.0, 1.0, if tick < 60 { 1.0 } else { -1.0 }), tick as f32 * 0.05, 0.3, 0, 0.0);
+            assert!(validator.check_command(&settings, &mut command).is_empty());
+            let previous_position = *player.get_position();
+            player.simulate(&command, &MovementSettings::default(), &[]);
+            assert!(validator.check_movement(&settings, previous_position, &player).is_empty());
+        }
+    }
+
+    #[test]
+    fn impossible_commands_are_flagged_and_fixed() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+
+        let mut first = command(0, 1.0, 0.2);
+        assert!(validator.check_command(&settings, &mut first).is_empty());
+
+        let mut broken = UserCmd::new(1, glam::f32::Vec3::new(f32::NAN, 0.0, 5.0), 1.0, 0.2, 0, 0.0);
+        let violations = validator.check_command(&settings, &mut broken);
+        assert_eq!(violations[0].kind, ViolationKind::InvalidInput);
+        assert_eq!(broken.movement, glam::f32::Vec3::ZERO);
+
+        let mut too_fast = UserCmd::new(2, glam::f32::Vec3::new(0.0, 0.0, 5.0), 1.0, 0.2, 0, 0.0);
+        assert_eq!(validator.check_command(&settings, &mut too_fast)[0].kind, ViolationKind::InvalidInput);
+        assert_eq!(too_fast.movement, glam::f32::Vec3::Z);
+
+        let mut looking_backwards = command(3, 1.0, 2.0);
+        assert_eq!(validator.check_command(&settings, &mut looking_backwards)[0].kind, ViolationKind::Pitch);
+        assert_eq!(looking_backwards.pitch, MAX_PITCH);
+    }
+
+    #[test]
+    fn snapping_round_is_an_impossible_turn() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+
+        validator.check_command(&settings, &mut command(0, 0.0, 0.0));
+        //Half a turn in one tick
+        let violations = validator.check_command(&settings, &mut command(1, std::f32::consts::PI, 0.0));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::TurnRate);
+        //The same turn spread over a second is fine, and yaw wrapping round isn't a turn
+        for tick in 2..62 {
+            assert!(validator.check_command(&settings, &mut command(tick, std::f32::consts::PI * (61 - tick) as f32 / 60.0, 0.0)).is_empty());
+        }
+        assert!(validator.check_command(&settings, &mut command(62, std::f32::consts::TAU, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn skipping_ticks_doesnt_hide_a_flick() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+
+        validator.check_command(&settings, &mut command(0, 0.0, 0.0));
+        //Claiming a second went by since the last command
+        let violations = validator.check_command(&settings, &mut command(60, std::f32::consts::PI, 0.0));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::TurnRate);
+    }
+
+    #[test]
+    fn teleports_and_speed_are_flagged() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+
+        let previous_position = *player.get_position();
+        player.set_position(glam::f32::Vec3::new(10.0, 0.0, 0.0));
+        player.simulate(&command(0, 0.0, 0.0), &MovementSettings::default(), &[]);
+
+        let kinds: Vec<ViolationKind> = validator.check_movement(&settings, previous_position, &player).iter().map(|violation| violation.kind).collect();
+        assert!(kinds.contains(&ViolationKind::Teleport));
+    }
+
+    #[test]
+    fn shots_from_the_far_side_of_a_wall_are_flagged() {
+
+        let settings = ValidationSettings::default();
+        let mut validator = PlayerValidator::new();
+        let mut player = Player::new(glam::f32::Vec3::ZERO, 0.0);
+        let wall = collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(-20.0, -20.0, -5.0), glam::f32::Vec3::new(20.0, -20.0, -5.0), glam::f32::Vec3::new(0.0, 20.0, -5.0)),
+        ]);
+        let shot_from = |origin: glam::f32::Vec3| weapon::Shot { weapon: 0, origin, direction: glam::f32::Vec3::NEG_Z };
+
+        //Nothing to go on before the first move
+        assert!(validator.check_shot(&shot_from(glam::f32::Vec3::new(0.0, 0.0, -10.0)), &[&wall]).is_empty());
+
+        let previous_position = *player.get_position();
+        player.simulate(&command(0, 0.0, 0.0), &MovementSettings::default(), &[]);
+        assert!(validator.check_movement(&settings, previous_position, &player).is_empty());
+
+        //From where the eye is, and just short of the wall, is fine
+        assert!(validator.check_shot(&shot_from(player.get_eye_position()), &[&wall]).is_empty());
+        assert!(validator.check_shot(&shot_from(player.get_eye_position() + glam::f32::Vec3::new(0.0, 0.0, -4.0)), &[&wall]).is_empty());
+
+        let violations = validator.check_shot(&shot_from(player.get_eye_position() + glam::f32::Vec3::new(0.0, 0.0, -10.0)), &[&wall]);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::ShotThroughWall);
+
+        //The server moving the player isn't them going through anything
+        validator.reset_movement();
+        assert!(validator.check_shot(&shot_from(glam::f32::Vec3::new(0.0, 0.0, -10.0)), &[&wall]).is_empty());
+    }
+
+    #[test]
+    fn kicks_once_a_threshold_is_reached() {
+
+        let mut settings = ValidationSettings::default();
+        settings.set_kick_threshold(ViolationKind::Speed, Some(3));
+        let mut validator = PlayerValidator::new();
+        let speeding = [Violation { kind: ViolationKind::Speed, value: 100.0, limit: 10.0 }];
+
+        assert_eq!(validator.record(&settings, &speeding), None);
+        assert_eq!(validator.record(&settings, &speeding), None);
+        assert_eq!(validator.record(&settings, &speeding), Some(ViolationKind::Speed));
+        assert_eq!(validator.get_count(ViolationKind::Speed), 3);
+
+        settings.set_kick_threshold(ViolationKind::Speed, None);
+        assert_eq!(validator.record(&settings, &speeding), None);
+    }
+}
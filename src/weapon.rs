@@ -0,0 +1,424 @@
+use serde::{Deserialize, Serialize};
+
+use crate::collision;
+use crate::game_state::TICK_RATE_SECONDS;
+use crate::lag_compensation::HitscanHit;
+use crate::net::interest;
+use crate::user_cmd::{self, UserCmd};
+
+//Seconds to put one weapon away and get the next one out
+pub const SWITCH_TIME: f32 = 0.4;
+
+//Everything that makes one gun different from another, adding a weapon is adding a row to WEAPONS
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WeaponDefinition {
+    pub name: &'static str,
+    pub damage: f32,
+    //Seconds between shots
+    pub fire_interval: f32,
+    pub magazine_size: u32,
+    //Seconds
+    pub reload_time: f32,
+    //Radians, furthest from the crosshair a shot can go
+    pub spread: f32,
+    pub range: f32,
+    //Keeps firing while held, otherwise fire has to be let go between shots
+    pub automatic: bool,
+    //Launches one of these instead of tracing a hit, damage is then the most the splash can do
+    pub projectile: Option<ProjectileDefinition>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProjectileDefinition {
+    pub speed: f32,
+    //Times the player's gravity, 0 flies straight
+    pub gravity_scale: f32,
+    //Seconds until it goes off by itself
+    pub lifetime: f32,
+    //Times it can glance off the world, hitting it again after that sets it off
+    pub max_bounces: u32,
+    //How much of the speed into a surface comes back out of it
+    pub restitution: f32,
+    pub radius: f32,
+    pub splash_radius: f32,
+}
+
+//In number key order
+pub const WEAPONS: [WeaponDefinition; 5] = [
+    WeaponDefinition { name: "Pistol", damage: 25.0, fire_interval: 0.2, magazine_size: 12, reload_time: 1.2, spread: 0.01, range: 50.0, automatic: false, projectile: None },
+    WeaponDefinition { name: "Rifle", damage: 14.0, fire_interval: 0.1, magazine_size: 30, reload_time: 2.0, spread: 0.03, range: 100.0, automatic: true, projectile: None },
+    WeaponDefinition { name: "Sniper", damage: 90.0, fire_interval: 1.2, magazine_size: 5, reload_time: 3.0, spread: 0.0, range: 300.0, automatic: false, projectile: None },
+    WeaponDefinition { name: "Rocket launcher", damage: 100.0, fire_interval: 0.8, magazine_size: 4, reload_time: 2.5, spread: 0.0, range: 0.0, automatic: false,
+        projectile: Some(ProjectileDefinition { speed: 25.0, gravity_scale: 0.0, lifetime: 5.0, max_bounces: 0, restitution: 0.0, radius: 0.2, splash_radius: 4.0 }) },
+    WeaponDefinition { name: "Grenade launcher", damage: 80.0, fire_interval: 0.6, magazine_size: 6, reload_time: 2.5, spread: 0.0, range: 0.0, automatic: false,
+        projectile: Some(ProjectileDefinition { speed: 15.0, gravity_scale: 1.0, lifetime: 2.5, max_bounces: 3, restitution: 0.5, radius: 0.15, splash_radius: 5.0 }) },
+];
+
+impl WeaponDefinition {
+
+    pub fn get_fire_interval_ticks(&self) -> u32 {
+
+        seconds_to_ticks(self.fire_interval)
+    }
+
+    pub fn get_reload_ticks(&self) -> u32 {
+
+        seconds_to_ticks(self.reload_time)
+    }
+}
+
+impl ProjectileDefinition {
+
+    pub fn get_lifetime_ticks(&self) -> u32 {
+
+        seconds_to_ticks(self.lifetime)
+    }
+}
+
+//A shot leaving the barrel, direction already has the spread in it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Shot {
+    //Index into WEAPONS
+    pub weapon: usize,
+    pub origin: glam::f32::Vec3,
+    pub direction: glam::f32::Vec3,
+}
+
+impl Shot {
+
+    //Out to the weapon's range
+    pub fn get_ray(&self) -> collision::Ray {
+
+        collision::Ray::new(self.origin, self.direction * WEAPONS[self.weapon].range)
+    }
+
+    pub fn get_definition(&self) -> &'static WeaponDefinition {
+
+        &WEAPONS[self.weapon]
+    }
+}
+
+//Whatever a shot hit first
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShotHit {
+    Player(HitscanHit),
+    World { position: glam::f32::Vec3, time: f32 },
+}
+
+//Each player's guns, only changed by update so the client can predict it and the server can send it back to check against
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeaponState {
+    current: u8,
+    //Rounds left in each weapon's magazine, reloading is free
+    ammo: [u32; WEAPONS.len()],
+    //Ticks until the current weapon can fire, switching to it counts
+    cooldown_ticks: u32,
+    //Ticks until the magazine is full again, 0 when not reloading
+    reload_ticks: u32,
+    //Fire was down last tick, semi automatic weapons need it let go before the next shot
+    trigger_held: bool,
+    //Seeds the spread so the client and server scatter shots the same way
+    shot_count: u32,
+}
+
+impl Default for WeaponState {
+
+    fn default() -> Self {
+
+        Self::new()
+    }
+}
+
+impl WeaponState {
+
+    pub fn new() -> Self {
+
+        Self { current: 0, ammo: WEAPONS.map(|weapon| weapon.magazine_size), cooldown_ticks: 0, reload_ticks: 0, trigger_held: false, shot_count: 0 }
+    }
+
+    //One tick, forward is where the crosshair points, returns the direction of the shot if the weapon went off
+    pub fn update(&mut self, command: &UserCmd, forward: glam::f32::Vec3) -> Option<glam::f32::Vec3> {
+
+        self.cooldown_ticks = self.cooldown_ticks.saturating_sub(1);
+        if self.reload_ticks > 0 {
+            self.reload_ticks -= 1;
+            if self.reload_ticks == 0 {
+                self.ammo[self.current as usize] = self.get_definition().magazine_size;
+            }
+        }
+
+        //Switching away drops a reload that was going
+        if let Some(weapon) = command.weapon.filter(|weapon| *weapon != self.current && (*weapon as usize) < WEAPONS.len()) {
+            self.current = weapon;
+            self.reload_ticks = 0;
+            self.cooldown_ticks = seconds_to_ticks(SWITCH_TIME);
+        }
+
+        let definition = *self.get_definition();
+        let trigger = command.is_pressed(user_cmd::BUTTON_FIRE);
+        let pulled = trigger && (definition.automatic || !self.trigger_held);
+        self.trigger_held = trigger;
+
+        let ammo = &mut self.ammo[self.current as usize];
+        if self.reload_ticks == 0 && *ammo < definition.magazine_size && (command.is_pressed(user_cmd::BUTTON_RELOAD) || (pulled && *ammo == 0)) {
+            self.reload_ticks = definition.get_reload_ticks();
+        }
+
+        if !pulled || *ammo == 0 || self.cooldown_ticks > 0 || self.reload_ticks > 0 {
+            return None;
+        }
+
+        *ammo -= 1;
+        self.cooldown_ticks = definition.get_fire_interval_ticks();
+        self.shot_count = self.shot_count.wrapping_add(1);
+
+        Some(get_spread_direction(forward, definition.spread, self.shot_count))
+    }
+
+    pub fn get_current(&self) -> usize {
+
+        self.current as usize
+    }
+
+    pub fn get_definition(&self) -> &'static WeaponDefinition {
+
+        &WEAPONS[self.current as usize]
+    }
+
+    //Rounds left in the current weapon
+    pub fn get_ammo(&self) -> u32 {
+
+        self.ammo[self.current as usize]
+    }
+
+    pub fn is_reloading(&self) -> bool {
+
+        self.reload_ticks > 0
+    }
+}
+
+//Where a projectile went off, hurts whoever is close enough and can be seen from it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Explosion {
+    pub owner: u32,
+    //Index into WEAPONS
+    pub weapon: usize,
+    pub position: glam::f32::Vec3,
+}
+
+impl Explosion {
+
+    pub fn get_definition(&self) -> &'static WeaponDefinition {
+
+        &WEAPONS[self.weapon]
+    }
+
+    //Full damage at the center falling off to nothing at the edge of the splash, measured to the nearest part of the body
+    pub fn get_splash_damage(&self, body: &collision::Capsule, world: &[&collision::TriangleSoup]) -> Option<f32> {
+
+        let definition = self.get_definition();
+        let splash_radius = definition.projectile?.splash_radius;
+        let axis = (body.get_tip() - body.get_base()).normalize_or_zero() * body.get_radius();
+        let closest = collision::closest_point_on_line(&(body.get_base() + axis), &(body.get_tip() - axis), &self.position);
+        let distance = (self.position.distance(closest) - body.get_radius()).max(0.0);
+        if distance >= splash_radius || !interest::is_visible(self.position, closest, world) {
+            return None;
+        }
+
+        Some(definition.damage * (1.0 - distance / splash_radius))
+    }
+}
+
+//Closest of the world and whichever player the lag compensated trace hit, None if nothing was in range
+pub fn trace(ray: &collision::Ray, world: &[&collision::TriangleSoup], player_hit: Option<HitscanHit>) -> Option<ShotHit> {
+
+    let world_hit = world.iter()
+        .map(|triangle_soup| triangle_soup.vs_ray(ray))
+        .filter(|t| t.collided && t.penetration_or_time <= 1.0)
+        .min_by(|a, b| a.penetration_or_time.total_cmp(&b.penetration_or_time));
+
+    match (world_hit, player_hit) {
+        (Some(world_hit), Some(player_hit)) if player_hit.time <= world_hit.penetration_or_time => Some(ShotHit::Player(player_hit)),
+        (Some(world_hit), _) => Some(ShotHit::World { position: world_hit.position, time: world_hit.penetration_or_time }),
+        (None, Some(player_hit)) => Some(ShotHit::Player(player_hit)),
+        (None, None) => None,
+    }
+}
+
+fn seconds_to_ticks(seconds: f32) -> u32 {
+
+    ((seconds / TICK_RATE_SECONDS).round() as u32).max(1)
+}
+
+//Somewhere in the cone around forward, picked from the seed rather than rand so both ends pick the same place
+fn get_spread_direction(forward: glam::f32::Vec3, spread: f32, seed: u32) -> glam::f32::Vec3 {
+
+    let forward = forward.normalize_or_zero();
+    if spread <= 0.0 {
+        return forward;
+    }
+
+    let angle = get_unit_random(seed.wrapping_mul(2)) * std::f32::consts::TAU;
+    //Square root spreads shots evenly over the cone instead of bunching them in the middle
+    let offset = spread * get_unit_random(seed.wrapping_mul(2).wrapping_add(1)).sqrt();
+    let right = forward.cross(glam::f32::Vec3::Y).try_normalize().unwrap_or(glam::f32::Vec3::X);
+    let up = right.cross(forward);
+
+    (forward + (right * angle.cos() + up * angle.sin()) * offset.tan()).normalize()
+}
+
+//Between 0 and 1
+fn get_unit_random(seed: u32) -> f32 {
+
+    let mut x = seed;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hitbox::HitRegion;
+
+    const RIFLE: u8 = 1;
+    const SNIPER: u8 = 2;
+
+    fn command(tick: u32, buttons: u32, weapon: Option<u8>) -> UserCmd {
+
+        let mut command = UserCmd::new(tick, glam::f32::Vec3::ZERO, 0.0, 0.0, buttons, 0.0);
+        command.weapon = weapon;
+
+        command
+    }
+
+    //Runs ticks with the same buttons held, returns how many shots went off
+    fn hold(weapons: &mut WeaponState, buttons: u32, ticks: u32) -> u32 {
+
+        (0..ticks).filter(|tick| weapons.update(&command(*tick, buttons, None), glam::f32::Vec3::Z).is_some()).count() as u32
+    }
+
+    #[test]
+    fn automatic_fire_follows_the_fire_interval_and_reloads_when_empty() {
+
+        let mut weapons = WeaponState::new();
+        weapons.update(&command(0, 0, Some(RIFLE)), glam::f32::Vec3::Z);
+        assert_eq!(weapons.get_definition().name, "Rifle");
+
+        //Nothing comes out while it's being got out
+        assert_eq!(hold(&mut weapons, user_cmd::BUTTON_FIRE, seconds_to_ticks(SWITCH_TIME) - 1), 0);
+
+        let rifle = WEAPONS[RIFLE as usize];
+        let ticks = rifle.get_fire_interval_ticks() * 10;
+        assert_eq!(hold(&mut weapons, user_cmd::BUTTON_FIRE, ticks), 10);
+        assert_eq!(weapons.get_ammo(), rifle.magazine_size - 10);
+
+        //Empties the magazine then pulling the trigger on nothing reloads
+        assert_eq!(hold(&mut weapons, user_cmd::BUTTON_FIRE, rifle.get_fire_interval_ticks() * (rifle.magazine_size - 10)), rifle.magazine_size - 10);
+        assert_eq!(weapons.get_ammo(), 0);
+        assert!(weapons.is_reloading());
+        assert_eq!(hold(&mut weapons, user_cmd::BUTTON_FIRE, rifle.get_reload_ticks()), 1);
+        assert_eq!(weapons.get_ammo(), rifle.magazine_size - 1);
+    }
+
+    #[test]
+    fn semi_automatic_needs_the_trigger_let_go() {
+
+        let mut weapons = WeaponState::new();
+        let pistol = WEAPONS[0];
+        let interval = pistol.get_fire_interval_ticks();
+
+        assert_eq!(hold(&mut weapons, user_cmd::BUTTON_FIRE, interval * 5), 1);
+        hold(&mut weapons, 0, 1);
+
+        let mut shots = 0;
+        for tick in 0..interval * 5 {
+            let buttons = if tick % interval == 0 { user_cmd::BUTTON_FIRE } else { 0 };
+            shots += weapons.update(&command(tick, buttons, None), glam::f32::Vec3::Z).is_some() as u32;
+        }
+        assert_eq!(shots, 5);
+    }
+
+    #[test]
+    fn switching_cancels_reloads_and_keeps_each_magazine() {
+
+        let mut weapons = WeaponState::new();
+        hold(&mut weapons, user_cmd::BUTTON_FIRE, 1);
+        let pistol_ammo = weapons.get_ammo();
+        hold(&mut weapons, user_cmd::BUTTON_RELOAD, 1);
+        assert!(weapons.is_reloading());
+
+        weapons.update(&command(0, 0, Some(SNIPER)), glam::f32::Vec3::Z);
+        assert!(!weapons.is_reloading());
+        assert_eq!(weapons.get_ammo(), WEAPONS[SNIPER as usize].magazine_size);
+
+        weapons.update(&command(0, 0, Some(0)), glam::f32::Vec3::Z);
+        assert_eq!(weapons.get_ammo(), pistol_ammo);
+
+        //Not a weapon, nothing happens
+        weapons.update(&command(0, 0, Some(WEAPONS.len() as u8)), glam::f32::Vec3::Z);
+        assert_eq!(weapons.get_current(), 0);
+
+        //A full magazine doesn't need reloading
+        let mut weapons = WeaponState::new();
+        hold(&mut weapons, user_cmd::BUTTON_RELOAD, 1);
+        assert!(!weapons.is_reloading());
+    }
+
+    #[test]
+    fn spread_stays_in_the_cone_and_is_the_same_everywhere() {
+
+        let forward = glam::f32::Vec3::new(1.0, 0.5, -0.3).normalize();
+        for seed in 0..500 {
+            let direction = get_spread_direction(forward, 0.05, seed);
+            assert!(direction.angle_between(forward) <= 0.05 + 0.0001);
+            assert_eq!(direction, get_spread_direction(forward, 0.05, seed));
+        }
+        assert_ne!(get_spread_direction(forward, 0.05, 1), get_spread_direction(forward, 0.05, 2));
+        assert_eq!(get_spread_direction(forward, 0.0, 1), forward);
+    }
+
+    #[test]
+    fn splash_falls_off_with_distance_and_stops_at_walls() {
+
+        let explosion = Explosion { owner: 1, weapon: 3, position: glam::f32::Vec3::ZERO };
+        let definition = WEAPONS[3];
+        let splash_radius = definition.projectile.unwrap().splash_radius;
+        let body = |x: f32| collision::Capsule::new(glam::f32::Vec3::new(x, -1.0, 0.0), glam::f32::Vec3::new(x, 1.0, 0.0), 0.5);
+
+        //Touching, halfway and out of reach, measured from the surface of the body
+        assert_eq!(explosion.get_splash_damage(&body(0.5), &[]), Some(definition.damage));
+        let halfway = explosion.get_splash_damage(&body(0.5 + splash_radius * 0.5), &[]).unwrap();
+        assert!((halfway - definition.damage * 0.5).abs() < 0.001);
+        assert_eq!(explosion.get_splash_damage(&body(0.5 + splash_radius), &[]), None);
+
+        let wall = collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(1.0, -5.0, -5.0), glam::f32::Vec3::new(1.0, 5.0, 0.0), glam::f32::Vec3::new(1.0, -5.0, 5.0)),
+        ]);
+        assert_eq!(explosion.get_splash_damage(&body(2.0), &[&wall]), None);
+        assert!(explosion.get_splash_damage(&body(-2.0), &[&wall]).is_some());
+
+        //Hitscan weapons don't splash
+        assert_eq!(Explosion { weapon: 0, ..explosion }.get_splash_damage(&body(0.5), &[]), None);
+    }
+
+    #[test]
+    fn closest_hit_wins() {
+
+        //Wall across the shot at z = 5
+        let wall = collision::TriangleSoup::new(vec![
+            collision::Triangle::new(glam::f32::Vec3::new(-5.0, -5.0, 5.0), glam::f32::Vec3::new(5.0, -5.0, 5.0), glam::f32::Vec3::new(0.0, 5.0, 5.0)),
+        ]);
+        let ray = collision::Ray::new(glam::f32::Vec3::ZERO, glam::f32::Vec3::Z * 10.0);
+        let player_hit = |z: f32| HitscanHit { player_id: 2, hitbox: 0, region: HitRegion::Head, position: glam::f32::Vec3::Z * z, time: z / 10.0 };
+
+        assert_eq!(trace(&ray, &[&wall], Some(player_hit(3.0))), Some(ShotHit::Player(player_hit(3.0))));
+        assert!(matches!(trace(&ray, &[&wall], Some(player_hit(7.0))), Some(ShotHit::World { time, .. }) if (time - 0.5).abs() < 0.001));
+        assert_eq!(trace(&ray, &[], Some(player_hit(7.0))), Some(ShotHit::Player(player_hit(7.0))));
+        assert_eq!(trace(&collision::Ray::new(glam::f32::Vec3::ZERO, glam::f32::Vec3::NEG_Z * 10.0), &[&wall], None), None);
+    }
+}
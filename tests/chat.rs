@@ -0,0 +1,139 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::chat::*;
+use mp_first_person_shooter::console::Console;
+use mp_first_person_shooter::game_state::GameState;
+use mp_first_person_shooter::input::Inputs;
+use mp_first_person_shooter::net::*;
+use mp_first_person_shooter::resource_manager::ResourceManager;
+
+//A whole game over loopback, ticked by hand
+struct Game {
+    game_state: GameState,
+    resource_manager: ResourceManager,
+    inputs: Inputs,
+    console: Rc<RefCell<Console>>,
+}
+
+impl Game {
+
+    fn new(network: Network) -> Self {
+
+        let console = Rc::new(RefCell::new(Console::new()));
+        let mut game_state = GameState::new(console.clone());
+        game_state.set_network(network);
+
+        Self { game_state, resource_manager: ResourceManager::new(console.clone()), inputs: Inputs::new(), console }
+    }
+
+    fn update(&mut self) {
+
+        self.game_state.update(&mut self.inputs, &mut self.resource_manager);
+    }
+
+    fn get_chat(&self) -> Vec<String> {
+
+        self.game_state.get_chat_log().get_history().iter().map(|line| line.get_display_text()).collect()
+    }
+
+    fn is_connected(&self) -> bool {
+
+        matches!(self.game_state.get_network(), Some(Network::Client(client)) if client.is_connected())
+    }
+}
+
+fn pump_until(games: &mut [&mut Game], mut done: impl FnMut(&[&mut Game]) -> bool) -> bool {
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        for game in games.iter_mut() {
+            game.update();
+        }
+        if done(games) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    false
+}
+
+fn pump_for(games: &mut [&mut Game], duration: Duration) {
+
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        for game in games.iter_mut() {
+            game.update();
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+//Host is on red, then players alternate blue and red
+fn start() -> (Game, Game, Game) {
+
+    let server = NetServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let mut host = Game::new(Network::Server(server));
+    let mut blue = Game::new(Network::Client(NetClient::new(address).unwrap()));
+    assert!(pump_until(&mut [&mut host, &mut blue], |games| games[1].is_connected()));
+    let mut red = Game::new(Network::Client(NetClient::new(address).unwrap()));
+    assert!(pump_until(&mut [&mut host, &mut blue, &mut red], |games| games[2].is_connected()));
+
+    (host, blue, red)
+}
+
+#[test]
+fn all_chat_reaches_everyone_and_team_chat_only_the_team() {
+
+    let (mut host, mut blue, mut red) = start();
+
+    blue.game_state.send_chat(ChatChannel::All, "  hello everyone ");
+    assert!(pump_until(&mut [&mut host, &mut blue, &mut red], |games| games.iter().all(|game| game.get_chat().len() == 1)));
+    for game in [&host, &blue, &red] {
+        assert_eq!(game.get_chat(), vec!["Player 1: hello everyone".to_string()]);
+    }
+
+    red.game_state.send_chat(ChatChannel::Team, "go left");
+    assert!(pump_until(&mut [&mut host, &mut blue, &mut red], |games| games[0].get_chat().len() == 2 && games[2].get_chat().len() == 2));
+    //Give a stray message a chance to turn up
+    pump_for(&mut [&mut host, &mut blue, &mut red], Duration::from_millis(200));
+    assert_eq!(host.get_chat()[1], "(Team) Player 2: go left");
+    assert_eq!(red.get_chat()[1], "(Team) Player 2: go left");
+    assert_eq!(blue.get_chat().len(), 1);
+
+    //The server keeps a record of every line with the team it came from
+    let log = host.console.borrow().get_log().clone();
+    assert!(log.contains("Chat [Blue] Player 1: hello everyone"));
+    assert!(log.contains("Chat [Red] (Team) Player 2: go left"));
+}
+
+#[test]
+fn floods_are_dropped_and_the_sender_is_warned() {
+
+    let (mut host, mut blue, mut red) = start();
+
+    for i in 0..CHAT_BURST as usize + 3 {
+        blue.game_state.send_chat(ChatChannel::All, &format!("spam {}", i));
+    }
+    assert!(pump_until(&mut [&mut host, &mut blue, &mut red], |games| games[1].get_chat().iter().any(|line| line == "You are sending messages too fast")));
+    pump_for(&mut [&mut host, &mut blue, &mut red], Duration::from_millis(200));
+
+    assert_eq!(host.get_chat().len(), CHAT_BURST as usize);
+    assert_eq!(red.get_chat().len(), CHAT_BURST as usize);
+    assert!(host.console.borrow().get_log().contains("Dropped chat from player 1"));
+}
+
+#[test]
+fn long_and_empty_messages_are_cut_or_dropped() {
+
+    let (mut host, mut blue, mut red) = start();
+
+    blue.game_state.send_chat(ChatChannel::All, "   ");
+    blue.game_state.send_chat(ChatChannel::All, &"a".repeat(MAX_CHAT_LENGTH * 3));
+    assert!(pump_until(&mut [&mut host, &mut blue, &mut red], |games| games[2].get_chat().len() == 1));
+
+    assert_eq!(red.game_state.get_chat_log().get_history()[0].text.len(), MAX_CHAT_LENGTH);
+}
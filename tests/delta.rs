@@ -0,0 +1,202 @@
+use mp_first_person_shooter::net::delta::*;
+use mp_first_person_shooter::net::PlayerSnapshot;
+
+//Player 1 walks in a circle, player 2 stands still, player 3 joins at tick 5 and leaves at tick 15
+fn world(tick: u32) -> Vec<PlayerSnapshot> {
+
+    let t = tick as f32 / 10.0;
+    let mut players = vec![
+        PlayerSnapshot { id: 1, position: glam::f32::Vec3::new(t.cos() * 5.0, 1.0, t.sin() * 5.0), yaw: t, pitch: (t * 0.5).sin() },
+        PlayerSnapshot { id: 2, position: glam::f32::Vec3::new(-3.0, 1.0, 2.0), yaw: 1.0, pitch: 0.0 },
+    ];
+    if (5..15).contains(&tick) {
+        players.push(PlayerSnapshot { id: 3, position: glam::f32::Vec3::new(tick as f32, 0.0, 0.0), yaw: 0.0, pitch: 0.0 });
+    }
+
+    players
+}
+
+fn assert_matches(decoded: &[PlayerSnapshot], expected: &[PlayerSnapshot]) {
+
+    let decoded: Vec<QuantizedPlayer> = decoded.iter().map(QuantizedPlayer::new).collect();
+    assert_eq!(decoded, quantize(expected));
+}
+
+#[test]
+fn quantization_is_close_enough() {
+
+    let player = PlayerSnapshot { id: 4, position: glam::f32::Vec3::new(123.456, -7.89, 0.001), yaw: -1.0, pitch: 1.2 };
+    let round_trip = QuantizedPlayer::new(&player).to_snapshot();
+
+    assert!(round_trip.position.distance(player.position) < 0.005);
+    assert!((round_trip.yaw - (-1.0_f32).rem_euclid(std::f32::consts::TAU)).abs() < 0.001);
+    assert!((round_trip.pitch - 1.2).abs() < 0.001);
+
+    //Quantizing an already quantized value has to be stable or the two ends drift apart
+    assert_eq!(QuantizedPlayer::new(&round_trip), QuantizedPlayer::new(&player));
+}
+
+#[test]
+fn first_snapshot_is_full_then_only_changes_are_sent() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    let full = encoder.encode(0, &world(0));
+    assert_eq!(full.baseline_tick, None);
+    assert_eq!(full.changed.len(), 2);
+    assert_matches(&decoder.decode(0, &full).unwrap(), &world(0));
+
+    encoder.acknowledge(decoder.get_ack().unwrap());
+    let delta = encoder.encode(1, &world(1));
+    assert_eq!(delta.baseline_tick, Some(0));
+    //Player 2 didn't move so isn't in there at all
+    assert_eq!(delta.changed.len(), 1);
+    assert_eq!(delta.changed[0].id, 1);
+    assert_matches(&decoder.decode(1, &delta).unwrap(), &world(1));
+
+    let mut buffer = [0; 256];
+    let full_size = postcard::to_slice(&full, &mut buffer).unwrap().len();
+    let delta_size = postcard::to_slice(&delta, &mut buffer).unwrap().len();
+    assert!(delta_size < full_size);
+}
+
+#[test]
+fn joins_and_leaves_round_trip() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    for tick in 0..20 {
+        let delta = encoder.encode(tick, &world(tick));
+        assert_matches(&decoder.decode(tick, &delta).unwrap(), &world(tick));
+        encoder.acknowledge(decoder.get_ack().unwrap());
+
+        if tick == 15 {
+            assert_eq!(delta.removed, vec![3]);
+        }
+    }
+}
+
+#[test]
+fn lost_snapshots_and_acks_still_decode() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    for tick in 0..100 {
+        let delta = encoder.encode(tick, &world(tick));
+
+        //Every third snapshot never arrives
+        if tick % 3 == 1 {
+            continue;
+        }
+
+        assert_matches(&decoder.decode(tick, &delta).unwrap(), &world(tick));
+
+        //And half the acks going back get lost too
+        if tick % 2 == 0 {
+            encoder.acknowledge(decoder.get_ack().unwrap());
+        }
+    }
+}
+
+#[test]
+fn out_of_order_acks_never_move_the_baseline_back() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    let deltas: Vec<WorldDelta> = (0..10).map(|tick| encoder.encode(tick, &world(tick))).collect();
+    for (tick, delta) in deltas.iter().enumerate() {
+        decoder.decode(tick as u32, delta).unwrap();
+    }
+
+    encoder.acknowledge(8);
+    encoder.acknowledge(3);
+    encoder.acknowledge(6);
+    assert_eq!(encoder.get_acked_tick(), Some(8));
+
+    let delta = encoder.encode(10, &world(10));
+    assert_eq!(delta.baseline_tick, Some(8));
+    assert_matches(&decoder.decode(10, &delta).unwrap(), &world(10));
+}
+
+#[test]
+fn out_of_order_snapshots_decode_against_their_own_baseline() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    let full = encoder.encode(0, &world(0));
+    decoder.decode(0, &full).unwrap();
+    encoder.acknowledge(0);
+
+    let first = encoder.encode(1, &world(1));
+    let second = encoder.encode(2, &world(2));
+
+    //Arrive backwards, both were diffed against tick 0 so both still work
+    assert_matches(&decoder.decode(2, &second).unwrap(), &world(2));
+    assert_matches(&decoder.decode(1, &first).unwrap(), &world(1));
+    assert_eq!(decoder.get_ack(), Some(2));
+}
+
+#[test]
+fn stale_ack_falls_back_to_a_full_snapshot() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    decoder.decode(0, &encoder.encode(0, &world(0))).unwrap();
+    encoder.acknowledge(0);
+
+    //Client goes quiet for long enough that the server forgets tick 0
+    for tick in 1..100 {
+        encoder.encode(tick, &world(tick));
+    }
+
+    let delta = encoder.encode(100, &world(100));
+    assert_eq!(delta.baseline_tick, None);
+    assert_matches(&decoder.decode(100, &delta).unwrap(), &world(100));
+}
+
+#[test]
+fn unknown_baseline_is_rejected() {
+
+    let mut decoder = DeltaDecoder::new();
+
+    let delta = WorldDelta { baseline_tick: Some(42), changed: Vec::new(), removed: Vec::new() };
+    assert!(decoder.decode(43, &delta).is_none());
+    assert_eq!(decoder.get_ack(), None);
+}
+
+#[test]
+fn budget_defers_players_instead_of_dropping_them() {
+
+    let mut encoder = DeltaEncoder::new();
+    let mut decoder = DeltaDecoder::new();
+
+    //Only room for one new player at a time
+    let players = world(0);
+    let budget = players.iter().chain(&world(1)).map(|player| get_encoded_size(&[], &QuantizedPlayer::new(player))).max().unwrap();
+
+    let (first, updated) = encoder.encode_prioritized(0, &players, &[2, 1], budget);
+    assert_eq!(updated, vec![2]);
+    let decoded = decoder.decode(0, &first).unwrap();
+    assert_matches(&decoded, &players[1..]);
+    encoder.acknowledge(0);
+
+    //Player 1 gets its turn, player 2 hasn't moved so costs nothing
+    let moved = world(1);
+    let (second, updated) = encoder.encode_prioritized(1, &moved, &[1, 2], budget);
+    assert_eq!(updated, vec![1, 2]);
+    assert!(second.removed.is_empty());
+    assert_matches(&decoder.decode(1, &second).unwrap(), &moved);
+    encoder.acknowledge(1);
+
+    //Nothing fits, everyone stays where the client last saw them rather than being removed
+    let (third, updated) = encoder.encode_prioritized(2, &world(2), &[1, 2], 0);
+    assert_eq!(updated, vec![2]);
+    assert!(third.changed.is_empty() && third.removed.is_empty());
+    assert_matches(&decoder.decode(2, &third).unwrap(), &moved);
+}
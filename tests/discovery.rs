@@ -0,0 +1,98 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::net::discovery::*;
+use mp_first_person_shooter::net::PROTOCOL_VERSION;
+
+//Pumps both ends until the browser has heard from count servers
+fn poll_until(browser: &mut ServerBrowser, responders: &mut [&mut DiscoveryResponder], count: usize) -> Vec<DiscoveredServer> {
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        for responder in responders.iter_mut() {
+            responder.poll(3, 16, 27015);
+        }
+        browser.poll();
+        let servers = browser.get_servers(ServerSortKey::Name, false);
+        if servers.len() >= count {
+            return servers.into_iter().cloned().collect();
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    Vec::new()
+}
+
+#[test]
+fn servers_answer_queries_over_loopback() {
+
+    let mut responder = DiscoveryResponder::bind("127.0.0.1:0", "LAN party", "test_triangle").unwrap();
+    let mut browser = ServerBrowser::new().unwrap();
+
+    browser.query(responder.local_addr().unwrap()).unwrap();
+    let servers = poll_until(&mut browser, &mut [&mut responder], 1);
+
+    assert_eq!(servers.len(), 1);
+    let server = &servers[0];
+    assert_eq!(server.info, ServerInfo { protocol_version: PROTOCOL_VERSION, name: "LAN party".to_string(), map: "test_triangle".to_string(), player_count: 3, max_players: 16, game_port: 27015 });
+    //Join address is where the answer came from with the game port
+    assert_eq!(server.address, SocketAddr::from((Ipv4Addr::LOCALHOST, 27015)));
+    assert!(server.is_compatible());
+    assert!(server.ping < Duration::from_secs(2));
+}
+
+#[test]
+fn answering_the_same_server_twice_lists_it_once() {
+
+    let mut responder = DiscoveryResponder::bind("127.0.0.1:0", "LAN party", "test_triangle").unwrap();
+    let mut browser = ServerBrowser::new().unwrap();
+
+    browser.query(responder.local_addr().unwrap()).unwrap();
+    browser.query(responder.local_addr().unwrap()).unwrap();
+    poll_until(&mut browser, &mut [&mut responder], 1);
+    std::thread::sleep(Duration::from_millis(50));
+    responder.poll(3, 16, 27015);
+    browser.poll();
+
+    assert_eq!(browser.get_servers(ServerSortKey::Ping, false).len(), 1);
+}
+
+#[test]
+fn unsolicited_responses_are_ignored() {
+
+    let mut browser = ServerBrowser::new().unwrap();
+    let spoofer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let info = ServerInfo { protocol_version: PROTOCOL_VERSION, name: "Spoofed".to_string(), map: "test_triangle".to_string(), player_count: 0, max_players: 16, game_port: 27015 };
+
+    spoofer.send_to(&encode(&DiscoveryPacket::Response { nonce: 1234, info }).unwrap(), (Ipv4Addr::LOCALHOST, browser.local_addr().unwrap().port())).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    browser.poll();
+
+    assert!(browser.get_servers(ServerSortKey::Name, false).is_empty());
+}
+
+#[test]
+fn queries_cant_be_used_to_amplify_traffic() {
+
+    let mut responder = DiscoveryResponder::bind("127.0.0.1:0", "LAN party", "test_triangle").unwrap();
+    let responder_address = responder.local_addr().unwrap();
+    let asker = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    //A query without its padding would get back more than it sent
+    let query = encode(&DiscoveryPacket::Query { nonce: 1 }).unwrap();
+    asker.send_to(&query[..16], responder_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(responder.poll(3, 16, 27015), 0);
+
+    //Asking over and over only gets one answer until the interval is up
+    for _ in 0..5 {
+        asker.send_to(&query, responder_address).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(responder.poll(3, 16, 27015), 1);
+
+    std::thread::sleep(DISCOVERY_REPLY_INTERVAL);
+    asker.send_to(&query, responder_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(responder.poll(3, 16, 27015), 1);
+}
@@ -0,0 +1,360 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::net::*;
+use mp_first_person_shooter::resource_manager;
+use mp_first_person_shooter::user_cmd::*;
+use mp_first_person_shooter::weapon::WeaponState;
+
+//Loopback is fast but still not instant, poll until something shows up or give up
+fn poll<T>(mut f: impl FnMut() -> Vec<T>) -> Vec<T> {
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        let received = f();
+        if !received.is_empty() {
+            return received;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    Vec::new()
+}
+
+//Pumps both ends until the handshake is done, returns the player id the server handed out
+fn handshake(server: &mut NetServer, client: &mut NetClient) -> u32 {
+
+    client.connect().unwrap();
+
+    let mut accepted = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) && (accepted.is_empty() || !client.is_connected()) {
+        for (player_id, packet) in server.receive() {
+            assert_eq!(packet, Packet::Accepted { player_id });
+            accepted.push(player_id);
+        }
+        client.receive();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(client.get_player_id(), Some(accepted[0]));
+
+    accepted[0]
+}
+
+fn receive_packets(socket: &mut NetSocket) -> Vec<Packet> {
+
+    let mut packets = Vec::new();
+    while let Some((_, datagram)) = socket.receive() {
+        packets.push(datagram.packet);
+    }
+
+    packets
+}
+
+fn loopback_pair() -> (NetServer, NetClient) {
+
+    let server = NetServer::bind("127.0.0.1:0").unwrap();
+    let client = NetClient::new(server.local_addr().unwrap()).unwrap();
+
+    (server, client)
+}
+
+#[test]
+fn sockets_exchange_datagrams_with_sequence_numbers() {
+
+    let mut a = NetSocket::bind("127.0.0.1:0").unwrap();
+    let mut b = NetSocket::bind("127.0.0.1:0").unwrap();
+    let b_address = b.local_addr().unwrap();
+
+    assert_eq!(a.send(&Packet::Keepalive, b_address).unwrap(), 1);
+    assert_eq!(a.send(&Packet::Disconnect(DisconnectReason::Quit), b_address).unwrap(), 2);
+
+    let received = poll(|| {
+        let mut datagrams = Vec::new();
+        while let Some(datagram) = b.receive() {
+            datagrams.push(datagram);
+        }
+        datagrams
+    });
+
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].0, a.local_addr().unwrap());
+    assert_eq!(received[0].1, Datagram { sequence: 1, reliable_ack: 0, reliable: Vec::new(), packet: Packet::Keepalive });
+    assert_eq!(received[1].1, Datagram { sequence: 2, reliable_ack: 0, reliable: Vec::new(), packet: Packet::Disconnect(DisconnectReason::Quit) });
+    assert_eq!(b.get_remote_sequence(&a.local_addr().unwrap()), 2);
+}
+
+#[test]
+fn client_and_server_exchange_input_and_snapshot() {
+
+    let (mut server, mut client) = loopback_pair();
+
+    assert_eq!(client.get_state(), ConnectionState::Idle);
+    let player_id = handshake(&mut server, &mut client);
+    assert_ne!(player_id, HOST_PLAYER_ID);
+    assert_eq!(server.get_client_count(), 1);
+    assert_eq!(client.get_state(), ConnectionState::Connected { player_id });
+
+    let input = Packet::Input { snapshot_ack: None, commands: (5..=7).map(|tick| UserCmd::new(tick, glam::f32::Vec3::Z, 1.0, -0.5, BUTTON_FIRE, 3.5)).collect() };
+    client.send(&input).unwrap();
+    assert_eq!(poll(|| server.receive()), vec![(player_id, input)]);
+
+    let players = vec![PlayerSnapshot { id: player_id, position: glam::f32::Vec3::new(1.0, 2.0, 3.0), yaw: 1.0, pitch: -0.5 }];
+    let mut weapons = WeaponState::new();
+    weapons.update(&UserCmd::new(8, glam::f32::Vec3::ZERO, 0.0, 0.0, BUTTON_FIRE, 0.0), glam::f32::Vec3::Z);
+    server.send_snapshot(player_id, 8, Some(7), Some(weapons), &players).unwrap();
+    let received = poll(|| client.receive());
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].tick, 8);
+    assert_eq!(received[0].player_id, player_id);
+    assert_eq!(received[0].input_tick, Some(7));
+    assert_eq!(received[0].weapons, Some(weapons));
+    assert!(received[0].players[0].position.distance(players[0].position) < 0.01);
+    //Nothing is kept for the challenge so counting starts again at Accepted, then the snapshot
+    assert_eq!(client.get_remote_sequence(), 2);
+}
+
+#[test]
+fn clock_responses_come_back_with_their_arrival_time() {
+
+    let (mut server, mut client) = loopback_pair();
+    let player_id = handshake(&mut server, &mut client);
+
+    client.send(&Packet::ClockRequest { client_time: 1.5 }).unwrap();
+    assert_eq!(poll(|| server.receive()), vec![(player_id, Packet::ClockRequest { client_time: 1.5 })]);
+
+    let sent = Instant::now();
+    server.send(player_id, &Packet::ClockResponse { client_time: 1.5, server_tick: 120.25 }).unwrap();
+    let responses = poll(|| {
+        client.receive();
+        client.take_clock_responses()
+    });
+    assert_eq!(responses.len(), 1);
+    assert_eq!((responses[0].client_time, responses[0].server_tick), (1.5, 120.25));
+    assert!(responses[0].received >= sent);
+    assert!(client.take_clock_responses().is_empty());
+}
+
+#[test]
+fn repeated_connects_only_register_once() {
+
+    let (mut server, mut client) = loopback_pair();
+
+    client.connect().unwrap();
+    client.connect().unwrap();
+
+    handshake(&mut server, &mut client);
+    std::thread::sleep(Duration::from_millis(20));
+    client.receive();
+    assert!(server.receive().is_empty());
+    assert_eq!(server.get_client_count(), 1);
+}
+
+#[test]
+fn disconnect_removes_client() {
+
+    let (mut server, mut client) = loopback_pair();
+
+    let player_id = handshake(&mut server, &mut client);
+
+    client.disconnect().unwrap();
+    assert_eq!(client.get_state(), ConnectionState::Disconnected(DisconnectReason::Quit));
+    assert_eq!(poll(|| server.receive()), vec![(player_id, Packet::Disconnect(DisconnectReason::Quit))]);
+    assert_eq!(server.get_client_count(), 0);
+    assert!(server.send(player_id, &Packet::Keepalive).is_err());
+}
+
+#[test]
+fn packets_from_unknown_addresses_are_ignored() {
+
+    let (mut server, mut client) = loopback_pair();
+    let server_address = server.local_addr().unwrap();
+
+    //Input from someone who never connected
+    let mut stranger = NetSocket::bind("127.0.0.1:0").unwrap();
+    stranger.send(&Packet::Input { snapshot_ack: None, commands: vec![UserCmd::new(0, glam::f32::Vec3::X, 0.0, 0.0, 0, 0.0)] }, server_address).unwrap();
+
+    //Garbage that isn't a datagram at all
+    let raw = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    raw.send_to(&[0xff; 16], server_address).unwrap();
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(server.receive().is_empty());
+    //Nothing is kept for them either
+    assert_eq!(server.get_socket().get_peer_count(), 0);
+
+    handshake(&mut server, &mut client);
+    assert_eq!(server.get_socket().get_peer_count(), 1);
+
+    //Client only listens to its server
+    let client_address: SocketAddr = ([127, 0, 0, 1], client.local_addr().unwrap().port()).into();
+    stranger.send(&Packet::Snapshot { tick: 0, player_id: 1, input_tick: None, weapons: None, delta: delta::WorldDelta { baseline_tick: None, changed: Vec::new(), removed: Vec::new() } }, client_address).unwrap();
+    stranger.send(&Packet::Disconnect(DisconnectReason::Kicked), client_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(client.receive().is_empty());
+    assert!(client.is_connected());
+}
+
+#[test]
+fn challenge_response_needs_the_right_token() {
+
+    let mut server = NetServer::bind("127.0.0.1:0").unwrap();
+    let server_address = server.local_addr().unwrap();
+    let mut spoofer = NetSocket::bind("127.0.0.1:0").unwrap();
+
+    let connect = Packet::Connect { protocol_version: PROTOCOL_VERSION, asset_manifest_hash: resource_manager::get_asset_manifest_hash() };
+    spoofer.send(&connect, server_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    server.receive();
+    let challenge = poll(|| receive_packets(&mut spoofer));
+    let [Packet::Challenge { token }] = challenge[..] else {
+        panic!("expected a challenge, got {:?}", challenge);
+    };
+    //Nothing is kept until it's answered
+    assert_eq!(server.get_socket().get_peer_count(), 0);
+
+    //Guessing doesn't get you in
+    for guess in (0..100).filter(|guess| *guess != token) {
+        spoofer.send(&Packet::ChallengeResponse { token: guess }, server_address).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(server.receive().is_empty());
+    assert_eq!(server.get_client_count(), 0);
+
+    //Tokens run out
+    server.set_timeout(Duration::from_millis(10));
+    std::thread::sleep(Duration::from_millis(30));
+    spoofer.send(&Packet::ChallengeResponse { token }, server_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(server.receive().is_empty());
+    assert_eq!(server.get_client_count(), 0);
+    assert_eq!(server.get_socket().get_peer_count(), 0);
+}
+
+#[test]
+fn connect_floods_dont_lock_out_real_clients() {
+
+    let (mut server, mut client) = loopback_pair();
+    let server_address = server.local_addr().unwrap();
+
+    //More connects than any table of pending challenges would hold, none of them answered
+    let connect = Packet::Connect { protocol_version: PROTOCOL_VERSION, asset_manifest_hash: resource_manager::get_asset_manifest_hash() };
+    let mut spoofers: Vec<NetSocket> = (0..300).map(|_| NetSocket::bind("127.0.0.1:0").unwrap()).collect();
+    for spoofer in &mut spoofers {
+        spoofer.send(&connect, server_address).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(server.receive().is_empty());
+    assert_eq!(server.get_socket().get_peer_count(), 0);
+
+    handshake(&mut server, &mut client);
+    assert_eq!(server.get_client_count(), 1);
+}
+
+#[test]
+fn mismatched_versions_are_refused() {
+
+    let (mut server, mut client) = loopback_pair();
+    let server_address = server.local_addr().unwrap();
+
+    let mut old_client = NetSocket::bind("127.0.0.1:0").unwrap();
+    old_client.send(&Packet::Connect { protocol_version: PROTOCOL_VERSION + 1, asset_manifest_hash: resource_manager::get_asset_manifest_hash() }, server_address).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    server.receive();
+    let refusal = poll(|| receive_packets(&mut old_client));
+    assert_eq!(refusal, vec![Packet::Disconnect(DisconnectReason::VersionMismatch)]);
+
+    //Different assets is just as bad
+    client.set_asset_manifest_hash(1234);
+    client.connect().unwrap();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) && client.get_state() == ConnectionState::Connecting {
+        server.receive();
+        client.receive();
+    }
+    assert_eq!(client.get_state(), ConnectionState::Disconnected(DisconnectReason::VersionMismatch));
+    assert_eq!(server.get_client_count(), 0);
+}
+
+#[test]
+fn full_server_refuses_new_clients() {
+
+    let (mut server, mut client) = loopback_pair();
+    server.set_max_clients(1);
+    handshake(&mut server, &mut client);
+
+    let mut late_client = NetClient::new(server.local_addr().unwrap()).unwrap();
+    late_client.connect().unwrap();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) && late_client.get_state() == ConnectionState::Connecting {
+        server.receive();
+        late_client.receive();
+    }
+    assert_eq!(late_client.get_state(), ConnectionState::Disconnected(DisconnectReason::ServerFull));
+    assert_eq!(server.get_client_count(), 1);
+}
+
+#[test]
+fn kicked_clients_are_told_why() {
+
+    let (mut server, mut client) = loopback_pair();
+    let player_id = handshake(&mut server, &mut client);
+
+    server.kick(player_id).unwrap();
+    assert_eq!(server.get_client_count(), 0);
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) && client.is_connected() {
+        client.receive();
+    }
+    assert_eq!(client.get_state(), ConnectionState::Disconnected(DisconnectReason::Kicked));
+}
+
+#[test]
+fn silence_times_out_both_ends() {
+
+    let (mut server, mut client) = loopback_pair();
+    let player_id = handshake(&mut server, &mut client);
+    server.set_timeout(Duration::from_millis(50));
+    client.set_timeout(Duration::from_millis(50));
+
+    std::thread::sleep(Duration::from_millis(60));
+    client.update();
+    assert_eq!(client.get_state(), ConnectionState::Disconnected(DisconnectReason::Timeout));
+    assert_eq!(server.receive(), vec![(player_id, Packet::Disconnect(DisconnectReason::Timeout))]);
+    assert_eq!(server.get_client_count(), 0);
+}
+
+#[test]
+fn keepalives_hold_an_idle_connection_open() {
+
+    let (mut server, mut client) = loopback_pair();
+    handshake(&mut server, &mut client);
+    server.set_timeout(Duration::from_millis(2500));
+    client.set_timeout(Duration::from_millis(2500));
+
+    //Nobody sends any game packets, only keepalives go back and forth
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        assert!(server.receive().is_empty());
+        client.receive();
+        server.update();
+        client.update();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(client.is_connected());
+    assert_eq!(server.get_client_count(), 1);
+}
+
+#[test]
+fn sequence_comparison_wraps() {
+
+    assert!(is_sequence_newer(2, 1));
+    assert!(!is_sequence_newer(1, 2));
+    assert!(!is_sequence_newer(5, 5));
+    assert!(is_sequence_newer(0, u32::MAX));
+    assert!(is_sequence_newer(3, u32::MAX - 3));
+}
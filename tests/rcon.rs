@@ -0,0 +1,238 @@
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::console::Console;
+use mp_first_person_shooter::game_state::GameState;
+use mp_first_person_shooter::input::Inputs;
+use mp_first_person_shooter::rcon::*;
+use mp_first_person_shooter::resource_manager::ResourceManager;
+
+//Stands in for an admin's RCON tool, the server side only moves when pump is called so the client never blocks
+struct StandInClient {
+    stream: TcpStream,
+    received: Vec<u8>,
+}
+
+impl StandInClient {
+
+    fn connect(server: &RconServer) -> Self {
+
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        Self { stream, received: Vec::new() }
+    }
+
+    fn send_line(&mut self, line: &str) {
+
+        self.stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+    }
+
+    //Pumps the server until a whole reply is back, the reply ends at an empty line
+    fn read_reply(&mut self, mut pump: impl FnMut()) -> Option<Vec<String>> {
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            pump();
+
+            let mut buffer = [0; 512];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return None,
+                Ok(size) => self.received.extend_from_slice(&buffer[..size]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(_) => return None,
+            }
+
+            let text = String::from_utf8_lossy(&self.received).to_string();
+            if let Some(end) = text.find("\n\n") {
+                self.received.drain(..end + 2);
+                return Some(text[..end].lines().map(|line| line.to_string()).collect());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        None
+    }
+
+    //True once the server has hung up
+    fn is_closed(&mut self, mut pump: impl FnMut()) -> bool {
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            pump();
+
+            let mut buffer = [0; 512];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return true,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(_) => return true,
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        false
+    }
+}
+
+fn console() -> Rc<RefCell<Console>> {
+
+    Rc::new(RefCell::new(Console::new()))
+}
+
+//Runs every command as "echo", so the reply is the command itself
+fn echo_server(server: &mut RconServer) -> impl FnMut() + '_ {
+
+    move || {
+        for command in server.poll() {
+            server.send_output(command.connection_id, &format!("echo {}\n\nsecond line", command.command));
+        }
+    }
+}
+
+#[test]
+fn wrong_password_is_refused_and_right_one_accepted() {
+
+    let mut server = RconServer::bind("127.0.0.1:0", "hunter2", console()).unwrap();
+    let mut client = StandInClient::connect(&server);
+
+    client.send_line("letmein");
+    assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec![RCON_BAD_PASSWORD.to_string()]));
+
+    //Commands go nowhere until the password is right, this one gets taken as another guess
+    client.send_line("status");
+    assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec![RCON_BAD_PASSWORD.to_string()]));
+
+    client.send_line("hunter2");
+    assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec![RCON_AUTHENTICATED.to_string()]));
+
+    client.send_line("status");
+    assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec!["echo status".to_string(), "second line".to_string()]));
+}
+
+#[test]
+fn too_many_bad_passwords_locks_the_address_out() {
+
+    let mut server = RconServer::bind("127.0.0.1:0", "hunter2", console()).unwrap();
+    let mut client = StandInClient::connect(&server);
+
+    for _ in 0..MAX_RCON_AUTH_FAILURES {
+        client.send_line("guess");
+        assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec![RCON_BAD_PASSWORD.to_string()]));
+    }
+    assert!(client.is_closed(echo_server(&mut server)));
+
+    //Even the right password is no good for a while
+    let mut client = StandInClient::connect(&server);
+    client.send_line("hunter2");
+    assert_eq!(client.read_reply(echo_server(&mut server)), Some(vec![RCON_LOCKED_OUT.to_string()]));
+    assert!(client.is_closed(echo_server(&mut server)));
+    assert_eq!(server.get_connection_count(), 0);
+}
+
+#[test]
+fn one_address_cant_hold_every_slot() {
+
+    let mut server = RconServer::bind("127.0.0.1:0", "hunter2", console()).unwrap();
+    let mut first = StandInClient::connect(&server);
+
+    //Still waiting on a password from here, so a second one is turned away
+    let mut second = StandInClient::connect(&server);
+    assert!(second.is_closed(echo_server(&mut server)));
+    assert_eq!(server.get_connection_count(), MAX_RCON_PENDING_PER_ADDRESS);
+
+    //Once it's in, the slot it was waiting in is free again
+    first.send_line("hunter2");
+    first.read_reply(echo_server(&mut server)).unwrap();
+    let mut third = StandInClient::connect(&server);
+    third.send_line("hunter2");
+    assert_eq!(third.read_reply(echo_server(&mut server)), Some(vec![RCON_AUTHENTICATED.to_string()]));
+}
+
+#[test]
+fn command_floods_are_rate_limited() {
+
+    let mut server = RconServer::bind("127.0.0.1:0", "hunter2", console()).unwrap();
+    let mut client = StandInClient::connect(&server);
+
+    client.send_line("hunter2");
+    client.read_reply(echo_server(&mut server)).unwrap();
+
+    //All at once so the bucket has no time to refill
+    let count = RCON_COMMAND_BURST as usize + 4;
+    for i in 0..count {
+        client.send_line(&format!("command {}", i));
+    }
+
+    let mut replies = Vec::new();
+    while replies.len() < count {
+        replies.push(client.read_reply(echo_server(&mut server)).unwrap());
+    }
+
+    let limited = replies.iter().filter(|reply| reply[0] == RCON_RATE_LIMITED).count();
+    assert!(limited >= 3, "{} of {} limited", limited, count);
+    assert_eq!(replies[0][0], "echo command 0");
+}
+
+#[test]
+fn admin_actions_are_logged() {
+
+    let console = console();
+    let mut server = RconServer::bind("127.0.0.1:0", "hunter2", console.clone()).unwrap();
+    let mut client = StandInClient::connect(&server);
+
+    client.send_line("wrong");
+    client.read_reply(echo_server(&mut server)).unwrap();
+    client.send_line("hunter2");
+    client.read_reply(echo_server(&mut server)).unwrap();
+    client.send_line("kick everyone");
+    client.read_reply(echo_server(&mut server)).unwrap();
+
+    let actions: Vec<&str> = server.get_admin_log().iter().map(|action| action.action.as_str()).collect();
+    assert_eq!(actions, vec!["Bad password, attempt 1", "Authenticated", "Command: kick everyone"]);
+    assert!(server.get_admin_log().iter().all(|action| action.address.ip().is_loopback()));
+    //The password never ends up in the log
+    assert!(!console.borrow().get_log().contains("hunter2"));
+    assert!(console.borrow().get_log().contains("Command: kick everyone"));
+
+    //Only the newest are kept in memory, rate limited or not each one is logged
+    for i in 0..MAX_RCON_ADMIN_LOG {
+        client.send_line(&format!("command {}", i));
+        client.read_reply(echo_server(&mut server)).unwrap();
+    }
+    assert_eq!(server.get_admin_log().len(), MAX_RCON_ADMIN_LOG);
+    assert!(server.get_admin_log().front().unwrap().action.ends_with("command 0"));
+    assert!(server.get_admin_log().back().unwrap().action.ends_with(&format!("command {}", MAX_RCON_ADMIN_LOG - 1)));
+}
+
+#[test]
+fn commands_run_through_the_game_console() {
+
+    let console = console();
+    let mut game_state = GameState::new_dedicated(console.clone());
+    let mut resource_manager = ResourceManager::new(console.clone());
+    let mut inputs = Inputs::new();
+
+    let rcon = RconServer::bind("127.0.0.1:0", "hunter2", console.clone()).unwrap();
+    let mut client = StandInClient::connect(&rcon);
+    game_state.set_rcon(rcon);
+
+    let mut pump = || {
+        game_state.update(&mut inputs, &mut resource_manager);
+    };
+
+    client.send_line("hunter2");
+    assert_eq!(client.read_reply(&mut pump), Some(vec![RCON_AUTHENTICATED.to_string()]));
+
+    client.send_line("not_a_command");
+    assert_eq!(client.read_reply(&mut pump), Some(vec!["Unknown command: not_a_command".to_string()]));
+
+    //Output only goes back to the admin that ran the command, but still ends up in the server's log
+    client.send_line("net_sim latency 50");
+    let reply = client.read_reply(&mut pump).unwrap();
+    assert_eq!(reply, vec!["net_sim needs a network, use --host or --connect".to_string()]);
+    assert!(console.borrow().get_log().contains("net_sim needs a network"));
+}
@@ -0,0 +1,156 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::chat::ChatChannel;
+use mp_first_person_shooter::net::*;
+use mp_first_person_shooter::user_cmd::UserCmd;
+
+//Sits between a client and a server on loopback and loses, duplicates and reorders what passes through
+struct LossyLoopback {
+    socket: UdpSocket,
+    server_address: SocketAddr,
+    client_address: Option<SocketAddr>,
+    held: Option<(Vec<u8>, SocketAddr)>,
+    random: u32,
+}
+
+impl LossyLoopback {
+
+    fn new(server_address: SocketAddr) -> Self {
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+
+        Self { socket, server_address, client_address: None, held: None, random: 0x1234_5678 }
+    }
+
+    fn local_addr(&self) -> SocketAddr {
+
+        self.socket.local_addr().unwrap()
+    }
+
+    //Fixed seed xorshift so a failure can be reproduced
+    fn roll(&mut self) -> u32 {
+
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+
+        self.random % 100
+    }
+
+    fn pump(&mut self) {
+
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        while let Ok((size, from)) = self.socket.recv_from(&mut buffer) {
+            let to = if from == self.server_address {
+                match self.client_address {
+                    Some(client_address) => client_address,
+                    None => continue,
+                }
+            }
+            else {
+                self.client_address = Some(from);
+                self.server_address
+            };
+
+            let roll = self.roll();
+            //30% lost
+            if roll < 30 {
+                continue;
+            }
+            //10% held back until after the next one
+            if roll < 40 && self.held.is_none() {
+                self.held = Some((buffer[..size].to_vec(), to));
+                continue;
+            }
+
+            self.socket.send_to(&buffer[..size], to).unwrap();
+            //10% arrive twice
+            if roll >= 90 {
+                self.socket.send_to(&buffer[..size], to).unwrap();
+            }
+            if let Some((held, held_to)) = self.held.take() {
+                self.socket.send_to(&held, held_to).unwrap();
+            }
+        }
+    }
+}
+
+fn chat(player_id: u32, number: u32) -> ReliableMessage {
+
+    ReliableMessage::Chat { player_id, channel: ChatChannel::All, text: format!("Message {}", number) }
+}
+
+#[test]
+fn reliable_messages_survive_a_lossy_loopback_in_order() {
+
+    const MESSAGES: u32 = 100;
+
+    let mut server = NetServer::bind("127.0.0.1:0").unwrap();
+    let mut loopback = LossyLoopback::new(server.local_addr().unwrap());
+    let mut client = NetClient::new(loopback.local_addr()).unwrap();
+
+    for number in 0..MESSAGES {
+        client.send_reliable(chat(0, number)).unwrap();
+    }
+    client.connect().unwrap();
+
+    let mut server_received = Vec::new();
+    let mut client_received = Vec::new();
+    let mut server_sent = false;
+
+    let start = Instant::now();
+    let mut tick = 0;
+    while (server_received.len() < MESSAGES as usize || client_received.len() < MESSAGES as usize) && start.elapsed() < Duration::from_secs(20) {
+        //Every tick both ends send something unreliable for the reliable messages to ride on
+        client.update();
+        if client.is_connected() {
+            client.send(&Packet::Input { snapshot_ack: client.get_snapshot_ack(), commands: vec![UserCmd::new(tick, glam::f32::Vec3::ZERO, 0.0, 0.0, 0, 0.0)] }).unwrap();
+        }
+        loopback.pump();
+
+        server.receive();
+        server_received.extend(server.receive_reliable().into_iter().map(|(_, message)| message));
+        for player_id in server.get_player_ids() {
+            if !server_sent {
+                for number in 0..MESSAGES {
+                    server.send_reliable(player_id, chat(player_id, number)).unwrap();
+                }
+                server_sent = true;
+            }
+            server.send_snapshot(player_id, tick, None, None, &[]).unwrap();
+        }
+        loopback.pump();
+
+        client.receive();
+        client_received.extend(client.receive_reliable());
+
+        tick += 1;
+        std::thread::sleep(Duration::from_millis(2));
+    }
+
+    let player_id = client.get_player_id().unwrap();
+    assert_eq!(server_received, (0..MESSAGES).map(|number| chat(0, number)).collect::<Vec<_>>());
+    assert_eq!(client_received, (0..MESSAGES).map(|number| chat(player_id, number)).collect::<Vec<_>>());
+}
+
+#[test]
+fn reliable_messages_are_not_delivered_from_other_addresses() {
+
+    let mut server = NetServer::bind("127.0.0.1:0").unwrap();
+    let mut stranger = NetSocket::bind("127.0.0.1:0").unwrap();
+    let server_address = server.local_addr().unwrap();
+
+    //Never did the handshake so the server has no player for it
+    stranger.send_reliable(chat(7, 0), server_address).unwrap();
+    stranger.send(&Packet::Keepalive, server_address).unwrap();
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(100) {
+        server.receive();
+        assert!(server.receive_reliable().is_empty());
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
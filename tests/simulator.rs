@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::net::simulator::*;
+use mp_first_person_shooter::net::*;
+
+const SEED: u64 = 0x5eed;
+
+//Keeps both sockets pumping for a while and returns the sequence numbers b got, in arrival order
+fn exchange(a: &mut NetSocket, b: &mut NetSocket, count: u32, wait: Duration) -> Vec<u32> {
+
+    let b_address = b.local_addr().unwrap();
+    let mut received = Vec::new();
+
+    //Drain as we go or the real socket buffer starts losing packets too
+    for _ in 0..count {
+        a.send(&Packet::Keepalive, b_address).unwrap();
+        while let Some((_, datagram)) = b.receive() {
+            received.push(datagram.sequence);
+        }
+    }
+
+    let start = Instant::now();
+    while start.elapsed() < wait {
+        a.flush();
+        while let Some((_, datagram)) = b.receive() {
+            received.push(datagram.sequence);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    received
+}
+
+fn pair(conditions: NetworkConditions) -> (NetSocket, NetSocket) {
+
+    let mut a = NetSocket::bind("127.0.0.1:0").unwrap();
+    let b = NetSocket::bind("127.0.0.1:0").unwrap();
+    a.set_simulator(Some(NetworkSimulator::new(conditions, SEED)));
+
+    (a, b)
+}
+
+#[test]
+fn latency_delays_delivery() {
+
+    let (mut a, mut b) = pair(NetworkConditions { latency: Duration::from_millis(100), ..Default::default() });
+
+    a.send(&Packet::Keepalive, b.local_addr().unwrap()).unwrap();
+    let sent = Instant::now();
+
+    let mut arrived = None;
+    while sent.elapsed() < Duration::from_secs(2) && arrived.is_none() {
+        a.flush();
+        if b.receive().is_some() {
+            arrived = Some(sent.elapsed());
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert!(arrived.unwrap() >= Duration::from_millis(100));
+}
+
+#[test]
+fn seeded_loss_and_duplication_are_reproducible() {
+
+    let conditions = NetworkConditions { loss: 0.2, duplication: 0.1, ..Default::default() };
+
+    let (mut a, mut b) = pair(conditions);
+    let first = exchange(&mut a, &mut b, 500, Duration::from_millis(200));
+    let (mut a, mut b) = pair(conditions);
+    let second = exchange(&mut a, &mut b, 500, Duration::from_millis(200));
+
+    assert_eq!(first, second);
+
+    let simulator = a.get_simulator().unwrap();
+    assert_eq!(first.len() as u64, 500 - simulator.get_dropped_count() + simulator.get_duplicated_count());
+    assert!((60..140).contains(&simulator.get_dropped_count()));
+    assert!(simulator.get_duplicated_count() > 0);
+}
+
+#[test]
+fn jitter_and_reordering_shuffle_arrival_order() {
+
+    let (mut a, mut b) = pair(NetworkConditions { latency: Duration::from_millis(5), jitter: Duration::from_millis(20), reordering: 0.1, ..Default::default() });
+
+    let received = exchange(&mut a, &mut b, 200, Duration::from_millis(300));
+
+    assert_eq!(received.len(), 200);
+    assert!(received.windows(2).any(|pair| pair[1] < pair[0]));
+
+    let mut sorted = received.clone();
+    sorted.sort();
+    assert_eq!(sorted, (1..=200).collect::<Vec<u32>>());
+}
+
+#[test]
+fn incoming_packets_go_through_the_simulator_too() {
+
+    let mut a = NetSocket::bind("127.0.0.1:0").unwrap();
+    let mut b = NetSocket::bind("127.0.0.1:0").unwrap();
+    b.set_simulator(Some(NetworkSimulator::new(NetworkConditions { loss: 1.0, ..Default::default() }, SEED)));
+
+    let received = exchange(&mut a, &mut b, 20, Duration::from_millis(50));
+
+    assert!(received.is_empty());
+    assert_eq!(b.get_simulator().unwrap().get_dropped_count(), 20);
+}
+
+#[test]
+fn handshake_survives_a_bad_connection() {
+
+    let mut server = NetServer::bind("127.0.0.1:0").unwrap();
+    let mut client = NetClient::new(server.local_addr().unwrap()).unwrap();
+    let conditions = NetworkConditions { latency: Duration::from_millis(20), jitter: Duration::from_millis(10), loss: 0.3, duplication: 0.1, reordering: 0.1 };
+    server.get_mut_socket().set_simulator(Some(NetworkSimulator::new(conditions, SEED)));
+    client.get_mut_socket().set_simulator(Some(NetworkSimulator::new(conditions, SEED + 1)));
+
+    client.connect().unwrap();
+    let mut accepted = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) && (accepted.is_empty() || !client.is_connected()) {
+        accepted.extend(server.receive());
+        client.receive();
+        client.update();
+        server.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(accepted.len(), 1);
+    assert!(client.is_connected());
+}
@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use mp_first_person_shooter::console::Console;
+use mp_first_person_shooter::entity::Projectile;
+use mp_first_person_shooter::game_state::GameState;
+use mp_first_person_shooter::input::{self, Inputs};
+use mp_first_person_shooter::net::*;
+use mp_first_person_shooter::resource_manager::ResourceManager;
+use mp_first_person_shooter::states::*;
+use mp_first_person_shooter::weapon;
+use winit::event::ElementState;
+
+struct Game {
+    game_state: GameState,
+    resource_manager: ResourceManager,
+    inputs: Inputs,
+    console: Rc<RefCell<Console>>,
+}
+
+impl Game {
+
+    fn new() -> Self {
+
+        let console = Rc::new(RefCell::new(Console::new()));
+
+        Self { game_state: GameState::new(console.clone()), resource_manager: ResourceManager::new(console.clone()), inputs: Inputs::new(), console }
+    }
+
+    fn update(&mut self) {
+
+        self.game_state.update(&mut self.inputs, &mut self.resource_manager);
+    }
+
+    //Console commands run at the start of the next tick
+    fn command(&mut self, command: &str) {
+
+        self.console.borrow_mut().submit_command(command);
+        self.update_until(|game| game.console.borrow().get_commands_pending() == 0);
+    }
+
+    fn update_until(&mut self, mut done: impl FnMut(&Game) -> bool) -> bool {
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(3) {
+            self.update();
+            if done(self) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        false
+    }
+
+    //Switching takes a moment, the trigger has to be pulled after that
+    fn select_rocket_launcher(&mut self) {
+
+        self.inputs.mouse_wheel_input(-3.0);
+    }
+
+    fn pull_trigger(&mut self) {
+
+        self.inputs.mouse_input(input::FIRE, ElementState::Pressed);
+    }
+
+    fn is_rocket_launcher_ready(&self, switched: Instant) -> bool {
+
+        self.game_state.get_player().get_weapons().get_current() == 3 && switched.elapsed().as_secs_f32() > weapon::SWITCH_TIME * 2.0
+    }
+
+    fn count_projectiles(&self) -> usize {
+
+        self.game_state.get_entities().iter::<Projectile>().count()
+    }
+}
+
+fn pump_until(host: &mut Game, client: &mut Game, mut done: impl FnMut(&Game, &Game) -> bool) -> bool {
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        host.update();
+        client.update();
+        if done(host, client) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    false
+}
+
+#[test]
+fn offline_games_start_in_the_menu_and_follow_the_console() {
+
+    let mut game = Game::new();
+    assert_eq!(game.game_state.get_state(), States::MainMenu);
+
+    game.game_state.set_state(States::Warmup, "menu");
+    game.command("state in_round");
+    assert_eq!(game.game_state.get_state(), States::InRound);
+    assert_eq!(game.game_state.get_round(), 1);
+
+    game.command("state loading");
+    assert_eq!(game.game_state.get_state(), States::InRound);
+    game.command("state lobby");
+    assert!(game.console.borrow().get_log().contains("state: unknown state lobby"));
+
+    let log = game.console.borrow().get_log().clone();
+    assert!(log.contains("State main_menu -> warmup (menu)"));
+    assert!(log.contains("State warmup -> in_round (console)"));
+}
+
+#[test]
+fn interpolation_can_be_tuned_from_the_console() {
+
+    let mut game = Game::new();
+    game.command("interpolation delay 10");
+    game.command("interpolation max_extrapolation 3");
+    game.command("interpolation lag 2");
+
+    let log = game.console.borrow().get_log().clone();
+    assert!(log.contains("Interpolation: delay 10 ticks, max extrapolation 15 ticks"));
+    assert!(log.contains("Interpolation: delay 10 ticks, max extrapolation 3 ticks"));
+    assert!(log.contains("interpolation: unknown setting lag"));
+}
+
+#[test]
+fn pausing_picks_up_where_it_left_off() {
+
+    let mut game = Game::new();
+    game.game_state.set_state(States::InRound, "test");
+    game.update_until(|game| game.game_state.get_state_time_left().unwrap() < 179.0);
+    let time_left = game.game_state.get_state_time_left().unwrap();
+
+    game.command("state pause");
+    assert_eq!(game.game_state.get_state(), States::Paused);
+    let paused = Instant::now();
+    game.update_until(|_| paused.elapsed() > Duration::from_millis(200));
+    game.command("state resume");
+
+    assert_eq!(game.game_state.get_state(), States::InRound);
+    //Resuming isn't a new round and the clock didn't run while paused
+    assert_eq!(game.game_state.get_round(), 1);
+    assert!((game.game_state.get_state_time_left().unwrap() - time_left).abs() < 0.5);
+}
+
+#[test]
+fn timed_states_move_on_by_themselves() {
+
+    let mut game = Game::new();
+    game.game_state.get_mut_state_settings().round_end_time = 0.1;
+    game.game_state.get_mut_state_settings().round_time = 0.1;
+    game.game_state.get_mut_state_settings().rounds_per_match = 2;
+    game.game_state.set_state(States::InRound, "test");
+
+    assert!(game.update_until(|game| game.game_state.get_state() == States::MatchEnd));
+    assert_eq!(game.game_state.get_round(), 2);
+    assert!(game.console.borrow().get_log().contains("State round_end -> in_round (timer)"));
+}
+
+#[test]
+fn clients_follow_the_server() {
+
+    let mut host = Game::new();
+    let server = NetServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    host.game_state.set_network(Network::Server(server));
+    assert_eq!(host.game_state.get_state(), States::Warmup);
+
+    let mut client = Game::new();
+    client.game_state.set_network(Network::Client(NetClient::new(address).unwrap()));
+    assert_eq!(client.game_state.get_state(), States::Connecting);
+
+    //Joining mid warmup
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::Warmup));
+
+    host.console.borrow_mut().submit_command("state in_round");
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::InRound));
+    assert_eq!(client.game_state.get_round(), 1);
+
+    //Clients don't get a say
+    client.command("state match_end");
+    assert!(client.console.borrow().get_log().contains("state: only the server can change state"));
+    assert_eq!(host.game_state.get_state(), States::InRound);
+
+    //Hosts leaving takes everyone back to the menu
+    host.console.borrow_mut().submit_command("state main_menu");
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::MainMenu));
+    assert!(client.game_state.get_network().is_none());
+    assert!(client.console.borrow().get_log().contains("disconnected: Kicked"));
+}
+
+#[test]
+fn rockets_fly_offline() {
+
+    let mut game = Game::new();
+    game.game_state.set_state(States::InRound, "test");
+    game.select_rocket_launcher();
+    let switched = Instant::now();
+    assert!(game.update_until(|game| game.is_rocket_launcher_ready(switched)));
+    game.pull_trigger();
+
+    assert!(game.update_until(|game| game.count_projectiles() > 0));
+}
+
+#[test]
+fn clients_see_rockets_the_server_launches() {
+
+    let mut host = Game::new();
+    let server = NetServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    host.game_state.set_network(Network::Server(server));
+    host.console.borrow_mut().submit_command("state in_round");
+
+    let mut client = Game::new();
+    client.game_state.set_network(Network::Client(NetClient::new(address).unwrap()));
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::InRound));
+    assert_eq!(client.count_projectiles(), 0);
+
+    host.select_rocket_launcher();
+    let switched = Instant::now();
+    assert!(pump_until(&mut host, &mut client, |host, _| host.is_rocket_launcher_ready(switched)));
+    host.pull_trigger();
+    assert!(pump_until(&mut host, &mut client, |host, client| host.count_projectiles() > 0 && client.count_projectiles() > 0));
+}
+
+#[test]
+fn nobody_moves_while_paused() {
+
+    let mut host = Game::new();
+    let server = NetServer::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    host.game_state.set_network(Network::Server(server));
+    host.console.borrow_mut().submit_command("state in_round");
+
+    let mut client = Game::new();
+    client.game_state.set_network(Network::Client(NetClient::new(address).unwrap()));
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::InRound));
+    let Some(Network::Client(net_client)) = client.game_state.get_network() else {
+        panic!("client lost its connection");
+    };
+    let player_id = net_client.get_player_id().unwrap();
+    let get_position = |host: &Game| host.game_state.get_remote_player(player_id).map(|player| *player.get_position());
+
+    //There's no level to stand on so the server has the client falling
+    let start = get_position(&host).unwrap();
+    assert!(pump_until(&mut host, &mut client, |host, _| get_position(host).unwrap().y < start.y - 1.0));
+
+    host.console.borrow_mut().submit_command("state pause");
+    assert!(pump_until(&mut host, &mut client, |_, client| client.game_state.get_state() == States::Paused));
+
+    let position = get_position(&host).unwrap();
+    let paused = Instant::now();
+    pump_until(&mut host, &mut client, |_, _| paused.elapsed() > Duration::from_millis(500));
+    assert!(get_position(&host).unwrap().abs_diff_eq(position, 0.001), "{:?} {:?}", position, get_position(&host));
+}